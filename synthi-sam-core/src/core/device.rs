@@ -69,6 +69,10 @@ pub trait Device {
 
     fn process(&mut self, info: SampleInfo);
 
+    /// Delay in samples the device adds to the signal, hosts can use it to align parallel paths
+    fn latency(&self) -> usize {
+        return 0;
+    }
 
     fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort>;
 
@@ -1,47 +1,47 @@
 use std::collections::VecDeque;
 use crate::util::get_default;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct NoteEvent {
     pub note: u8,
     pub velocity: f64,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct PolyphonicAftertouchEvent {
     pub note: u8,
     pub aftertouch: f64,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct MonophonicAftertouchEvent {
     pub aftertouch: f64,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct ControlChangeEvent {
     pub control: u8,
     pub value: f64,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct ProgramChangeEvent {
     pub program: u8,
 }
 
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct PitchBendEvent {
     pub pitch_bend: f64,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct SysExEvent {
     pub data: Vec<u8>,
 }
 
 /// Represents a type of MIDI message with it's respective properties
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum MidiMessageContent {
     NoteOff(NoteEvent),
     NoteOn(NoteEvent),
@@ -50,11 +50,15 @@ pub enum MidiMessageContent {
     ProgramChange(ProgramChangeEvent),
     MonophonicAftertouch(MonophonicAftertouchEvent),
     PitchBend(PitchBendEvent),
-    SysEx(SysExEvent)
+    SysEx(SysExEvent),
+    Clock,      //24 per quarter note
+    Start,
+    Continue,
+    Stop,
 }
 
 /// Represents a MIDI message with a type and content as well as the channel it is sent in
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MidiMessage {
     pub channel: u8,
     pub message: MidiMessageContent,
@@ -90,7 +94,13 @@ impl MidiMessage {
                     let second = (get_default(&data, 2, 0) & 0b0111_1111) as f64;
                     message_type = MidiMessageContent::PitchBend(PitchBendEvent{ pitch_bend: (first + second * 128.0)/8192.0 - 1.0} );
                 },
-                0xF0 => message_type = MidiMessageContent::SysEx(SysExEvent{data: data.to_vec()}),
+                0xF0 => message_type = match data[0] {
+                    0xF8 => MidiMessageContent::Clock,
+                    0xFA => MidiMessageContent::Start,
+                    0xFB => MidiMessageContent::Continue,
+                    0xFC => MidiMessageContent::Stop,
+                    _ => MidiMessageContent::SysEx(SysExEvent{data: data.to_vec()}),
+                },
                 _ =>  return Err("Invalid message type!"),
             }
             channel = data[0] & 0x0F;
@@ -105,6 +115,26 @@ impl MidiMessage {
         });
     }
 
+    /// Creates a message with the given content
+    pub fn from_content(channel: u8, message: MidiMessageContent) -> MidiMessage {
+        return MidiMessage {
+            channel: channel,
+            message: message,
+        };
+    }
+
+    pub fn note_on(channel: u8, note: u8, velocity: f64) -> MidiMessage {
+        return MidiMessage::from_content(channel, MidiMessageContent::NoteOn(NoteEvent { note: note, velocity: velocity }));
+    }
+
+    pub fn note_off(channel: u8, note: u8, velocity: f64) -> MidiMessage {
+        return MidiMessage::from_content(channel, MidiMessageContent::NoteOff(NoteEvent { note: note, velocity: velocity }));
+    }
+
+    pub fn control_change(channel: u8, control: u8, value: f64) -> MidiMessage {
+        return MidiMessage::from_content(channel, MidiMessageContent::ControlChange(ControlChangeEvent { control: control, value: value }));
+    }
+
 }
 
 
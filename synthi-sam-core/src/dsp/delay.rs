@@ -0,0 +1,102 @@
+use super::interpolation::cubic;
+
+/// Ring buffer that can be read at fractional delays
+///
+/// The delay is given in samples, values between samples are interpolated with a cubic hermite spline.
+pub struct DelayLine {
+    buffer: Vec<f64>,
+    write: usize,
+}
+
+impl Default for DelayLine {
+    fn default() -> Self {
+        return DelayLine::new(0);
+    }
+}
+
+impl DelayLine {
+
+    /// Creates a delay line that can delay up to the given amount of samples
+    pub fn new(max_delay: usize) -> DelayLine {
+        return DelayLine {
+            buffer: vec![0.0; max_delay + 4],
+            write: 0,
+        };
+    }
+
+    /// Changes the maximum delay and clears the buffer, this allocates so it shouldn't be called while processing
+    pub fn resize(&mut self, max_delay: usize) {
+        self.buffer = vec![0.0; max_delay + 4];
+        self.write = 0;
+    }
+
+    /// Maximum delay in samples
+    #[inline(always)]
+    pub fn max_delay(&self) -> usize {
+        return self.buffer.len() - 4;
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+    }
+
+    /// Writes the next sample
+    #[inline]
+    pub fn push(&mut self, sample: f64) {
+        self.write = (self.write + 1) % self.buffer.len();
+        self.buffer[self.write] = sample;
+    }
+
+    /// Returns the sample that was pushed the given amount of whole samples ago (0 is the last one)
+    #[inline]
+    pub fn tap(&self, delay: usize) -> f64 {
+        let len = self.buffer.len();
+        return self.buffer[(self.write + len - delay.min(len - 1)) % len];
+    }
+
+    /// Reads the line at a fractional delay, delays below one sample are clamped to one sample
+    #[inline]
+    pub fn read(&self, delay: f64) -> f64 {
+        let delay = delay.clamp(1.0, (self.max_delay() as f64).max(1.0)); //Empty lines still have room for the interpolation points
+        let whole = delay.floor();
+        let t = delay - whole;
+        let whole = whole as usize;
+        //Four points around the read position, from newest to oldest
+        return cubic(self.tap(whole - 1), self.tap(whole), self.tap(whole + 1), self.tap(whole + 2), t);
+    }
+
+}
+
+/// Schroeder allpass filter built on a delay line, used to diffuse transients in reverbs
+#[derive(Default)]
+pub struct Allpass {
+    line: DelayLine,
+}
+
+impl Allpass {
+
+    pub fn new(max_delay: usize) -> Allpass {
+        return Allpass {
+            line: DelayLine::new(max_delay),
+        };
+    }
+
+    /// Changes the maximum delay and clears the buffer, this allocates so it shouldn't be called while processing
+    pub fn resize(&mut self, max_delay: usize) {
+        self.line.resize(max_delay);
+    }
+
+    pub fn reset(&mut self) {
+        self.line.reset();
+    }
+
+    /// Processes a sample with the delay in samples and a gain between -1 and 1
+    #[inline]
+    pub fn process(&mut self, sample: f64, delay: f64, gain: f64) -> f64 {
+        let delayed = self.line.read(delay - 1.0);
+        let feed = sample + gain * delayed;
+        self.line.push(feed);
+        return delayed - gain * feed;
+    }
+
+}
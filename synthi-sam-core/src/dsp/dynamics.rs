@@ -0,0 +1,115 @@
+use std::fmt::Display;
+
+/// Lowest level in dB, silence is clamped to this so the logarithm stays finite
+pub const MIN_DB: f64 = -150.0;
+/// Length of the averaging window of the RMS detector in seconds
+const RMS_WINDOW: f64 = 0.01;
+
+#[inline]
+pub fn db_to_gain(db: f64) -> f64 {
+    return f64::from(10.0).powf(db/20.0);
+}
+
+#[inline]
+pub fn gain_to_db(gain: f64) -> f64 {
+    return (20.0 * gain.abs().log10()).max(MIN_DB);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DetectionMode {
+    Peak,
+    Rms,
+}
+
+impl Default for DetectionMode {
+    fn default() -> Self {
+        return DetectionMode::Peak;
+    }
+}
+
+impl Display for DetectionMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            DetectionMode::Peak => "Peak",
+            DetectionMode::Rms => "RMS",
+        })
+    }
+}
+
+/// Measures the level of a signal in dB
+#[derive(Copy, Clone, Default)]
+pub struct LevelDetector {
+    square: f64,
+    coeff: f64,
+}
+
+impl LevelDetector {
+
+    pub fn setup(&mut self, time_step: f64) {
+        self.coeff = 1.0 - (-time_step/RMS_WINDOW).exp();
+        self.square = 0.0;
+    }
+
+    #[inline]
+    pub fn process(&mut self, mode: DetectionMode, sample: f64) -> f64 {
+        return match mode {
+            DetectionMode::Peak => gain_to_db(sample),
+            DetectionMode::Rms => {
+                self.square += (sample * sample - self.square) * self.coeff;
+                //The square root is halved in the dB domain
+                gain_to_db(self.square) * 0.5
+            },
+        }
+    }
+
+}
+
+/// One-pole smoothing with different times for rising and falling values
+#[derive(Copy, Clone, Default)]
+pub struct EnvelopeFollower {
+    value: f64,
+    attack: f64,
+    release: f64,
+}
+
+impl EnvelopeFollower {
+
+    /// Sets attack and release in seconds, 0 follows the input immediately
+    pub fn set_times(&mut self, attack: f64, release: f64, time_step: f64) {
+        let coeff = |time: f64| if time > 0.0 { 1.0 - (-time_step/time).exp() } else { 1.0 };
+        self.attack = coeff(attack);
+        self.release = coeff(release);
+    }
+
+    #[inline(always)]
+    pub fn reset(&mut self, value: f64) {
+        self.value = value;
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f64) -> f64 {
+        let coeff = if input > self.value { self.attack } else { self.release };
+        self.value += (input - self.value) * coeff;
+        return self.value;
+    }
+
+    #[inline(always)]
+    pub fn value(&self) -> f64 {
+        return self.value;
+    }
+
+}
+
+/// Returns the output level in dB of a compressor with a quadratic soft knee
+#[inline]
+pub fn compress_level(level: f64, threshold: f64, ratio: f64, knee: f64) -> f64 {
+    let over = level - threshold;
+    if over * 2.0 < -knee {
+        return level;
+    }
+    if knee > 0.0 && over * 2.0 <= knee {
+        let x = over + knee * 0.5;
+        return level + (1.0/ratio - 1.0) * x * x/(2.0 * knee);
+    }
+    return threshold + over/ratio;
+}
@@ -0,0 +1,344 @@
+use std::fmt::Display;
+
+/// Shape of a single envelope segment
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EnvelopeCurve {
+    Linear,
+    Exponential,    //Fast change at the start of the segment, slowly approaching the target
+    Curved(f64),    //Power curve, values below 1 bend like exponential, values above 1 start slow
+}
+
+impl Default for EnvelopeCurve {
+    fn default() -> Self {
+        return EnvelopeCurve::Linear;
+    }
+}
+
+impl Display for EnvelopeCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            EnvelopeCurve::Linear => write!(f, "Linear"),
+            EnvelopeCurve::Exponential => write!(f, "Exponential"),
+            EnvelopeCurve::Curved(c) => write!(f, "Curved ({})", c),
+        }
+    }
+}
+
+const EXP_STEEPNESS: f64 = 5.0;
+
+/// Fade out time of a killed envelope in seconds
+pub const KILL_TIME: f64 = 0.005;
+
+impl EnvelopeCurve {
+    /// Maps the progress of a segment (0 to 1) to the progress of the level (0 to 1)
+    #[inline]
+    pub fn shape(&self, progress: f64) -> f64 {
+        let progress = progress.clamp(0.0, 1.0);
+        return match self {
+            EnvelopeCurve::Linear => progress,
+            EnvelopeCurve::Exponential => (1.0 - (-EXP_STEEPNESS * progress).exp())/(1.0 - (-EXP_STEEPNESS).exp()),
+            EnvelopeCurve::Curved(c) => progress.powf(c.max(0.01)),
+        }
+    }
+}
+
+/// Describes what happens when a note is pressed while the envelope is still running
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TriggerMode {
+    Retrigger,  //Restart the attack from the current level
+    Legato,     //Keep running when the envelope hasn't been released yet
+}
+
+impl Default for TriggerMode {
+    fn default() -> Self {
+        return TriggerMode::Retrigger;
+    }
+}
+
+/// A segment that moves from the current level to a target in a given time
+#[derive(Default, Copy, Clone)]
+struct Segment {
+    start: f64,
+    time: f64,
+}
+
+impl Segment {
+
+    #[inline]
+    fn restart(&mut self, level: f64) {
+        self.start = level;
+        self.time = 0.0;
+    }
+
+    /// Advances the segment and returns the new level and wether the segment is finished
+    #[inline]
+    fn process(&mut self, target: f64, duration: f64, curve: EnvelopeCurve, time_step: f64) -> (f64, bool) {
+        self.time += time_step;
+        if duration <= 0.0 || self.time >= duration {
+            return (target, true);
+        }
+        return (self.start + (target - self.start) * curve.shape(self.time/duration), false);
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ADSRStage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Kill,   //Fast fade out, e.g. when the voice was stolen
+}
+
+impl Default for ADSRStage {
+    fn default() -> Self {
+        return ADSRStage::Idle;
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct ADSREnvelopeConfig {
+    pub attack: f64,            //Seconds
+    pub decay: f64,             //Seconds
+    pub sustain: f64,           //Level from 0 to 1
+    pub release: f64,           //Seconds
+    pub attack_curve: EnvelopeCurve,
+    pub decay_curve: EnvelopeCurve,
+    pub release_curve: EnvelopeCurve,
+    pub velocity_amount: f64,   //0 ignores the velocity, 1 scales the envelope fully by the velocity
+    pub trigger_mode: TriggerMode,
+}
+
+impl Default for ADSREnvelopeConfig {
+    fn default() -> Self {
+        return ADSREnvelopeConfig {
+            attack: 0.005,
+            decay: 0.0,
+            sustain: 1.0,
+            release: 0.005,
+            attack_curve: EnvelopeCurve::Linear,
+            decay_curve: EnvelopeCurve::Exponential,
+            release_curve: EnvelopeCurve::Exponential,
+            velocity_amount: 0.0,
+            trigger_mode: TriggerMode::Retrigger,
+        };
+    }
+}
+
+/// Attack-Decay-Sustain-Release envelope
+#[derive(Default)]
+pub struct ADSREnvelope {
+    stage: ADSRStage,
+    segment: Segment,
+    level: f64,
+    velocity_gain: f64,
+}
+
+impl ADSREnvelope {
+
+    /// Starts the envelope, an already running envelope will continue from it's current level
+    pub fn press(&mut self, config: &ADSREnvelopeConfig, velocity: f64) {
+        if config.trigger_mode == TriggerMode::Legato && (self.stage == ADSRStage::Attack || self.stage == ADSRStage::Decay || self.stage == ADSRStage::Sustain) {
+            return;
+        }
+        self.velocity_gain = 1.0 - config.velocity_amount + config.velocity_amount * velocity;
+        self.stage = ADSRStage::Attack;
+        self.segment.restart(self.level);
+    }
+
+    /// Moves the envelope to the release stage
+    pub fn release(&mut self) {
+        if self.stage != ADSRStage::Idle && self.stage != ADSRStage::Release && self.stage != ADSRStage::Kill {
+            self.stage = ADSRStage::Release;
+            self.segment.restart(self.level);
+        }
+    }
+
+    /// Fades the envelope out within a few milliseconds
+    pub fn kill(&mut self) {
+        if self.stage != ADSRStage::Idle {
+            self.stage = ADSRStage::Kill;
+            self.segment.restart(self.level);
+        }
+    }
+
+    /// Stops the envelope immediately
+    pub fn reset(&mut self) {
+        self.stage = ADSRStage::Idle;
+        self.level = 0.0;
+    }
+
+    /// Advances the envelope by one sample and returns the current gain
+    pub fn process(&mut self, config: &ADSREnvelopeConfig, time_step: f64) -> f64 {
+        match self.stage {
+            ADSRStage::Idle => {},
+            ADSRStage::Attack => {
+                let (level, done) = self.segment.process(1.0, config.attack, config.attack_curve, time_step);
+                self.level = level;
+                if done {
+                    self.stage = ADSRStage::Decay;
+                    self.segment.restart(self.level);
+                }
+            },
+            ADSRStage::Decay => {
+                let (level, done) = self.segment.process(config.sustain, config.decay, config.decay_curve, time_step);
+                self.level = level;
+                if done {
+                    self.stage = ADSRStage::Sustain;
+                }
+            },
+            ADSRStage::Sustain => self.level = config.sustain,
+            ADSRStage::Release => {
+                let (level, done) = self.segment.process(0.0, config.release, config.release_curve, time_step);
+                self.level = level;
+                if done {
+                    self.stage = ADSRStage::Idle;
+                }
+            },
+            ADSRStage::Kill => {
+                let (level, done) = self.segment.process(0.0, KILL_TIME, EnvelopeCurve::Linear, time_step);
+                self.level = level;
+                if done {
+                    self.stage = ADSRStage::Idle;
+                }
+            },
+        }
+        return self.level * self.velocity_gain;
+    }
+
+    /// Returns the current output without advancing the envelope
+    #[inline(always)]
+    pub fn level(&self) -> f64 {
+        return self.level * self.velocity_gain;
+    }
+
+    #[inline(always)]
+    pub fn stage(&self) -> ADSRStage {
+        return self.stage;
+    }
+
+    /// Returns wether the envelope still produces a signal
+    #[inline(always)]
+    pub fn is_active(&self) -> bool {
+        return self.stage != ADSRStage::Idle;
+    }
+
+}
+
+/// A single stage of a multi-stage envelope
+#[derive(Copy, Clone)]
+pub struct EnvelopeStage {
+    pub level: f64,     //Target level
+    pub time: f64,      //Seconds to reach the level
+    pub curve: EnvelopeCurve,
+}
+
+/// Configuration of a breakpoint envelope with an arbitrary number of stages
+///
+/// While the note is held the envelope stops at the end of the sustain stage or repeats the loop stages.
+/// When it is released it continues with the first stage after the sustain stage (or the loop end).
+/// The envelope is finished after the last stage.
+#[derive(Clone, Default)]
+pub struct MultiStageEnvelopeConfig {
+    pub stages: Vec<EnvelopeStage>,
+    pub sustain_stage: Option<usize>,
+    pub loop_stages: Option<(usize, usize)>,    //First and last stage of the loop (inclusive)
+    pub velocity_amount: f64,
+    pub trigger_mode: TriggerMode,
+}
+
+#[derive(Default)]
+pub struct MultiStageEnvelope {
+    stage: usize,
+    active: bool,
+    released: bool,
+    segment: Segment,
+    level: f64,
+    velocity_gain: f64,
+}
+
+impl MultiStageEnvelope {
+
+    /// Starts the envelope at the first stage
+    pub fn press(&mut self, config: &MultiStageEnvelopeConfig, velocity: f64) {
+        if config.trigger_mode == TriggerMode::Legato && self.active && !self.released {
+            return;
+        }
+        self.velocity_gain = 1.0 - config.velocity_amount + config.velocity_amount * velocity;
+        self.stage = 0;
+        self.active = !config.stages.is_empty();
+        self.released = false;
+        self.segment.restart(self.level);
+    }
+
+    /// Jumps to the release part of the envelope
+    pub fn release(&mut self, config: &MultiStageEnvelopeConfig) {
+        if !self.active || self.released {
+            return;
+        }
+        self.released = true;
+        let hold = config.sustain_stage.or(config.loop_stages.map(|(_, end)| end));
+        match hold {
+            Some(hold) => {
+                //Only jump forward, stages before the hold point have not been reached yet
+                if self.stage <= hold {
+                    self.stage = hold + 1;
+                    self.segment.restart(self.level);
+                }
+            },
+            None => {
+                self.stage = config.stages.len().max(1) - 1;
+                self.segment.restart(self.level);
+            }
+        }
+        if self.stage >= config.stages.len() {
+            self.active = false;
+        }
+    }
+
+    /// Stops the envelope immediately
+    pub fn reset(&mut self) {
+        self.active = false;
+        self.level = 0.0;
+    }
+
+    /// Advances the envelope by one sample and returns the current gain
+    pub fn process(&mut self, config: &MultiStageEnvelopeConfig, time_step: f64) -> f64 {
+        if self.active {
+            if self.stage >= config.stages.len() {
+                self.active = false;
+            }
+            else {
+                let stage = config.stages[self.stage];
+                let sustaining = !self.released && config.sustain_stage == Some(self.stage);
+                let (level, done) = self.segment.process(stage.level, stage.time, stage.curve, time_step);
+                self.level = level;
+                if done && !sustaining {
+                    match config.loop_stages {
+                        Some((start, end)) if !self.released && self.stage == end => self.stage = start,
+                        _ => self.stage += 1,
+                    }
+                    self.segment.restart(self.level);
+                    if self.stage >= config.stages.len() {
+                        self.active = false;
+                    }
+                }
+            }
+        }
+        return self.level * self.velocity_gain;
+    }
+
+    #[inline(always)]
+    pub fn stage(&self) -> usize {
+        return self.stage;
+    }
+
+    /// Returns wether the envelope still produces a signal
+    #[inline(always)]
+    pub fn is_active(&self) -> bool {
+        return self.active;
+    }
+
+}
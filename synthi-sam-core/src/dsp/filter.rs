@@ -0,0 +1,424 @@
+use std::{f64::consts::PI, fmt::Display};
+
+const MIN_CUTOFF: f64 = 5.0;
+const MAX_CUTOFF_RATIO: f64 = 0.49; //Relative to the sample rate
+const MIN_Q: f64 = 0.025;
+
+/// Keeps the cutoff in a range where the filters stay stable
+#[inline(always)]
+fn clamp_cutoff(cutoff: f64, time_step: f64) -> f64 {
+    return cutoff.clamp(MIN_CUTOFF, (MAX_CUTOFF_RATIO/time_step).max(MIN_CUTOFF));
+}
+
+/// Prewarped integrator gain of a trapezoidal integrator
+#[inline(always)]
+fn prewarp(cutoff: f64, time_step: f64) -> f64 {
+    return (PI * clamp_cutoff(cutoff, time_step) * time_step).tan();
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SVFilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+    Peak,
+}
+
+impl Default for SVFilterType {
+    fn default() -> Self {
+        return SVFilterType::LowPass;
+    }
+}
+
+impl Display for SVFilterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SVFilterType::LowPass => "Low Pass",
+            SVFilterType::HighPass => "High Pass",
+            SVFilterType::BandPass => "Band Pass",
+            SVFilterType::Notch => "Notch",
+            SVFilterType::Peak => "Peak",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SVFilterConfig {
+    pub filter_type: SVFilterType,
+    pub cutoff: f64,    //Hz
+    pub q: f64,         //0.707 is flat, higher values increase the resonance
+}
+
+impl Default for SVFilterConfig {
+    fn default() -> Self {
+        return SVFilterConfig {
+            filter_type: SVFilterType::LowPass,
+            cutoff: 20000.0,
+            q: std::f64::consts::FRAC_1_SQRT_2,
+        };
+    }
+}
+
+/// Zero-delay-feedback state variable filter (trapezoidal integration)
+///
+/// The coefficients are calculated every sample, so cutoff and resonance can be modulated freely.
+#[derive(Default)]
+pub struct StateVariableFilter {
+    ic1eq: f64,
+    ic2eq: f64,
+}
+
+impl StateVariableFilter {
+
+    pub fn process(&mut self, config: &SVFilterConfig, sample: f64, time_step: f64) -> f64 {
+        let g = prewarp(config.cutoff, time_step);
+        let k = 1.0/config.q.max(MIN_Q);
+        let a1 = 1.0/(1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let v3 = sample - self.ic2eq;
+        let v1 = a1 * self.ic1eq + a2 * v3;
+        let v2 = self.ic2eq + a2 * self.ic1eq + a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        let low = v2;
+        let band = v1;
+        let high = sample - k * band - low;
+        return match config.filter_type {
+            SVFilterType::LowPass => low,
+            SVFilterType::HighPass => high,
+            SVFilterType::BandPass => band * k, //Normalized to unity gain at the cutoff
+            SVFilterType::Notch => low + high,
+            SVFilterType::Peak => low - high,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+
+}
+
+#[derive(Copy, Clone)]
+pub struct LadderFilterConfig {
+    pub cutoff: f64,        //Hz
+    pub resonance: f64,     //0 to 1, self oscillation starts at 1
+    pub drive: f64,         //Input gain into the saturation, 1 is neutral
+}
+
+impl Default for LadderFilterConfig {
+    fn default() -> Self {
+        return LadderFilterConfig {
+            cutoff: 20000.0,
+            resonance: 0.0,
+            drive: 1.0,
+        };
+    }
+}
+
+/// Moog-style 4 pole (24 dB/oct) ladder low pass filter
+///
+/// Consists of four zero-delay-feedback one-pole stages, the feedback loop is solved instantaneously
+/// and saturated with tanh, which keeps the filter stable even while self-oscillating.
+#[derive(Default)]
+pub struct LadderFilter {
+    stages: [f64; 4],
+}
+
+impl LadderFilter {
+
+    pub fn process(&mut self, config: &LadderFilterConfig, sample: f64, time_step: f64) -> f64 {
+        let g = prewarp(config.cutoff, time_step);
+        let gain = g/(1.0 + g);
+        let k = config.resonance.clamp(0.0, 1.0) * 4.0;
+        let drive = config.drive.max(0.01);
+
+        //Solve the feedback loop
+        let feedback = self.stages.iter().fold(0.0, |acc, s| acc * gain + s/(1.0 + g));
+        let gain4 = gain.powi(4);
+        let input = sample * (1.0 + k * 0.5); //Compensate the passband loss at high resonance
+        let out = (gain4 * input + feedback)/(1.0 + k * gain4);
+        let mut u = ((input - k * out) * drive).tanh()/drive;
+
+        //Process stages
+        for s in self.stages.iter_mut() {
+            let v = (u - *s) * gain;
+            let y = v + *s;
+            *s = y + v;
+            u = y;
+        }
+        return u;
+    }
+
+    pub fn reset(&mut self) {
+        self.stages = [0.0; 4];
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BiquadType {
+    LowPass,
+    HighPass,
+    BandPass,
+    Notch,
+    AllPass,
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+impl Default for BiquadType {
+    fn default() -> Self {
+        return BiquadType::LowPass;
+    }
+}
+
+impl Display for BiquadType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            BiquadType::LowPass => "Low Pass",
+            BiquadType::HighPass => "High Pass",
+            BiquadType::BandPass => "Band Pass",
+            BiquadType::Notch => "Notch",
+            BiquadType::AllPass => "All Pass",
+            BiquadType::Peak => "Peak",
+            BiquadType::LowShelf => "Low Shelf",
+            BiquadType::HighShelf => "High Shelf",
+        })
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct BiquadConfig {
+    pub filter_type: BiquadType,
+    pub freq: f64,      //Hz
+    pub q: f64,
+    pub gain: f64,      //dB, only used by peak and shelf filters
+}
+
+impl Default for BiquadConfig {
+    fn default() -> Self {
+        return BiquadConfig {
+            filter_type: BiquadType::LowPass,
+            freq: 1000.0,
+            q: std::f64::consts::FRAC_1_SQRT_2,
+            gain: 0.0,
+        };
+    }
+}
+
+/// Normalized biquad coefficients (a0 = 1)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl Default for BiquadCoefficients {
+    fn default() -> Self {
+        return BiquadCoefficients::identity();
+    }
+}
+
+impl BiquadCoefficients {
+
+    /// Coefficients that pass the signal unchanged
+    pub fn identity() -> BiquadCoefficients {
+        return BiquadCoefficients { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0 };
+    }
+
+    /// Calculates the coefficients according to the RBJ audio EQ cookbook
+    pub fn new(config: &BiquadConfig, time_step: f64) -> BiquadCoefficients {
+        let w0 = 2.0 * PI * clamp_cutoff(config.freq, time_step) * time_step;
+        let cos = w0.cos();
+        let alpha = w0.sin()/(2.0 * config.q.max(MIN_Q));
+        let a = 10.0f64.powf(config.gain/40.0);
+
+        let (b0, b1, b2, a0, a1, a2) = match config.filter_type {
+            BiquadType::LowPass => ((1.0 - cos)/2.0, 1.0 - cos, (1.0 - cos)/2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::HighPass => ((1.0 + cos)/2.0, -(1.0 + cos), (1.0 + cos)/2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::AllPass => (1.0 - alpha, -2.0 * cos, 1.0 + alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::Peak => (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha/a, -2.0 * cos, 1.0 - alpha/a),
+            BiquadType::LowShelf => {
+                let sq = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + sq),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - sq),
+                    (a + 1.0) + (a - 1.0) * cos + sq,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - sq,
+                )
+            },
+            BiquadType::HighShelf => {
+                let sq = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + sq),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - sq),
+                    (a + 1.0) - (a - 1.0) * cos + sq,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - sq,
+                )
+            },
+        };
+        return BiquadCoefficients {
+            b0: b0/a0,
+            b1: b1/a0,
+            b2: b2/a0,
+            a1: a1/a0,
+            a2: a2/a0,
+        };
+    }
+
+    /// Returns the linear magnitude of the filter at the given frequency
+    pub fn magnitude(&self, freq: f64, time_step: f64) -> f64 {
+        let w = 2.0 * PI * freq * time_step;
+        let (cos1, sin1) = (w.cos(), w.sin());
+        let (cos2, sin2) = ((2.0 * w).cos(), (2.0 * w).sin());
+        //Evaluate numerator and denominator on the unit circle
+        let num_re = self.b0 + self.b1 * cos1 + self.b2 * cos2;
+        let num_im = -(self.b1 * sin1 + self.b2 * sin2);
+        let den_re = 1.0 + self.a1 * cos1 + self.a2 * cos2;
+        let den_im = -(self.a1 * sin1 + self.a2 * sin2);
+        return ((num_re * num_re + num_im * num_im)/(den_re * den_re + den_im * den_im)).sqrt();
+    }
+
+}
+
+/// Biquad filter in transposed direct form II
+#[derive(Default)]
+pub struct BiquadFilter {
+    z1: f64,
+    z2: f64,
+}
+
+impl BiquadFilter {
+
+    #[inline]
+    pub fn process(&mut self, coeffs: &BiquadCoefficients, sample: f64) -> f64 {
+        let out = coeffs.b0 * sample + self.z1;
+        self.z1 = coeffs.b1 * sample - coeffs.a1 * out + self.z2;
+        self.z2 = coeffs.b2 * sample - coeffs.a2 * out;
+        return out;
+    }
+
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME_STEP: f64 = 1.0/48000.0;
+    const NYQUIST: f64 = 24000.0;
+    const CUTOFF: f64 = 1000.0;
+
+    fn assert_close(actual: f64, expected: f64, name: &str) {
+        assert!((actual - expected).abs() < 1e-3, "{}: expected {}, got {}", name, expected, actual);
+    }
+
+    /// Returns the peak output amplitude of a unit sine after the filter has settled
+    fn sine_gain(freq: f64, mut process: impl FnMut(f64) -> f64) -> f64 {
+        let settle = 9600;
+        let mut peak: f64 = 0.0;
+        for i in 0..(settle + 4800) {
+            let out = process((2.0 * PI * freq * i as f64 * TIME_STEP).sin());
+            if i >= settle {
+                peak = peak.max(out.abs());
+            }
+        }
+        return peak;
+    }
+
+    #[test]
+    fn biquad_magnitude() {
+        let boost = 10.0f64.powf(6.0/20.0);
+        let half_boost = 10.0f64.powf(3.0/20.0);
+        //Expected gain at DC, at the cutoff and at Nyquist
+        let cases = [
+            (BiquadType::LowPass, 1.0, std::f64::consts::FRAC_1_SQRT_2, 0.0),
+            (BiquadType::HighPass, 0.0, std::f64::consts::FRAC_1_SQRT_2, 1.0),
+            (BiquadType::BandPass, 0.0, 1.0, 0.0),
+            (BiquadType::Notch, 1.0, 0.0, 1.0),
+            (BiquadType::AllPass, 1.0, 1.0, 1.0),
+            (BiquadType::Peak, 1.0, boost, 1.0),
+            (BiquadType::LowShelf, boost, half_boost, 1.0),
+            (BiquadType::HighShelf, 1.0, half_boost, boost),
+        ];
+        for (filter_type, dc, cutoff, nyquist) in cases {
+            let config = BiquadConfig {
+                filter_type: filter_type,
+                freq: CUTOFF,
+                gain: 6.0,
+                ..Default::default()
+            };
+            let coeffs = BiquadCoefficients::new(&config, TIME_STEP);
+            let name = filter_type.to_string();
+            assert_close(coeffs.magnitude(0.0, TIME_STEP), dc, &name);
+            assert_close(coeffs.magnitude(CUTOFF, TIME_STEP), cutoff, &name);
+            assert_close(coeffs.magnitude(NYQUIST, TIME_STEP), nyquist, &name);
+        }
+    }
+
+    #[test]
+    fn state_variable_filter_gain() {
+        let mut config = SVFilterConfig {
+            cutoff: CUTOFF,
+            ..Default::default()
+        };
+        let mut filter = StateVariableFilter::default();
+        assert!((sine_gain(100.0, |s| filter.process(&config, s, TIME_STEP)) - 1.0).abs() < 0.02);
+        filter.reset();
+        assert!(sine_gain(10000.0, |s| filter.process(&config, s, TIME_STEP)) < 0.02);
+
+        config.filter_type = SVFilterType::HighPass;
+        filter.reset();
+        assert!(sine_gain(100.0, |s| filter.process(&config, s, TIME_STEP)) < 0.02);
+        filter.reset();
+        assert!((sine_gain(10000.0, |s| filter.process(&config, s, TIME_STEP)) - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn ladder_filter_gain() {
+        let config = LadderFilterConfig {
+            cutoff: CUTOFF,
+            ..Default::default()
+        };
+        let mut filter = LadderFilter::default();
+        //Small input level so the saturation stays linear
+        assert!((sine_gain(100.0, |s| filter.process(&config, s * 0.1, TIME_STEP) * 10.0) - 1.0).abs() < 0.05);
+        filter.reset();
+        assert!(sine_gain(10000.0, |s| filter.process(&config, s * 0.1, TIME_STEP) * 10.0) < 0.001);
+    }
+
+    #[test]
+    fn ladder_filter_bounded_at_full_resonance() {
+        let config = LadderFilterConfig {
+            cutoff: CUTOFF,
+            resonance: 1.0,
+            drive: 1.0,
+        };
+        let mut filter = LadderFilter::default();
+        //Drive it hard at the cutoff, then let it ring out on its own
+        let loud = sine_gain(CUTOFF, |s| filter.process(&config, s * 4.0, TIME_STEP));
+        assert!(loud.is_finite() && loud < 2.0, "{}", loud);
+        let ringing = sine_gain(CUTOFF, |_| filter.process(&config, 0.0, TIME_STEP));
+        assert!(ringing.is_finite() && ringing < 2.0, "{}", ringing);
+    }
+
+}
@@ -0,0 +1,147 @@
+use std::{f64::consts::PI, fmt::Display};
+
+/// Zero crossings of the sinc kernel on each side
+const SINC_HALF_WIDTH: usize = 8;
+pub const SINC_TAPS: usize = SINC_HALF_WIDTH * 2;
+/// Fractional positions the kernel is precomputed for, positions between them are interpolated linearly
+const SINC_PHASES: usize = 512;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Cubic,
+    Sinc,
+}
+
+impl Default for Interpolation {
+    fn default() -> Self {
+        return Interpolation::Cubic;
+    }
+}
+
+impl Display for Interpolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Interpolation::Linear => "Linear",
+            Interpolation::Cubic => "Cubic",
+            Interpolation::Sinc => "Sinc",
+        })
+    }
+}
+
+/// Four point cubic hermite spline between y1 and y2
+#[inline]
+pub fn cubic(y0: f64, y1: f64, y2: f64, y3: f64, t: f64) -> f64 {
+    let c1 = 0.5 * (y2 - y0);
+    let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+    let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+    return ((c3 * t + c2) * t + c1) * t + y1;
+}
+
+/// Sinc function windowed by a Blackman-Harris window that reaches zero at the given half width
+pub fn windowed_sinc(x: f64, half_width: f64) -> f64 {
+    if x.abs() >= half_width {
+        return 0.0;
+    }
+    let sinc = if x.abs() < 1e-9 { 1.0 } else { (PI * x).sin()/(PI * x) };
+    let w = 2.0 * PI * (x/half_width * 0.5 + 0.5);
+    let window = 0.35875 - 0.48829 * w.cos() + 0.14128 * (2.0 * w).cos() - 0.01168 * (3.0 * w).cos();
+    return sinc * window;
+}
+
+/// Precomputed windowed sinc kernel for band limited interpolation
+///
+/// The table allocates, so it should be created outside of the audio thread and shared.
+pub struct SincTable {
+    table: Vec<f32>,
+}
+
+impl Default for SincTable {
+    fn default() -> Self {
+        return SincTable::new();
+    }
+}
+
+impl SincTable {
+
+    pub fn new() -> SincTable {
+        let mut table = Vec::with_capacity((SINC_PHASES + 1) * SINC_TAPS);
+        for phase in 0..=SINC_PHASES {
+            let frac = phase as f64/SINC_PHASES as f64;
+            for tap in 0..SINC_TAPS {
+                let x = tap as f64 - (SINC_HALF_WIDTH - 1) as f64 - frac;
+                table.push(windowed_sinc(x, SINC_HALF_WIDTH as f64) as f32);
+            }
+        }
+        return SincTable {
+            table: table,
+        };
+    }
+
+    /// Interpolates at index + frac, the closure returns the sample at an index
+    #[inline]
+    pub fn interpolate<F: Fn(isize) -> f64>(&self, index: isize, frac: f64, sample: F) -> f64 {
+        let position = frac.clamp(0.0, 1.0) * SINC_PHASES as f64;
+        let phase = (position as usize).min(SINC_PHASES - 1);
+        let t = position - phase as f64;
+        let (a, b) = (&self.table[phase * SINC_TAPS..], &self.table[(phase + 1) * SINC_TAPS..]);
+        let mut sum = 0.0;
+        for tap in 0..SINC_TAPS {
+            let weight = a[tap] as f64 + (b[tap] as f64 - a[tap] as f64) * t;
+            sum += sample(index + tap as isize - (SINC_HALF_WIDTH - 1) as isize) * weight;
+        }
+        return sum;
+    }
+
+}
+
+impl Interpolation {
+
+    /// Reads a signal at a fractional position, the closure returns the sample at an index
+    #[inline]
+    pub fn interpolate<F: Fn(isize) -> f64>(&self, table: &SincTable, position: f64, sample: F) -> f64 {
+        let index = position.floor();
+        let frac = position - index;
+        let index = index as isize;
+        return match self {
+            Interpolation::Linear => {
+                let (a, b) = (sample(index), sample(index + 1));
+                a + (b - a) * frac
+            },
+            Interpolation::Cubic => cubic(sample(index - 1), sample(index), sample(index + 1), sample(index + 2), frac),
+            Interpolation::Sinc => table.interpolate(index, frac, sample),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNAL: [f64; 24] = [0.3, -0.1, 0.8, 0.5, -0.7, 0.2, 0.9, -0.4, 0.1, 0.6, -0.9, 0.0, 0.4, -0.2, 0.7, -0.6, 0.25, 0.05, -0.35, 0.45, 0.15, -0.8, 0.55, -0.05];
+
+    fn sample(index: isize) -> f64 {
+        return if index >= 0 { SIGNAL.get(index as usize).copied().unwrap_or(0.0) } else { 0.0 };
+    }
+
+    #[test]
+    fn integer_positions_return_input() {
+        let table = SincTable::new();
+        for interpolation in [Interpolation::Linear, Interpolation::Cubic, Interpolation::Sinc] {
+            for (i, expected) in SIGNAL.iter().enumerate() {
+                let value = interpolation.interpolate(&table, i as f64, sample);
+                assert!((value - expected).abs() < 1e-9, "{} at {}: {}", interpolation, i, value);
+            }
+        }
+    }
+
+    #[test]
+    fn cubic_end_points() {
+        assert_eq!(cubic(0.3, -0.1, 0.8, 0.5, 0.0), -0.1);
+        assert!((cubic(0.3, -0.1, 0.8, 0.5, 1.0) - 0.8).abs() < 1e-12);
+        //Straight lines stay straight
+        assert!((cubic(0.0, 1.0, 2.0, 3.0, 0.25) - 1.25).abs() < 1e-12);
+    }
+
+}
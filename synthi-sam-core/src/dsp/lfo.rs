@@ -0,0 +1,153 @@
+use std::fmt::Display;
+
+use crate::util::{random::Random, tempo::NoteDivision};
+
+use super::oscillator::{Oscillator, WaveForm};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+    SampleAndHold,  //New random value every cycle
+    SmoothRandom,   //Glides between random values every cycle
+}
+
+impl Default for LfoShape {
+    fn default() -> Self {
+        return LfoShape::Sine;
+    }
+}
+
+impl Display for LfoShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            LfoShape::Sine => "Sine",
+            LfoShape::Triangle => "Triangle",
+            LfoShape::Saw => "Saw",
+            LfoShape::Square => "Square",
+            LfoShape::SampleAndHold => "Sample & Hold",
+            LfoShape::SmoothRandom => "Smooth Random",
+        })
+    }
+}
+
+/// Describes how the LFO behaves when a note is pressed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LfoMode {
+    Free,       //Phase keeps running, only delay and fade-in restart
+    Retrigger,  //Phase, delay and fade-in restart with every note
+    Global,     //A single LFO shared by all voices, the owner advances it once per sample and notes don't affect it
+}
+
+impl Default for LfoMode {
+    fn default() -> Self {
+        return LfoMode::Free;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LfoRate {
+    Free(f64),              //Hz
+    Synced(NoteDivision),   //Length of one cycle
+}
+
+impl Default for LfoRate {
+    fn default() -> Self {
+        return LfoRate::Free(1.0);
+    }
+}
+
+impl LfoRate {
+
+    /// Returns the frequency in Hz at the given tempo
+    #[inline]
+    pub fn freq(&self, bpm: f64) -> f64 {
+        return match self {
+            LfoRate::Free(freq) => *freq,
+            LfoRate::Synced(division) => division.freq(bpm),
+        }
+    }
+
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct LfoConfig {
+    pub shape: LfoShape,
+    pub rate: LfoRate,
+    pub mode: LfoMode,
+    pub phase: f64,     //Start phase when retriggered (0 to 1)
+    pub delay: f64,     //Seconds until the LFO starts
+    pub fade_in: f64,   //Seconds to fade in after the delay
+}
+
+/// Low frequency oscillator with a bipolar output from -1 to 1
+#[derive(Default)]
+pub struct Lfo {
+    osc: Oscillator,
+    random: Random,
+    last_value: f64,
+    next_value: f64,
+    time: f64,
+}
+
+impl Lfo {
+
+    /// Seeds the generator of the random shapes, so voices don't share the same random sequence
+    pub fn seed(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+    }
+
+    /// Notifies the LFO that a new note started
+    pub fn trigger(&mut self, config: &LfoConfig) {
+        match config.mode {
+            LfoMode::Free => self.time = 0.0,
+            LfoMode::Retrigger => {
+                self.time = 0.0;
+                self.osc.reset(config.phase);
+                self.last_value = self.next_value;
+                self.next_value = self.random.next_bipolar();
+            },
+            LfoMode::Global => {},
+        }
+    }
+
+    /// Advances the LFO by one sample and returns the current value
+    pub fn process(&mut self, config: &LfoConfig, bpm: f64, time_step: f64) -> f64 {
+        if self.osc.advance(config.rate.freq(bpm), time_step) {
+            self.last_value = self.next_value;
+            self.next_value = self.random.next_bipolar();
+        }
+        let phase = self.osc.phase();
+        let value = match config.shape {
+            LfoShape::Sine => WaveForm::Sine.synthesize(phase, 0.5),
+            LfoShape::Triangle => WaveForm::Triangle.synthesize(phase, 0.5),
+            LfoShape::Saw => WaveForm::Saw.synthesize(phase, 0.5),
+            LfoShape::Square => WaveForm::Square.synthesize(phase, 0.5),
+            LfoShape::SampleAndHold => self.next_value,
+            LfoShape::SmoothRandom => {
+                let progress = (1.0 - (phase * std::f64::consts::PI).cos()) * 0.5;
+                self.last_value + (self.next_value - self.last_value) * progress
+            },
+        };
+        return value * self.fade(config, time_step);
+    }
+
+    /// Gain of delay and fade-in
+    fn fade(&mut self, config: &LfoConfig, time_step: f64) -> f64 {
+        if config.mode == LfoMode::Global {
+            return 1.0;
+        }
+        let fade_end = config.delay + config.fade_in;
+        if self.time >= fade_end {
+            return 1.0;
+        }
+        self.time += time_step;
+        if self.time < config.delay {
+            return 0.0;
+        }
+        return ((self.time - config.delay)/config.fade_in.max(time_step)).min(1.0);
+    }
+
+}
@@ -1,4 +1,15 @@
 pub mod oscillator;
+pub mod envelope;
+pub mod lfo;
+pub mod filter;
+pub mod portamento;
+pub mod unison;
+pub mod tuning;
+pub mod delay;
+pub mod smooth;
+pub mod oversampling;
+pub mod dynamics;
+pub mod interpolation;
 
 #[inline]
 pub fn note_to_freq_transpose (note: f64) -> f64 {
@@ -10,3 +21,11 @@ pub fn note_to_freq (note: f64) -> f64 {
     return 440.0 * f64::from(2.0).powf((note - 69.0)/12.0);
 }
 
+/// Returns the left and right gain for a pan position from -1 (left) to 1 (right) using an equal power law
+/// 
+/// The center position yields unity gain on both channels
+#[inline]
+pub fn pan_equal_power (pan: f64) -> (f64, f64) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f64::consts::FRAC_PI_4;
+    return (angle.cos() * std::f64::consts::SQRT_2, angle.sin() * std::f64::consts::SQRT_2);
+}
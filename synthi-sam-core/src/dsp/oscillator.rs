@@ -4,6 +4,7 @@ use std::fmt::Display;
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum WaveForm {
     Sine,
+    Triangle,
     Saw,
     Square,
 }
@@ -18,6 +19,7 @@ impl Display for WaveForm {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
             WaveForm::Sine => "Sine",
+            WaveForm::Triangle => "Triangle",
             WaveForm::Square => "Square",
             WaveForm::Saw => "Saw",
         })
@@ -25,12 +27,14 @@ impl Display for WaveForm {
 }
 
 impl WaveForm {
-    fn synthesize(&self, phase: f64) -> f64{
+    /// Returns the value of the waveform at the given phase (0 to 1), the pulse width only affects the square wave
+    pub fn synthesize(&self, phase: f64, pulse_width: f64) -> f64{
         let f: f64;
         match self {
             WaveForm::Sine => f = (phase * (std::f64::consts::PI as f64) * 2.0).sin(),
+            WaveForm::Triangle => f = 1.0 - (phase * 4.0 - 2.0).abs(),
             WaveForm::Square => {
-                if phase < 0.5 {
+                if phase < pulse_width {
                     f = 1.0;
                 }
                 else {
@@ -43,10 +47,21 @@ impl WaveForm {
     }
 }
 
-#[derive(Default)]
+#[derive(Copy, Clone)]
 pub struct OscilatorConfig {
     pub waveform: WaveForm,
     pub freq: f64,
+    pub pulse_width: f64,
+}
+
+impl Default for OscilatorConfig {
+    fn default() -> Self {
+        return OscilatorConfig {
+            waveform: WaveForm::default(),
+            freq: 0.0,
+            pulse_width: 0.5,
+        };
+    }
 }
 
 #[derive(Default)]
@@ -56,14 +71,37 @@ pub struct Oscillator {
 
 impl Oscillator {
 
-    pub fn process(&mut self, osc: OscilatorConfig, time_step: f64) -> f64 {
-        self.phase += time_step * osc.freq;
+    /// Restarts the oscillator at the given phase (0 to 1)
+    pub fn reset(&mut self, phase: f64) {
+        self.phase = phase - phase.floor();
+    }
+
+    /// Advances the phase and returns wether a new cycle has started
+    #[inline]
+    pub fn advance(&mut self, freq: f64, time_step: f64) -> bool {
+        let step = time_step * freq;
+        //Hold the phase on invalid frequencies instead of losing it
+        if !step.is_finite() {
+            return false;
+        }
+        self.phase += step;
         //Modulo
-        while self.phase >= 1.0 {
-            self.phase -= 1.0;
+        let wrapped = self.phase >= 1.0;
+        if wrapped {
+            self.phase = self.phase.fract();
         }
+        return wrapped;
+    }
+
+    #[inline(always)]
+    pub fn phase(&self) -> f64 {
+        return self.phase;
+    }
+
+    pub fn process(&mut self, osc: OscilatorConfig, time_step: f64) -> f64 {
+        self.advance(osc.freq, time_step);
         //Synthesize
-        return osc.waveform.synthesize(self.phase);
+        return osc.waveform.synthesize(self.phase, osc.pulse_width.clamp(0.01, 0.99));
     }
 
 }
\ No newline at end of file
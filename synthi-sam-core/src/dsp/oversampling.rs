@@ -0,0 +1,216 @@
+use std::{f64::consts::PI, fmt::Display};
+
+/// Number of allpass coefficients of each half-band filter
+const HALF_BAND_COEFS: usize = 8;
+/// Width of the transition band relative to the sample rate, 8 coefficients give about 70 dB of rejection
+const HALF_BAND_TRANSITION: f64 = 0.04;
+pub const MAX_OVERSAMPLING: usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OversamplingFactor {
+    None,
+    X2,
+    X4,
+    X8,
+}
+
+impl Default for OversamplingFactor {
+    fn default() -> Self {
+        return OversamplingFactor::None;
+    }
+}
+
+impl Display for OversamplingFactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x", self.factor())
+    }
+}
+
+impl OversamplingFactor {
+
+    #[inline]
+    pub fn factor(&self) -> usize {
+        return 1 << self.stages();
+    }
+
+    /// Number of 2x stages
+    #[inline]
+    pub fn stages(&self) -> usize {
+        return match self {
+            OversamplingFactor::None => 0,
+            OversamplingFactor::X2 => 1,
+            OversamplingFactor::X4 => 2,
+            OversamplingFactor::X8 => 3,
+        }
+    }
+
+}
+
+/// Calculates the coefficients of a polyphase IIR half-band filter from the width of the transition band
+///
+/// This is the elliptic design by Valenzuela and Constantinides, as used in Laurent de Soras' HIIR library.
+pub fn half_band_coefficients(count: usize, transition: f64) -> Vec<f64> {
+    let mut k = ((1.0 - transition * 2.0) * PI/4.0).tan();
+    k *= k;
+    let kksqrt = (1.0 - k * k).powf(0.25);
+    let e = 0.5 * (1.0 - kksqrt)/(1.0 + kksqrt);
+    let e4 = e.powi(4);
+    let q = e * (1.0 + e4 * (2.0 + e4 * (15.0 + 150.0 * e4)));
+    let order = (count * 2 + 1) as f64;
+
+    return (1..=count).map(|c| {
+        let c = c as f64;
+        let mut num = 0.0;
+        let mut i = 0.0;
+        let mut sign = 1.0;
+        loop {
+            let term = q.powf(i * (i + 1.0)) * ((i * 2.0 + 1.0) * c * PI/order).sin() * sign;
+            num += term;
+            sign = -sign;
+            i += 1.0;
+            if term.abs() <= 1e-100 {
+                break;
+            }
+        }
+        let mut den = 0.0;
+        let mut i = 1.0;
+        let mut sign = -1.0;
+        loop {
+            let term = q.powf(i * i) * (i * 2.0 * c * PI/order).cos() * sign;
+            den += term;
+            sign = -sign;
+            i += 1.0;
+            if term.abs() <= 1e-100 {
+                break;
+            }
+        }
+        let ww = num * q.powf(0.25)/(den + 0.5);
+        let wwsq = ww * ww;
+        let x = ((1.0 - wwsq * k) * (1.0 - wwsq/k)).sqrt()/(1.0 + wwsq);
+        (1.0 - x)/(1.0 + x)
+    }).collect();
+}
+
+/// Two parallel chains of allpass filters, the even coefficients are on the first path and the odd ones on the second
+#[derive(Clone)]
+struct AllpassPaths {
+    coefs: [f64; HALF_BAND_COEFS],
+    x: [f64; HALF_BAND_COEFS],
+    y: [f64; HALF_BAND_COEFS],
+}
+
+impl AllpassPaths {
+
+    fn new() -> AllpassPaths {
+        let mut coefs = [0.0; HALF_BAND_COEFS];
+        coefs.copy_from_slice(&half_band_coefficients(HALF_BAND_COEFS, HALF_BAND_TRANSITION));
+        return AllpassPaths {
+            coefs: coefs,
+            x: [0.0; HALF_BAND_COEFS],
+            y: [0.0; HALF_BAND_COEFS],
+        };
+    }
+
+    fn reset(&mut self) {
+        self.x = [0.0; HALF_BAND_COEFS];
+        self.y = [0.0; HALF_BAND_COEFS];
+    }
+
+    /// Runs one sample through each path
+    #[inline]
+    fn process(&mut self, even: f64, odd: f64) -> (f64, f64) {
+        let (mut even, mut odd) = (even, odd);
+        for i in (0..HALF_BAND_COEFS).step_by(2) {
+            let t0 = (even - self.y[i]) * self.coefs[i] + self.x[i];
+            let t1 = (odd - self.y[i + 1]) * self.coefs[i + 1] + self.x[i + 1];
+            self.x[i] = even;
+            self.x[i + 1] = odd;
+            self.y[i] = t0;
+            self.y[i + 1] = t1;
+            even = t0;
+            odd = t1;
+        }
+        return (even, odd);
+    }
+
+}
+
+/// Changes the sample rate by factors of two with cascaded polyphase half-band filters
+///
+/// The signal is upsampled, processed by a closure at the higher rate and filtered back down,
+/// so that the harmonics of nonlinear processing above the original Nyquist frequency don't alias.
+#[derive(Clone)]
+pub struct Oversampler {
+    factor: OversamplingFactor,
+    up: [AllpassPaths; 3],
+    down: [AllpassPaths; 3],
+}
+
+impl Default for Oversampler {
+    fn default() -> Self {
+        return Oversampler::new(OversamplingFactor::None);
+    }
+}
+
+impl Oversampler {
+
+    pub fn new(factor: OversamplingFactor) -> Oversampler {
+        return Oversampler {
+            factor: factor,
+            up: [AllpassPaths::new(), AllpassPaths::new(), AllpassPaths::new()],
+            down: [AllpassPaths::new(), AllpassPaths::new(), AllpassPaths::new()],
+        };
+    }
+
+    #[inline(always)]
+    pub fn factor(&self) -> OversamplingFactor {
+        return self.factor;
+    }
+
+    /// Changes the factor and clears the filters
+    pub fn set_factor(&mut self, factor: OversamplingFactor) {
+        if factor != self.factor {
+            self.factor = factor;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for paths in self.up.iter_mut().chain(self.down.iter_mut()) {
+            paths.reset();
+        }
+    }
+
+    /// Processes one sample at the base rate, the closure is called factor times with the upsampled signal
+    #[inline]
+    pub fn process<F: FnMut(f64) -> f64>(&mut self, sample: f64, mut f: F) -> f64 {
+        let stages = self.factor.stages();
+        let mut buffer = [0.0; MAX_OVERSAMPLING];
+        let mut temp = [0.0; MAX_OVERSAMPLING];
+        buffer[0] = sample;
+        let mut count = 1;
+        //Upsample
+        for paths in self.up[..stages].iter_mut() {
+            for i in 0..count {
+                let (a, b) = paths.process(buffer[i], buffer[i]);
+                temp[i * 2] = a;
+                temp[i * 2 + 1] = b;
+            }
+            count *= 2;
+            buffer[..count].copy_from_slice(&temp[..count]);
+        }
+        for s in buffer[..count].iter_mut() {
+            *s = f(*s);
+        }
+        //Downsample
+        for paths in self.down[..stages].iter_mut().rev() {
+            count /= 2;
+            for i in 0..count {
+                let (a, b) = paths.process(buffer[i * 2 + 1], buffer[i * 2]);
+                buffer[i] = (a + b) * 0.5;
+            }
+        }
+        return buffer[0];
+    }
+
+}
@@ -0,0 +1,80 @@
+use std::fmt::Display;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GlideMode {
+    ConstantTime,   //Every glide takes the same time
+    ConstantRate,   //The time depends on the interval
+}
+
+impl Default for GlideMode {
+    fn default() -> Self {
+        return GlideMode::ConstantTime;
+    }
+}
+
+impl Display for GlideMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            GlideMode::ConstantTime => "Constant Time",
+            GlideMode::ConstantRate => "Constant Rate",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct PortamentoConfig {
+    pub time: f64,          //Seconds for constant time, seconds per octave for constant rate, 0 disables the glide
+    pub mode: GlideMode,
+    pub legato_only: bool,  //Only glide when the new note overlaps the previous one
+}
+
+/// Glides the pitch (in notes) from the previous to the current note
+#[derive(Default)]
+pub struct Portamento {
+    current: f64,
+    target: f64,
+    step: f64,  //Notes per second
+}
+
+impl Portamento {
+
+    /// Starts a glide to a new note, without a previous note or if the glide is disabled it jumps to the target
+    pub fn start(&mut self, config: &PortamentoConfig, from: Option<f64>, to: f64, legato: bool) {
+        self.target = to;
+        match from {
+            Some(from) if config.time > 0.0 && (legato || !config.legato_only) => {
+                self.current = from;
+                let distance = (to - from).abs();
+                self.step = match config.mode {
+                    GlideMode::ConstantTime => distance/config.time,
+                    GlideMode::ConstantRate => 12.0/config.time,
+                };
+            },
+            _ => {
+                self.current = to;
+                self.step = 0.0;
+            }
+        }
+    }
+
+    /// Advances the glide and returns the current note
+    #[inline]
+    pub fn process(&mut self, time_step: f64) -> f64 {
+        if self.current != self.target {
+            let step = self.step * time_step;
+            if (self.target - self.current).abs() <= step {
+                self.current = self.target;
+            }
+            else {
+                self.current += step * (self.target - self.current).signum();
+            }
+        }
+        return self.current;
+    }
+
+    #[inline(always)]
+    pub fn current(&self) -> f64 {
+        return self.current;
+    }
+
+}
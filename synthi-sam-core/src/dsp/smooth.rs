@@ -0,0 +1,64 @@
+/// One-pole smoothing of a parameter, removes clicks and zipper noise when a value changes
+#[derive(Copy, Clone)]
+pub struct Smoother {
+    value: f64,
+    target: f64,
+    coeff: f64,
+}
+
+impl Default for Smoother {
+    fn default() -> Self {
+        return Smoother {
+            value: 0.0,
+            target: 0.0,
+            coeff: 1.0,
+        };
+    }
+}
+
+impl Smoother {
+
+    /// Creates a smoother that starts at the given value
+    pub fn new(value: f64) -> Smoother {
+        return Smoother {
+            value: value,
+            target: value,
+            coeff: 1.0,
+        };
+    }
+
+    /// Sets the time in seconds to reach about 63% of a change, 0 disables the smoothing
+    pub fn set_time(&mut self, time: f64, time_step: f64) {
+        self.coeff = if time > 0.0 { 1.0 - (-time_step/time).exp() } else { 1.0 };
+    }
+
+    #[inline(always)]
+    pub fn set(&mut self, target: f64) {
+        self.target = target;
+    }
+
+    /// Jumps to a value without smoothing
+    #[inline(always)]
+    pub fn reset(&mut self, value: f64) {
+        self.value = value;
+        self.target = value;
+    }
+
+    /// Advances by one sample and returns the smoothed value
+    #[inline]
+    pub fn process(&mut self) -> f64 {
+        self.value += (self.target - self.value) * self.coeff;
+        return self.value;
+    }
+
+    #[inline(always)]
+    pub fn value(&self) -> f64 {
+        return self.value;
+    }
+
+    #[inline(always)]
+    pub fn target(&self) -> f64 {
+        return self.target;
+    }
+
+}
@@ -0,0 +1,330 @@
+use std::{str::FromStr, path::Path};
+
+const MIDI_NOTES: usize = 128;
+
+/// Frequency of A4 (MIDI note 69) that MIDI Tuning Standard messages are relative to
+const MTS_REFERENCE_FREQ: f64 = 440.0;
+const MTS_NO_CHANGE: (u8, u8, u8) = (0x7F, 0x7F, 0x7F);
+
+/// Returns the next line of a Scala file that isn't a comment
+fn next_line<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    return lines.find(|l| !l.starts_with('!')).map(|l| l.trim());
+}
+
+/// Parses the first word of a line as a number
+fn parse_value<T: FromStr>(line: Option<&str>, err: &'static str) -> Result<T, &'static str> {
+    return line.and_then(|l| l.split_whitespace().next()).ok_or(err)?.parse().map_err(|_| err);
+}
+
+/// A scale in the Scala (.scl) format
+///
+/// The pitches are given in cents above the first note of the scale, the last pitch is the period (usually the octave).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScalaScale {
+    pub description: String,
+    pub pitches: Vec<f64>,
+}
+
+impl Default for ScalaScale {
+    fn default() -> Self {
+        return ScalaScale::equal_temperament(12, 1200.0);
+    }
+}
+
+impl FromStr for ScalaScale {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        //The description may be empty
+        let description = next_line(&mut lines).ok_or("Missing scale description!")?.to_string();
+        let count: usize = parse_value(next_line(&mut lines), "Invalid scale size!")?;
+        let mut pitches = Vec::with_capacity(count);
+        while pitches.len() < count {
+            let line = next_line(&mut lines).ok_or("Missing scale pitch!")?;
+            if line.is_empty() {
+                continue;
+            }
+            let value = line.split_whitespace().next().unwrap_or("");
+            let cents = if value.contains('.') {
+                value.parse::<f64>().map_err(|_| "Invalid scale pitch!")?
+            }
+            else {
+                let (num, den) = value.split_once('/').unwrap_or((value, "1"));
+                let num = num.parse::<f64>().map_err(|_| "Invalid scale ratio!")?;
+                let den = den.parse::<f64>().map_err(|_| "Invalid scale ratio!")?;
+                if num <= 0.0 || den <= 0.0 {
+                    return Err("Invalid scale ratio!");
+                }
+                1200.0 * (num/den).log2()
+            };
+            pitches.push(cents);
+        }
+        if pitches.is_empty() {
+            return Err("Empty scale!");
+        }
+        return Ok(ScalaScale {
+            description: description,
+            pitches: pitches,
+        });
+    }
+}
+
+impl ScalaScale {
+
+    /// Divides the period into equal steps
+    pub fn equal_temperament(steps: usize, period: f64) -> ScalaScale {
+        let steps = steps.max(1);
+        return ScalaScale {
+            description: format!("{} tone equal temperament", steps),
+            pitches: (1..=steps).map(|i| period * (i as f64)/(steps as f64)).collect(),
+        };
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ScalaScale, &'static str> {
+        return std::fs::read_to_string(path).map_err(|_| "Couldn't read scale file!")?.parse();
+    }
+
+    #[inline(always)]
+    pub fn size(&self) -> usize {
+        return self.pitches.len();
+    }
+
+    /// Returns the pitch of a scale degree in cents, degrees outside the scale are moved by whole periods
+    pub fn cents(&self, degree: i32) -> f64 {
+        let size = self.pitches.len() as i32;
+        if size == 0 {
+            return 0.0;
+        }
+        let period = self.pitches[self.pitches.len() - 1];
+        let step = degree.rem_euclid(size);
+        let base = if step == 0 { 0.0 } else { self.pitches[step as usize - 1] };
+        return degree.div_euclid(size) as f64 * period + base;
+    }
+
+}
+
+/// A keyboard mapping in the Scala (.kbm) format that assigns scale degrees to MIDI notes
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyboardMapping {
+    pub first_note: u8,
+    pub last_note: u8,
+    pub middle_note: u8,            //Note that plays the first degree of the scale
+    pub reference_note: u8,
+    pub reference_freq: f64,
+    pub octave_degree: usize,       //Degree that a repetition of the mapping moves, 0 uses the scale period
+    pub mapping: Vec<Option<i32>>,  //Scale degree of each key starting at the middle note, empty maps all keys linearly
+}
+
+impl Default for KeyboardMapping {
+    fn default() -> Self {
+        return KeyboardMapping::linear(60, 69, 440.0);
+    }
+}
+
+impl FromStr for KeyboardMapping {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().filter(|l| !l.trim().is_empty());
+        let size: usize = parse_value(next_line(&mut lines), "Invalid mapping size!")?;
+        let first_note: u8 = parse_value(next_line(&mut lines), "Invalid first note!")?;
+        let last_note: u8 = parse_value(next_line(&mut lines), "Invalid last note!")?;
+        let middle_note: u8 = parse_value(next_line(&mut lines), "Invalid middle note!")?;
+        let reference_note: u8 = parse_value(next_line(&mut lines), "Invalid reference note!")?;
+        let reference_freq: f64 = parse_value(next_line(&mut lines), "Invalid reference frequency!")?;
+        let octave_degree: usize = parse_value(next_line(&mut lines), "Invalid octave degree!")?;
+        let mut mapping = Vec::with_capacity(size);
+        for _ in 0..size {
+            //Missing entries at the end are unmapped
+            mapping.push(match next_line(&mut lines).and_then(|l| l.split_whitespace().next()) {
+                Some("x") | None => None,
+                Some(degree) => Some(degree.parse().map_err(|_| "Invalid mapping entry!")?),
+            });
+        }
+        if first_note > 127 || last_note > 127 || middle_note > 127 || reference_note > 127 || reference_freq <= 0.0 {
+            return Err("Invalid keyboard mapping!");
+        }
+        return Ok(KeyboardMapping {
+            first_note: first_note,
+            last_note: last_note,
+            middle_note: middle_note,
+            reference_note: reference_note,
+            reference_freq: reference_freq,
+            octave_degree: octave_degree,
+            mapping: mapping,
+        });
+    }
+}
+
+impl KeyboardMapping {
+
+    /// Maps consecutive keys to consecutive scale degrees
+    pub fn linear(middle_note: u8, reference_note: u8, reference_freq: f64) -> KeyboardMapping {
+        return KeyboardMapping {
+            first_note: 0,
+            last_note: 127,
+            middle_note: middle_note,
+            reference_note: reference_note,
+            reference_freq: reference_freq,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        };
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeyboardMapping, &'static str> {
+        return std::fs::read_to_string(path).map_err(|_| "Couldn't read keyboard mapping file!")?.parse();
+    }
+
+    /// Returns the pitch of a note in cents relative to the first degree of the scale, None if the key is unmapped
+    pub fn cents(&self, scale: &ScalaScale, note: u8) -> Option<f64> {
+        if note < self.first_note || note > self.last_note {
+            return None;
+        }
+        let offset = note as i32 - self.middle_note as i32;
+        if self.mapping.is_empty() {
+            return Some(scale.cents(offset));
+        }
+        let size = self.mapping.len() as i32;
+        let octave_degree = if self.octave_degree == 0 { scale.size() } else { self.octave_degree } as i32;
+        let degree = self.mapping[offset.rem_euclid(size) as usize]?;
+        return Some(offset.div_euclid(size) as f64 * scale.cents(octave_degree) + scale.cents(degree));
+    }
+
+}
+
+/// Frequencies of all MIDI notes, built from a scale and keyboard mapping and changed by MIDI Tuning Standard messages
+///
+/// Unmapped keys keep their equal tempered frequency.
+#[derive(Clone)]
+pub struct Tuning {
+    scale: ScalaScale,
+    mapping: KeyboardMapping,
+    freqs: [f64; MIDI_NOTES],
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        return Tuning::new(ScalaScale::default(), KeyboardMapping::default());
+    }
+}
+
+impl Tuning {
+
+    pub fn new(scale: ScalaScale, mapping: KeyboardMapping) -> Tuning {
+        let mut tuning = Tuning {
+            scale: scale,
+            mapping: mapping,
+            freqs: [0.0; MIDI_NOTES],
+        };
+        tuning.rebuild();
+        return tuning;
+    }
+
+    /// Loads a scale and an optional keyboard mapping, without a mapping the scale starts at middle C and A4 is 440 Hz
+    pub fn load<P: AsRef<Path>>(scale: P, mapping: Option<P>) -> Result<Tuning, &'static str> {
+        let scale = ScalaScale::load(scale)?;
+        let mapping = match mapping {
+            Some(path) => KeyboardMapping::load(path)?,
+            None => KeyboardMapping::default(),
+        };
+        return Ok(Tuning::new(scale, mapping));
+    }
+
+    #[inline(always)]
+    pub fn scale(&self) -> &ScalaScale {
+        return &self.scale;
+    }
+
+    #[inline(always)]
+    pub fn mapping(&self) -> &KeyboardMapping {
+        return &self.mapping;
+    }
+
+    /// Changes the note that is tuned to the reference frequency, this discards changes made by tuning messages
+    pub fn set_reference(&mut self, note: u8, freq: f64) {
+        self.mapping.reference_note = note.min(127);
+        self.mapping.reference_freq = freq.max(1.0);
+        self.rebuild();
+    }
+
+    /// Recalculates the frequencies from the scale and mapping
+    pub fn rebuild(&mut self) {
+        let reference = self.mapping.cents(&self.scale, self.mapping.reference_note)
+            .unwrap_or(100.0 * (self.mapping.reference_note as f64 - self.mapping.middle_note as f64));
+        for (note, freq) in self.freqs.iter_mut().enumerate() {
+            *freq = match self.mapping.cents(&self.scale, note as u8) {
+                Some(cents) => self.mapping.reference_freq * f64::from(2.0).powf((cents - reference)/1200.0),
+                None => self.mapping.reference_freq * f64::from(2.0).powf((note as f64 - self.mapping.reference_note as f64)/12.0),
+            };
+        }
+    }
+
+    /// Returns the frequency of a note, fractional notes (e.g. from pitch bends or glides) are interpolated exponentially
+    #[inline]
+    pub fn freq(&self, note: f64) -> f64 {
+        let index = note.floor();
+        if index < 0.0 {
+            return self.freqs[0] * f64::from(2.0).powf(note/12.0);
+        }
+        if index >= (MIDI_NOTES - 1) as f64 {
+            return self.freqs[MIDI_NOTES - 1] * f64::from(2.0).powf((note - (MIDI_NOTES - 1) as f64)/12.0);
+        }
+        let index = index as usize;
+        let low = self.freqs[index];
+        return low * (self.freqs[index + 1]/low).powf(note - index as f64);
+    }
+
+    /// Sets a note to a frequency given in the MTS format (semitone and 14 bit fraction of a semitone)
+    fn set_mts_note(&mut self, key: u8, data: (u8, u8, u8)) {
+        if data == MTS_NO_CHANGE || key as usize >= MIDI_NOTES {
+            return;
+        }
+        let (semitone, msb, lsb) = data;
+        let note = (semitone & 0x7F) as f64 + (((msb as u32 & 0x7F) << 7 | (lsb as u32 & 0x7F)) as f64)/16384.0;
+        self.freqs[key as usize] = MTS_REFERENCE_FREQ * f64::from(2.0).powf((note - 69.0)/12.0);
+    }
+
+    /// Applies a MIDI Tuning Standard message and returns wether the message was a supported tuning message
+    ///
+    /// Supported are the single note tuning change (real-time and non-real-time, with and without bank)
+    /// and the bulk tuning dump. The tuning program and device id are ignored.
+    pub fn apply_sysex(&mut self, data: &[u8]) -> bool {
+        let data = data.strip_prefix(&[0xF0]).unwrap_or(data);
+        let data = data.strip_suffix(&[0xF7]).unwrap_or(data);
+        //Universal real-time (7F) or non-real-time (7E), device id, MIDI tuning (08), sub id
+        if data.len() < 4 || (data[0] != 0x7F && data[0] != 0x7E) || data[2] != 0x08 {
+            return false;
+        }
+        let body = &data[4..];
+        return match data[3] {
+            //Bulk dump: program, 16 name bytes, 128 times 3 bytes, checksum
+            0x01 => {
+                if body.len() < 17 + MIDI_NOTES * 3 {
+                    return false;
+                }
+                for (key, entry) in body[17..17 + MIDI_NOTES * 3].chunks_exact(3).enumerate() {
+                    self.set_mts_note(key as u8, (entry[0], entry[1], entry[2]));
+                }
+                true
+            },
+            //Single note tuning change: program, count, count times key and 3 bytes
+            0x02 => self.apply_note_changes(body.get(1..)),
+            //Single note tuning change with bank: bank, program, count, ...
+            0x07 => self.apply_note_changes(body.get(2..)),
+            _ => false,
+        }
+    }
+
+    fn apply_note_changes(&mut self, data: Option<&[u8]>) -> bool {
+        let (count, changes) = match data.and_then(|d| d.split_first()) {
+            Some(d) => d,
+            None => return false,
+        };
+        for change in changes.chunks_exact(4).take(*count as usize) {
+            self.set_mts_note(change[0], (change[1], change[2], change[3]));
+        }
+        return true;
+    }
+
+}
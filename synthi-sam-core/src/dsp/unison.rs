@@ -0,0 +1,122 @@
+use std::fmt::Display;
+
+use crate::util::random::Random;
+
+use super::{oscillator::{Oscillator, OscilatorConfig}, note_to_freq_transpose, pan_equal_power};
+
+pub const MAX_UNISON_VOICES: usize = 16;
+
+/// Describes how the detune is distributed across the stacked voices
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DetuneCurve {
+    Linear,         //Voices are spaced evenly
+    Exponential,    //Voices gather around the center, only the outer voices are detuned far
+    Wide,           //Voices gather at the outside
+}
+
+impl Default for DetuneCurve {
+    fn default() -> Self {
+        return DetuneCurve::Linear;
+    }
+}
+
+impl Display for DetuneCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            DetuneCurve::Linear => "Linear",
+            DetuneCurve::Exponential => "Exponential",
+            DetuneCurve::Wide => "Wide",
+        })
+    }
+}
+
+impl DetuneCurve {
+    /// Maps a voice position from -1 to 1 to the detune factor from -1 to 1
+    fn apply(&self, position: f64) -> f64 {
+        return match self {
+            DetuneCurve::Linear => position,
+            DetuneCurve::Exponential => position * position.abs(),
+            DetuneCurve::Wide => position.signum() * position.abs().sqrt(),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct UnisonConfig {
+    pub voices: usize,              //Amount of stacked oscillators (1 to 16)
+    pub detune: f64,                //Detune of the outermost voices in semitones
+    pub detune_curve: DetuneCurve,
+    pub spread: f64,                //Stereo spread from 0 (mono) to 1 (full width)
+    pub blend: f64,                 //Mix between the center voice (0) and the side voices (1)
+    pub random_phase: bool,         //Start every voice at a random phase, otherwise all voices start at 0
+}
+
+impl Default for UnisonConfig {
+    fn default() -> Self {
+        return UnisonConfig {
+            voices: 1,
+            detune: 0.0,
+            detune_curve: DetuneCurve::Linear,
+            spread: 0.0,
+            blend: 0.5,
+            random_phase: false,
+        };
+    }
+}
+
+impl UnisonConfig {
+
+    #[inline(always)]
+    fn voice_count(&self) -> usize {
+        return self.voices.clamp(1, MAX_UNISON_VOICES);
+    }
+
+}
+
+/// A stack of detuned oscillators playing the same waveform
+#[derive(Default)]
+pub struct UnisonOscillator {
+    oscillators: [Oscillator; MAX_UNISON_VOICES],
+}
+
+impl UnisonOscillator {
+
+    /// Restarts all voices, should be called when a note starts
+    pub fn reset(&mut self, config: &UnisonConfig, random: &mut Random) {
+        for osc in self.oscillators.iter_mut() {
+            osc.reset(if config.random_phase { random.next_f64() } else { 0.0 });
+        }
+    }
+
+    /// Processes all voices and returns the left and right sample
+    pub fn process(&mut self, osc: OscilatorConfig, config: &UnisonConfig, time_step: f64) -> (f64, f64) {
+        let voices = config.voice_count();
+        //Single voice
+        if voices == 1 {
+            let sample = self.oscillators[0].process(osc, time_step);
+            return (sample, sample);
+        }
+
+        //Voices with the smallest distance to the center count as center voices (one for odd, two for even voice counts)
+        let center_voices = if voices.is_multiple_of(2) { 2 } else { 1 };
+        let side_voices = voices - center_voices;
+        let center_gain = if side_voices > 0 { 1.0 - config.blend } else { 1.0 } / (center_voices as f64).sqrt();
+        let side_gain = if side_voices > 0 { config.blend / (side_voices as f64).sqrt() } else { 0.0 };
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (i, oscillator) in self.oscillators[..voices].iter_mut().enumerate() {
+            let position = (i as f64) / ((voices - 1) as f64) * 2.0 - 1.0;
+            let detune = note_to_freq_transpose(config.detune * config.detune_curve.apply(position));
+            let sample = oscillator.process(OscilatorConfig { freq: osc.freq * detune, ..osc }, time_step);
+
+            let center = (i * 2 + 1).abs_diff(voices) <= 1;
+            let gain = if center { center_gain } else { side_gain };
+            let (l, r) = pan_equal_power(position * config.spread);
+            left += sample * gain * l;
+            right += sample * gain * r;
+        }
+        return (left, right);
+    }
+
+}
@@ -0,0 +1,188 @@
+use std::fmt::Display;
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{delay::DelayLine, oscillator::{Oscillator, WaveForm}, lfo::LfoRate}};
+
+pub const MAX_CHORUS_VOICES: usize = 8;
+const MAX_CHORUS_DELAY: f64 = 0.05;  //Seconds, including the depth
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChorusMode {
+    Standard,
+    JunoI,      //Slow sweep of a single delay, inverted on the right channel
+    JunoII,     //Faster sweep
+    JunoIAndII, //Both buttons pressed, a fast and shallow vibrato
+}
+
+impl Default for ChorusMode {
+    fn default() -> Self {
+        return ChorusMode::Standard;
+    }
+}
+
+impl Display for ChorusMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ChorusMode::Standard => "Standard",
+            ChorusMode::JunoI => "Juno I",
+            ChorusMode::JunoII => "Juno II",
+            ChorusMode::JunoIAndII => "Juno I + II",
+        })
+    }
+}
+
+impl ChorusMode {
+
+    /// Rate in Hz, center delay and depth in milliseconds of the Juno modes
+    fn juno_parameters(&self) -> Option<(f64, f64, f64)> {
+        return match self {
+            ChorusMode::Standard => None,
+            ChorusMode::JunoI => Some((0.513, 3.5, 1.85)),
+            ChorusMode::JunoII => Some((0.863, 3.5, 1.85)),
+            ChorusMode::JunoIAndII => Some((9.75, 3.3, 0.3)),
+        }
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChorusConfig {
+    pub mode: ChorusMode,
+    //The following parameters only apply to the standard mode
+    pub voices: usize,      //1 to 8
+    pub rate: LfoRate,
+    pub delay: f64,         //Milliseconds
+    pub depth: f64,         //Milliseconds
+    pub spread: f64,        //0 to 1, LFO phase offset of the right channel, 1 modulates both channels in opposite phase
+    pub mix: f64,           //0 is dry, 1 is wet only
+}
+
+impl Default for ChorusConfig {
+    fn default() -> Self {
+        return ChorusConfig {
+            mode: ChorusMode::Standard,
+            voices: 3,
+            rate: LfoRate::Free(0.8),
+            delay: 12.0,
+            depth: 3.0,
+            spread: 1.0,
+            mix: 0.5,
+        };
+    }
+}
+
+/// Chorus with up to eight modulated voices or a recreation of the Juno-60 chorus modes
+///
+/// Every channel has it's own delay line, the right one is swept by the opposite LFO phase.
+/// In the standard mode every voice has it's own LFO phase.
+/// The Juno modes use a single voice swept by a triangle LFO.
+pub struct Chorus {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: ChorusConfig,
+    pub bpm: f64,
+    sample_rate: f64,
+    time_step: f64,
+    line_l: DelayLine,
+    line_r: DelayLine,
+    lfo: Oscillator,
+}
+
+impl Chorus {
+
+    pub fn new() -> Chorus {
+        return Chorus {
+            info: DeviceInfo {
+                name: "Chorus",
+                type_identifier: "synthi_sam_chorus",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: ChorusConfig::default(),
+            bpm: 120.0,
+            sample_rate: 44100.0,
+            time_step: 1.0/44100.0,
+            line_l: DelayLine::default(),
+            line_r: DelayLine::default(),
+            lfo: Oscillator::default(),
+        };
+    }
+
+}
+
+impl Device for Chorus {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.sample_rate = info.sample_rate as f64;
+        self.time_step = info.time_step;
+        for line in [&mut self.line_l, &mut self.line_r] {
+            line.resize((MAX_CHORUS_DELAY * self.sample_rate).ceil() as usize + 1);
+        }
+        self.lfo.reset(0.0);
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let (in_l, in_r) = (channels[0], channels[1]);
+        self.line_l.push(in_l);
+        self.line_r.push(in_r);
+
+        let (wet_l, wet_r, mix) = match self.config.mode.juno_parameters() {
+            Some((rate, delay, depth)) => {
+                self.lfo.advance(rate, self.time_step);
+                let modulation = WaveForm::Triangle.synthesize(self.lfo.phase(), 0.5) * depth;
+                let left = self.line_l.read((delay + modulation) * 0.001 * self.sample_rate);
+                let right = self.line_r.read((delay - modulation) * 0.001 * self.sample_rate);
+                (left, right, 0.5)
+            },
+            None => {
+                self.lfo.advance(self.config.rate.freq(self.bpm), self.time_step);
+                let voices = self.config.voices.clamp(1, MAX_CHORUS_VOICES);
+                let depth = self.config.depth.max(0.0);
+                let delay = self.config.delay.max(depth).min(MAX_CHORUS_DELAY * 1000.0 - depth);
+                let gain = 1.0/(voices as f64).sqrt();
+                let offset = self.config.spread.clamp(0.0, 1.0) * 0.5;
+                let (mut left, mut right) = (0.0, 0.0);
+                for i in 0..voices {
+                    //The voices are spread evenly over the LFO cycle
+                    let phase = (self.lfo.phase() + i as f64/(voices as f64)).fract();
+                    let modulation_l = WaveForm::Sine.synthesize(phase, 0.5) * depth;
+                    let modulation_r = WaveForm::Sine.synthesize((phase + offset).fract(), 0.5) * depth;
+                    left += self.line_l.read((delay + modulation_l) * 0.001 * self.sample_rate) * gain;
+                    right += self.line_r.read((delay + modulation_r) * 0.001 * self.sample_rate) * gain;
+                }
+                (left, right, self.config.mix.clamp(0.0, 1.0))
+            },
+        };
+        self.output.port.take_input(&[in_l * (1.0 - mix) + wet_l * mix, in_r * (1.0 - mix) + wet_r * mix]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
@@ -0,0 +1,138 @@
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::dynamics::{DetectionMode, LevelDetector, EnvelopeFollower, compress_level, db_to_gain}};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CompressorConfig {
+    pub detection: DetectionMode,
+    pub threshold: f64,     //dB
+    pub ratio: f64,         //1 or higher
+    pub knee: f64,          //dB, width of the soft knee, 0 is a hard knee
+    pub attack: f64,        //Milliseconds
+    pub release: f64,       //Milliseconds
+    pub makeup: f64,        //dB
+    pub sidechain: bool,    //The level is measured on the sidechain input instead of the main input
+    pub mix: f64,           //0 is dry, 1 is compressed only, in between is parallel compression
+}
+
+impl Default for CompressorConfig {
+    fn default() -> Self {
+        return CompressorConfig {
+            detection: DetectionMode::Rms,
+            threshold: -18.0,
+            ratio: 4.0,
+            knee: 6.0,
+            attack: 10.0,
+            release: 150.0,
+            makeup: 0.0,
+            sidechain: false,
+            mix: 1.0,
+        };
+    }
+}
+
+/// Feed-forward stereo compressor with linked channels
+///
+/// The gain reduction is calculated in dB and smoothed with the attack and release times afterwards.
+pub struct Compressor {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    sidechain: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: CompressorConfig,
+    time_step: f64,
+    times: (f64, f64),  //Attack and release the follower was set up for
+    detector: LevelDetector,
+    reduction: EnvelopeFollower,
+}
+
+impl Compressor {
+
+    pub fn new() -> Compressor {
+        return Compressor {
+            info: DeviceInfo {
+                name: "Compressor",
+                type_identifier: "synthi_sam_compressor",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            sidechain: NamedAudioPort::new("Sidechain In", "sidechain_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: CompressorConfig::default(),
+            time_step: 1.0/44100.0,
+            times: (-1.0, -1.0),
+            detector: LevelDetector::default(),
+            reduction: EnvelopeFollower::default(),
+        };
+    }
+
+    /// Current gain reduction in dB, for metering
+    #[inline]
+    pub fn gain_reduction(&self) -> f64 {
+        return self.reduction.value();
+    }
+
+}
+
+impl Device for Compressor {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.sidechain.port.reset();
+        self.output.port.reset();
+        self.time_step = info.time_step;
+        self.times = (-1.0, -1.0);
+        self.detector.setup(info.time_step);
+        self.reduction.reset(0.0);
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let (in_l, in_r) = (channels[0], channels[1]);
+        let times = (self.config.attack.max(0.0), self.config.release.max(0.0));
+        if times != self.times {
+            self.times = times;
+            self.reduction.set_times(times.0 * 0.001, times.1 * 0.001, self.time_step);
+        }
+
+        let key = if self.config.sidechain {
+            let sidechain = self.sidechain.port.channels();
+            sidechain[0].abs().max(sidechain[1].abs())
+        }
+        else {
+            in_l.abs().max(in_r.abs())
+        };
+        let level = self.detector.process(self.config.detection, key);
+        let compressed = compress_level(level, self.config.threshold, self.config.ratio.max(1.0), self.config.knee.max(0.0));
+        let reduction = self.reduction.process(level - compressed);
+
+        let gain = db_to_gain(self.config.makeup - reduction);
+        let mix = self.config.mix.clamp(0.0, 1.0);
+        self.output.port.take_input(&[in_l * (1.0 - mix) + in_l * gain * mix, in_r * (1.0 - mix) + in_r * gain * mix]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            1 => Some(&mut self.sidechain),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
@@ -0,0 +1,220 @@
+use std::fmt::Display;
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{delay::DelayLine, smooth::Smoother, lfo::{Lfo, LfoConfig, LfoShape, LfoRate, LfoMode}, filter::{StateVariableFilter, SVFilterConfig, SVFilterType}}, util::tempo::NoteDivision};
+
+/// Longest possible delay in seconds
+pub const MAX_DELAY_TIME: f64 = 4.0;
+/// Longest modulation depth in seconds
+const MAX_MOD_DEPTH: f64 = 0.02;
+/// Time of the delay time smoothing in seconds, a change sounds like a tape speeding up or slowing down
+const TIME_SMOOTHING: f64 = 0.1;
+const MAX_FEEDBACK: f64 = 0.99;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DelayTime {
+    Millis(f64),
+    Synced(NoteDivision),
+}
+
+impl Default for DelayTime {
+    fn default() -> Self {
+        return DelayTime::Synced(NoteDivision::dotted(1, 8));
+    }
+}
+
+impl Display for DelayTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            DelayTime::Millis(ms) => write!(f, "{} ms", ms),
+            DelayTime::Synced(division) => write!(f, "{}", division),
+        }
+    }
+}
+
+impl DelayTime {
+
+    /// Returns the delay in seconds at the given tempo
+    #[inline]
+    pub fn seconds(&self, bpm: f64) -> f64 {
+        return match self {
+            DelayTime::Millis(ms) => ms * 0.001,
+            DelayTime::Synced(division) => division.duration(bpm),
+        }.clamp(0.0, MAX_DELAY_TIME);
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DelayConfig {
+    pub left: DelayTime,
+    pub right: DelayTime,
+    pub feedback: f64,      //0 to 0.99
+    pub ping_pong: bool,    //The input is sent to the left line only and the lines feed into each other
+    pub low_cut: f64,       //Hz, high pass in the feedback loop
+    pub high_cut: f64,      //Hz, low pass in the feedback loop
+    pub mod_rate: f64,      //Hz
+    pub mod_depth: f64,     //Milliseconds
+    pub mix: f64,           //0 is dry, 1 is wet only
+}
+
+impl Default for DelayConfig {
+    fn default() -> Self {
+        return DelayConfig {
+            left: DelayTime::Synced(NoteDivision::dotted(1, 8)),
+            right: DelayTime::Synced(NoteDivision::new(1, 4)),
+            feedback: 0.4,
+            ping_pong: false,
+            low_cut: 80.0,
+            high_cut: 6000.0,
+            mod_rate: 0.3,
+            mod_depth: 0.0,
+            mix: 0.3,
+        };
+    }
+}
+
+/// One side of the delay
+#[derive(Default)]
+struct DelayChannel {
+    line: DelayLine,
+    time: Smoother,
+    lfo: Lfo,
+    low_cut: StateVariableFilter,
+    high_cut: StateVariableFilter,
+    feedback: f64,
+}
+
+impl DelayChannel {
+
+    /// Reads the delayed signal and filters it for the feedback path
+    #[inline]
+    fn read(&mut self, lfo: &LfoConfig, depth: f64, low_cut: &SVFilterConfig, high_cut: &SVFilterConfig, sample_rate: f64, time_step: f64) -> f64 {
+        let modulation = self.lfo.process(lfo, 0.0, time_step) * depth;
+        //The line is read before the current sample is pushed
+        let delayed = self.line.read((self.time.process() + modulation) * sample_rate - 1.0);
+        let filtered = self.low_cut.process(low_cut, delayed, time_step);
+        self.feedback = self.high_cut.process(high_cut, filtered, time_step);
+        return delayed;
+    }
+
+}
+
+/// Stereo delay with tempo sync, filtered feedback, ping-pong and modulated delay lines
+///
+/// Delay time changes are smoothed, so they never click but shortly bend the pitch of the echoes.
+pub struct StereoDelay {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: DelayConfig,
+    pub bpm: f64,
+    sample_rate: f64,
+    time_step: f64,
+    left: DelayChannel,
+    right: DelayChannel,
+}
+
+impl StereoDelay {
+
+    pub fn new() -> StereoDelay {
+        return StereoDelay {
+            info: DeviceInfo {
+                name: "Stereo Delay",
+                type_identifier: "synthi_sam_stereo_delay",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: DelayConfig::default(),
+            bpm: 120.0,
+            sample_rate: 44100.0,
+            time_step: 1.0/44100.0,
+            left: DelayChannel::default(),
+            right: DelayChannel::default(),
+        };
+    }
+
+}
+
+impl Device for StereoDelay {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.sample_rate = info.sample_rate as f64;
+        self.time_step = info.time_step;
+        let max_delay = ((MAX_DELAY_TIME + MAX_MOD_DEPTH) * self.sample_rate).ceil() as usize + 1;
+        let left = self.config.left.seconds(self.bpm);
+        let right = self.config.right.seconds(self.bpm);
+        for (channel, time, phase) in [(&mut self.left, left, 0.0), (&mut self.right, right, 0.25)] {
+            channel.line.resize(max_delay);
+            channel.time.set_time(TIME_SMOOTHING, info.time_step);
+            channel.time.reset(time);
+            channel.low_cut.reset();
+            channel.high_cut.reset();
+            channel.feedback = 0.0;
+            //The right LFO is a quarter cycle ahead for a wider stereo image
+            channel.lfo.trigger(&LfoConfig { mode: LfoMode::Retrigger, phase: phase, ..Default::default() });
+        }
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let (in_l, in_r) = (channels[0], channels[1]);
+
+        let lfo = LfoConfig {
+            shape: LfoShape::Sine,
+            rate: LfoRate::Free(self.config.mod_rate),
+            mode: LfoMode::Free,
+            ..Default::default()
+        };
+        let depth = (self.config.mod_depth * 0.001).clamp(0.0, MAX_MOD_DEPTH);
+        let low_cut = SVFilterConfig { filter_type: SVFilterType::HighPass, cutoff: self.config.low_cut, ..Default::default() };
+        let high_cut = SVFilterConfig { filter_type: SVFilterType::LowPass, cutoff: self.config.high_cut, ..Default::default() };
+        //Keep the modulation from reading before the write position
+        self.left.time.set(self.config.left.seconds(self.bpm).max(depth));
+        self.right.time.set(self.config.right.seconds(self.bpm).max(depth));
+
+        let wet_l = self.left.read(&lfo, depth, &low_cut, &high_cut, self.sample_rate, self.time_step);
+        let wet_r = self.right.read(&lfo, depth, &low_cut, &high_cut, self.sample_rate, self.time_step);
+
+        let feedback = self.config.feedback.clamp(0.0, MAX_FEEDBACK);
+        if self.config.ping_pong {
+            self.left.line.push((in_l + in_r) * 0.5 + self.right.feedback * feedback);
+            self.right.line.push(self.left.feedback * feedback);
+        }
+        else {
+            self.left.line.push(in_l + self.left.feedback * feedback);
+            self.right.line.push(in_r + self.right.feedback * feedback);
+        }
+
+        let mix = self.config.mix.clamp(0.0, 1.0);
+        self.output.port.take_input(&[in_l * (1.0 - mix) + wet_l * mix, in_r * (1.0 - mix) + wet_r * mix]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
@@ -0,0 +1,250 @@
+use std::{f64::consts::PI, fmt::Display};
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{filter::{StateVariableFilter, SVFilterConfig, SVFilterType}, oversampling::{Oversampler, OversamplingFactor}}};
+
+/// Operating point of the tube curve, shifts the curve so both half waves clip differently
+const TUBE_BIAS: f64 = 0.35;
+/// Cutoff of the DC blocker behind the shaper in Hz
+const DC_CUTOFF: f64 = 10.0;
+const MAX_DRIVE: f64 = 60.0;    //dB
+const MAX_BITS: f64 = 24.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DistortionType {
+    SoftClip,
+    HardClip,
+    Tube,
+    Foldback,
+    Bitcrush,
+}
+
+impl Default for DistortionType {
+    fn default() -> Self {
+        return DistortionType::SoftClip;
+    }
+}
+
+impl Display for DistortionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            DistortionType::SoftClip => "Soft Clip",
+            DistortionType::HardClip => "Hard Clip",
+            DistortionType::Tube => "Tube",
+            DistortionType::Foldback => "Foldback",
+            DistortionType::Bitcrush => "Bitcrush",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DistortionConfig {
+    pub distortion_type: DistortionType,
+    pub drive: f64,                             //dB, gain before the shaper
+    pub bits: f64,                              //1 to 24, only used by bitcrush
+    pub crush_rate: f64,                        //Hz, rate of the sample and hold, only used by bitcrush
+    pub pre_filter: Option<SVFilterConfig>,     //Tone filter before the shaper
+    pub post_filter: Option<SVFilterConfig>,    //Tone filter behind the shaper
+    pub oversampling: OversamplingFactor,
+    pub output: f64,                            //dB
+    pub mix: f64,                               //0 is dry, 1 is wet only
+}
+
+impl Default for DistortionConfig {
+    fn default() -> Self {
+        return DistortionConfig {
+            distortion_type: DistortionType::SoftClip,
+            drive: 12.0,
+            bits: 8.0,
+            crush_rate: 11025.0,
+            pre_filter: Some(SVFilterConfig {
+                filter_type: SVFilterType::HighPass,
+                cutoff: 60.0,
+                q: std::f64::consts::FRAC_1_SQRT_2,
+            }),
+            post_filter: Some(SVFilterConfig {
+                filter_type: SVFilterType::LowPass,
+                cutoff: 8000.0,
+                q: std::f64::consts::FRAC_1_SQRT_2,
+            }),
+            oversampling: OversamplingFactor::X4,
+            output: -6.0,
+            mix: 1.0,
+        };
+    }
+}
+
+/// State of the sample and hold of the bitcrusher
+#[derive(Default)]
+struct Crusher {
+    phase: f64,
+    value: f64,
+}
+
+impl Crusher {
+
+    #[inline]
+    fn process(&mut self, sample: f64, bits: f64, rate: f64, time_step: f64) -> f64 {
+        self.phase += rate * time_step;
+        if self.phase >= 1.0 {
+            self.phase = self.phase.fract();
+            let steps = f64::from(2.0).powf(bits.clamp(1.0, MAX_BITS) - 1.0);
+            self.value = (sample.clamp(-1.0, 1.0) * steps).round()/steps;
+        }
+        return self.value;
+    }
+
+}
+
+#[inline]
+fn shape(distortion_type: DistortionType, sample: f64) -> f64 {
+    return match distortion_type {
+        DistortionType::SoftClip => sample.tanh(),
+        DistortionType::HardClip => sample.clamp(-1.0, 1.0),
+        //The bias makes the positive half clip earlier than the negative one, which adds even harmonics
+        DistortionType::Tube => (sample + TUBE_BIAS).tanh() - TUBE_BIAS.tanh(),
+        DistortionType::Foldback => {
+            //Triangle wave of the input, everything above 1 is mirrored back
+            let t = (sample + 1.0).rem_euclid(4.0);
+            if t < 2.0 { t - 1.0 } else { 3.0 - t }
+        },
+        DistortionType::Bitcrush => sample,
+    }
+}
+
+#[derive(Default)]
+struct DistortionChannel {
+    pre_filter: StateVariableFilter,
+    post_filter: StateVariableFilter,
+    oversampler: Oversampler,
+    crusher: Crusher,
+    dc_in: f64,
+    dc_out: f64,
+}
+
+impl DistortionChannel {
+
+    fn reset(&mut self, factor: OversamplingFactor) {
+        self.pre_filter.reset();
+        self.post_filter.reset();
+        self.oversampler.set_factor(factor);
+        self.oversampler.reset();
+        self.crusher = Crusher::default();
+        self.dc_in = 0.0;
+        self.dc_out = 0.0;
+    }
+
+    #[inline]
+    fn process(&mut self, config: &DistortionConfig, sample: f64, time_step: f64, dc_coeff: f64) -> f64 {
+        let mut value = sample;
+        if let Some(filter) = &config.pre_filter {
+            value = self.pre_filter.process(filter, value, time_step);
+        }
+        let drive = f64::from(10.0).powf(config.drive.clamp(0.0, MAX_DRIVE)/20.0);
+
+        self.oversampler.set_factor(config.oversampling);
+        let os_time_step = time_step/(config.oversampling.factor() as f64);
+        let crusher = &mut self.crusher;
+        value = self.oversampler.process(value * drive, |s| {
+            return match config.distortion_type {
+                DistortionType::Bitcrush => crusher.process(s, config.bits, config.crush_rate, os_time_step),
+                t => shape(t, s),
+            };
+        });
+
+        //The tube curve and the bitcrusher can leave an offset
+        let blocked = value - self.dc_in + dc_coeff * self.dc_out;
+        self.dc_in = value;
+        self.dc_out = blocked;
+        value = blocked;
+
+        if let Some(filter) = &config.post_filter {
+            value = self.post_filter.process(filter, value, time_step);
+        }
+        return value;
+    }
+
+}
+
+/// Waveshaping distortion with tone filters before and behind the shaper
+///
+/// The shaper runs at up to eight times the sample rate so the harmonics it creates don't alias.
+/// The filters run at the normal rate.
+pub struct Distortion {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: DistortionConfig,
+    time_step: f64,
+    dc_coeff: f64,
+    left: DistortionChannel,
+    right: DistortionChannel,
+}
+
+impl Distortion {
+
+    pub fn new() -> Distortion {
+        return Distortion {
+            info: DeviceInfo {
+                name: "Distortion",
+                type_identifier: "synthi_sam_distortion",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: DistortionConfig::default(),
+            time_step: 1.0/44100.0,
+            dc_coeff: 0.0,
+            left: DistortionChannel::default(),
+            right: DistortionChannel::default(),
+        };
+    }
+
+}
+
+impl Device for Distortion {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.time_step = info.time_step;
+        self.dc_coeff = 1.0 - 2.0 * PI * DC_CUTOFF * info.time_step;
+        self.left.reset(self.config.oversampling);
+        self.right.reset(self.config.oversampling);
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let (in_l, in_r) = (channels[0], channels[1]);
+        let wet_l = self.left.process(&self.config, in_l, self.time_step, self.dc_coeff);
+        let wet_r = self.right.process(&self.config, in_r, self.time_step, self.dc_coeff);
+        let gain = f64::from(10.0).powf(self.config.output/20.0);
+        let mix = self.config.mix.clamp(0.0, 1.0);
+        self.output.port.take_input(&[in_l * (1.0 - mix) + wet_l * gain * mix, in_r * (1.0 - mix) + wet_r * gain * mix]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
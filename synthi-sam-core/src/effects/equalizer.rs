@@ -0,0 +1,304 @@
+use std::{f64::consts::PI, fmt::Display};
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{filter::{BiquadConfig, BiquadCoefficients, BiquadFilter, BiquadType}, dynamics::{db_to_gain, gain_to_db}}};
+
+pub const EQ_BANDS: usize = 8;
+/// A 48 dB/oct cut needs four second order sections
+const MAX_SECTIONS: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EqBandType {
+    LowCut,
+    LowShelf,
+    Peak,
+    Notch,
+    HighShelf,
+    HighCut,
+}
+
+impl Default for EqBandType {
+    fn default() -> Self {
+        return EqBandType::Peak;
+    }
+}
+
+impl Display for EqBandType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            EqBandType::LowCut => "Low Cut",
+            EqBandType::LowShelf => "Low Shelf",
+            EqBandType::Peak => "Peak",
+            EqBandType::Notch => "Notch",
+            EqBandType::HighShelf => "High Shelf",
+            EqBandType::HighCut => "High Cut",
+        })
+    }
+}
+
+/// Steepness of the cut filters
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CutSlope {
+    Db6,
+    Db12,
+    Db24,
+    Db36,
+    Db48,
+}
+
+impl Default for CutSlope {
+    fn default() -> Self {
+        return CutSlope::Db12;
+    }
+}
+
+impl Display for CutSlope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} dB/oct", self.order() * 6)
+    }
+}
+
+impl CutSlope {
+
+    /// Order of the butterworth filter
+    #[inline]
+    pub fn order(&self) -> usize {
+        return match self {
+            CutSlope::Db6 => 1,
+            CutSlope::Db12 => 2,
+            CutSlope::Db24 => 4,
+            CutSlope::Db36 => 6,
+            CutSlope::Db48 => 8,
+        }
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EqBand {
+    pub enabled: bool,
+    pub band_type: EqBandType,
+    pub freq: f64,          //Hz
+    pub gain: f64,          //dB, only used by peaks and shelves
+    pub q: f64,             //Width of peaks and notches, resonance of 12 dB cuts and shelves
+    pub slope: CutSlope,    //Only used by cuts
+}
+
+impl Default for EqBand {
+    fn default() -> Self {
+        return EqBand::new(EqBandType::Peak, 1000.0);
+    }
+}
+
+impl EqBand {
+
+    /// Creates a disabled band with neutral gain
+    pub fn new(band_type: EqBandType, freq: f64) -> EqBand {
+        return EqBand {
+            enabled: false,
+            band_type: band_type,
+            freq: freq,
+            gain: 0.0,
+            q: std::f64::consts::FRAC_1_SQRT_2,
+            slope: CutSlope::Db12,
+        };
+    }
+
+    /// Calculates the biquad sections of the band and returns how many of them are used
+    pub fn coefficients(&self, time_step: f64) -> ([BiquadCoefficients; MAX_SECTIONS], usize) {
+        let mut sections = [BiquadCoefficients::identity(); MAX_SECTIONS];
+        let biquad = |filter_type: BiquadType, q: f64| BiquadCoefficients::new(&BiquadConfig {
+            filter_type: filter_type,
+            freq: self.freq,
+            q: q,
+            gain: self.gain,
+        }, time_step);
+
+        let count = match self.band_type {
+            EqBandType::LowShelf => { sections[0] = biquad(BiquadType::LowShelf, self.q); 1 },
+            EqBandType::Peak => { sections[0] = biquad(BiquadType::Peak, self.q); 1 },
+            EqBandType::Notch => { sections[0] = biquad(BiquadType::Notch, self.q); 1 },
+            EqBandType::HighShelf => { sections[0] = biquad(BiquadType::HighShelf, self.q); 1 },
+            EqBandType::LowCut | EqBandType::HighCut => {
+                let high_pass = self.band_type == EqBandType::LowCut;
+                let order = self.slope.order();
+                if order == 1 {
+                    sections[0] = Self::first_order(high_pass, self.freq, time_step);
+                    1
+                }
+                else if order == 2 {
+                    //A single section can be made resonant
+                    sections[0] = biquad(if high_pass { BiquadType::HighPass } else { BiquadType::LowPass }, self.q);
+                    1
+                }
+                else {
+                    //Cascaded sections with the pole angles of a butterworth filter
+                    let count = order/2;
+                    for (k, section) in sections[..count].iter_mut().enumerate() {
+                        let q = 1.0/(2.0 * ((2 * k + 1) as f64 * PI/(2.0 * order as f64)).cos());
+                        *section = biquad(if high_pass { BiquadType::HighPass } else { BiquadType::LowPass }, q);
+                    }
+                    count
+                }
+            },
+        };
+        return (sections, count);
+    }
+
+    /// First order butterworth filter using the bilinear transform
+    fn first_order(high_pass: bool, freq: f64, time_step: f64) -> BiquadCoefficients {
+        let t = (PI * freq.clamp(5.0, 0.49/time_step) * time_step).tan();
+        let a1 = (t - 1.0)/(t + 1.0);
+        let (b0, b1) = if high_pass { (1.0/(1.0 + t), -1.0/(1.0 + t)) } else { (t/(1.0 + t), t/(1.0 + t)) };
+        return BiquadCoefficients { b0: b0, b1: b1, b2: 0.0, a1: a1, a2: 0.0 };
+    }
+
+    /// Returns the gain of the band at the given frequency in dB, 0 if the band is disabled
+    pub fn magnitude(&self, freq: f64, time_step: f64) -> f64 {
+        if !self.enabled {
+            return 0.0;
+        }
+        let (sections, count) = self.coefficients(time_step);
+        return sections[..count].iter().map(|s| gain_to_db(s.magnitude(freq, time_step))).sum();
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EqualizerConfig {
+    pub bands: [EqBand; EQ_BANDS],
+    pub output: f64,    //dB
+}
+
+impl Default for EqualizerConfig {
+    fn default() -> Self {
+        return EqualizerConfig {
+            bands: [
+                EqBand::new(EqBandType::LowCut, 30.0),
+                EqBand::new(EqBandType::LowShelf, 100.0),
+                EqBand::new(EqBandType::Peak, 250.0),
+                EqBand::new(EqBandType::Peak, 800.0),
+                EqBand::new(EqBandType::Peak, 2500.0),
+                EqBand::new(EqBandType::Peak, 6000.0),
+                EqBand::new(EqBandType::HighShelf, 10000.0),
+                EqBand::new(EqBandType::HighCut, 18000.0),
+            ],
+            output: 0.0,
+        };
+    }
+}
+
+impl EqualizerConfig {
+
+    /// Returns the gain of the whole EQ at the given frequency in dB
+    pub fn magnitude(&self, freq: f64, time_step: f64) -> f64 {
+        return self.bands.iter().map(|b| b.magnitude(freq, time_step)).sum::<f64>() + self.output;
+    }
+
+}
+
+/// State of one band, the coefficients are only recalculated when the band changes
+#[derive(Default)]
+struct EqBandState {
+    band: Option<EqBand>,
+    sections: [BiquadCoefficients; MAX_SECTIONS],
+    count: usize,
+    filters: [[BiquadFilter; MAX_SECTIONS]; 2],
+}
+
+/// Parametric EQ with eight bands of biquad filters
+pub struct Equalizer {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: EqualizerConfig,
+    time_step: f64,
+    bands: [EqBandState; EQ_BANDS],
+}
+
+impl Equalizer {
+
+    pub fn new() -> Equalizer {
+        return Equalizer {
+            info: DeviceInfo {
+                name: "Equalizer",
+                type_identifier: "synthi_sam_equalizer",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: EqualizerConfig::default(),
+            time_step: 1.0/44100.0,
+            bands: Default::default(),
+        };
+    }
+
+    /// Returns the gain of the current settings at the given frequency in dB, used to draw the EQ curve
+    pub fn magnitude(&self, freq: f64) -> f64 {
+        return self.config.magnitude(freq, self.time_step);
+    }
+
+}
+
+impl Device for Equalizer {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.time_step = info.time_step;
+        for state in self.bands.iter_mut() {
+            *state = EqBandState::default();
+        }
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let mut samples = [channels[0], channels[1]];
+        for (state, band) in self.bands.iter_mut().zip(self.config.bands.iter()) {
+            if state.band != Some(*band) {
+                //Filters that were disabled or changed their structure start without old state
+                if state.band.map(|b| (b.enabled, b.band_type, b.slope)) != Some((true, band.band_type, band.slope)) {
+                    for filter in state.filters.iter_mut().flatten() {
+                        filter.reset();
+                    }
+                }
+                (state.sections, state.count) = band.coefficients(self.time_step);
+                state.band = Some(*band);
+            }
+            if band.enabled {
+                for (sample, filters) in samples.iter_mut().zip(state.filters.iter_mut()) {
+                    for (filter, coeffs) in filters.iter_mut().zip(state.sections[..state.count].iter()) {
+                        *sample = filter.process(coeffs, *sample);
+                    }
+                }
+            }
+        }
+        let gain = db_to_gain(self.config.output);
+        self.output.port.take_input(&[samples[0] * gain, samples[1] * gain]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
@@ -0,0 +1,151 @@
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{delay::DelayLine, oscillator::{Oscillator, WaveForm}, lfo::LfoRate}};
+
+const MAX_FLANGER_DELAY: f64 = 0.025;   //Seconds, including the depth
+const MAX_FEEDBACK: f64 = 0.95;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FlangerConfig {
+    pub rate: LfoRate,
+    pub delay: f64,         //Milliseconds, shortest delay of the sweep or center delay in through-zero mode
+    pub depth: f64,         //Milliseconds
+    pub feedback: f64,      //-0.95 to 0.95
+    pub stereo_phase: f64,  //LFO offset of the right channel (0 to 1)
+    pub through_zero: bool, //The dry signal is delayed as well, so the sweep can cross it
+    pub mix: f64,           //0 is dry, 1 is wet only, 0.5 gives the deepest notches
+}
+
+impl Default for FlangerConfig {
+    fn default() -> Self {
+        return FlangerConfig {
+            rate: LfoRate::Free(0.2),
+            delay: 1.0,
+            depth: 3.0,
+            feedback: 0.5,
+            stereo_phase: 0.25,
+            through_zero: false,
+            mix: 0.5,
+        };
+    }
+}
+
+#[derive(Default)]
+struct FlangerChannel {
+    line: DelayLine,
+    feedback: f64,
+}
+
+impl FlangerChannel {
+
+    #[inline]
+    fn process(&mut self, config: &FlangerConfig, sample: f64, lfo: f64, sample_rate: f64) -> (f64, f64) {
+        let feedback = config.feedback.clamp(-MAX_FEEDBACK, MAX_FEEDBACK);
+        let max = MAX_FLANGER_DELAY * 1000.0;
+        self.line.push(sample + self.feedback * feedback);
+        let (dry, wet) = if config.through_zero {
+            //The wet delay moves around the dry delay
+            let center = config.delay.max(config.depth).clamp(0.0, max * 0.5);
+            let wet = center + lfo * config.depth.clamp(0.0, center);
+            (self.line.read(center * 0.001 * sample_rate), self.line.read(wet * 0.001 * sample_rate))
+        }
+        else {
+            let depth = config.depth.clamp(0.0, max);
+            let delay = config.delay.clamp(0.0, max - depth) + (lfo + 1.0) * 0.5 * depth;
+            (sample, self.line.read(delay * 0.001 * sample_rate))
+        };
+        self.feedback = wet;
+        return (dry, wet);
+    }
+
+}
+
+/// Flanger with feedback, stereo LFO offset and an optional through-zero mode
+pub struct Flanger {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: FlangerConfig,
+    pub bpm: f64,
+    sample_rate: f64,
+    time_step: f64,
+    lfo: Oscillator,
+    left: FlangerChannel,
+    right: FlangerChannel,
+}
+
+impl Flanger {
+
+    pub fn new() -> Flanger {
+        return Flanger {
+            info: DeviceInfo {
+                name: "Flanger",
+                type_identifier: "synthi_sam_flanger",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: FlangerConfig::default(),
+            bpm: 120.0,
+            sample_rate: 44100.0,
+            time_step: 1.0/44100.0,
+            lfo: Oscillator::default(),
+            left: FlangerChannel::default(),
+            right: FlangerChannel::default(),
+        };
+    }
+
+}
+
+impl Device for Flanger {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.sample_rate = info.sample_rate as f64;
+        self.time_step = info.time_step;
+        self.lfo.reset(0.0);
+        for channel in [&mut self.left, &mut self.right] {
+            channel.line.resize((MAX_FLANGER_DELAY * self.sample_rate).ceil() as usize + 1);
+            channel.feedback = 0.0;
+        }
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let (in_l, in_r) = (channels[0], channels[1]);
+        //Triangle sweeps sound more even than sines
+        self.lfo.advance(self.config.rate.freq(self.bpm), self.time_step);
+        let lfo_l = WaveForm::Triangle.synthesize(self.lfo.phase(), 0.5);
+        let lfo_r = WaveForm::Triangle.synthesize((self.lfo.phase() + self.config.stereo_phase).rem_euclid(1.0), 0.5);
+
+        let (dry_l, wet_l) = self.left.process(&self.config, in_l, lfo_l, self.sample_rate);
+        let (dry_r, wet_r) = self.right.process(&self.config, in_r, lfo_r, self.sample_rate);
+        let mix = self.config.mix.clamp(0.0, 1.0);
+        self.output.port.take_input(&[dry_l * (1.0 - mix) + wet_l * mix, dry_r * (1.0 - mix) + wet_r * mix]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
@@ -0,0 +1,135 @@
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::dynamics::{EnvelopeFollower, db_to_gain, gain_to_db}};
+
+/// Release of the level detector in seconds, keeps the gate from chattering on low frequencies
+const DETECTOR_RELEASE: f64 = 0.01;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GateConfig {
+    pub threshold: f64,     //dB, the gate opens above this level
+    pub hysteresis: f64,    //dB, the gate closes when the level falls this far below the threshold
+    pub range: f64,         //dB, attenuation of the closed gate
+    pub attack: f64,        //Milliseconds
+    pub hold: f64,          //Milliseconds the gate stays open after the level fell below the threshold
+    pub release: f64,       //Milliseconds
+}
+
+impl Default for GateConfig {
+    fn default() -> Self {
+        return GateConfig {
+            threshold: -50.0,
+            hysteresis: 6.0,
+            range: -80.0,
+            attack: 0.5,
+            hold: 20.0,
+            release: 100.0,
+        };
+    }
+}
+
+/// Noise gate with hysteresis and hold time
+pub struct Gate {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: GateConfig,
+    time_step: f64,
+    times: (f64, f64),  //Attack and release the follower was set up for
+    detector: EnvelopeFollower,
+    gain: EnvelopeFollower,
+    open: bool,
+    hold: f64,          //Seconds left until the gate closes
+}
+
+impl Gate {
+
+    pub fn new() -> Gate {
+        return Gate {
+            info: DeviceInfo {
+                name: "Gate",
+                type_identifier: "synthi_sam_gate",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: GateConfig::default(),
+            time_step: 1.0/44100.0,
+            times: (-1.0, -1.0),
+            detector: EnvelopeFollower::default(),
+            gain: EnvelopeFollower::default(),
+            open: false,
+            hold: 0.0,
+        };
+    }
+
+    #[inline(always)]
+    pub fn is_open(&self) -> bool {
+        return self.open;
+    }
+
+}
+
+impl Device for Gate {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.time_step = info.time_step;
+        self.times = (-1.0, -1.0);
+        self.detector.set_times(0.0, DETECTOR_RELEASE, info.time_step);
+        self.detector.reset(0.0);
+        self.gain.reset(db_to_gain(self.config.range.min(0.0)));
+        self.open = false;
+        self.hold = 0.0;
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let (in_l, in_r) = (channels[0], channels[1]);
+        let times = (self.config.attack.max(0.0), self.config.release.max(0.0));
+        if times != self.times {
+            self.times = times;
+            self.gain.set_times(times.0 * 0.001, times.1 * 0.001, self.time_step);
+        }
+
+        let level = gain_to_db(self.detector.process(in_l.abs().max(in_r.abs())));
+        if level > self.config.threshold {
+            self.open = true;
+            self.hold = self.config.hold.max(0.0) * 0.001;
+        }
+        else if self.open && level < self.config.threshold - self.config.hysteresis.max(0.0) {
+            self.hold -= self.time_step;
+            if self.hold <= 0.0 {
+                self.open = false;
+            }
+        }
+
+        let gain = self.gain.process(if self.open { 1.0 } else { db_to_gain(self.config.range.min(0.0)) });
+        self.output.port.take_input(&[in_l * gain, in_r * gain]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{delay::DelayLine, dynamics::{db_to_gain, gain_to_db}}};
+
+const MAX_LOOKAHEAD: f64 = 20.0;    //Milliseconds
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LimiterConfig {
+    pub input: f64,         //dB, gain before limiting
+    pub ceiling: f64,       //dB, the output never exceeds this
+    pub release: f64,       //Milliseconds
+    pub lookahead: f64,     //Milliseconds, only applied in setup since it changes the latency
+}
+
+impl Default for LimiterConfig {
+    fn default() -> Self {
+        return LimiterConfig {
+            input: 0.0,
+            ceiling: -0.3,
+            release: 100.0,
+            lookahead: 5.0,
+        };
+    }
+}
+
+/// Brickwall limiter that delays the signal so it can reduce the gain before a peak arrives
+///
+/// The gain needed by every sample is held for the lookahead time and then smoothed by a moving average
+/// over the same length. That way the gain has fully reached the needed value when the peak is played,
+/// and there is no overshoot.
+pub struct Limiter {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: LimiterConfig,
+    time_step: f64,
+    lookahead: usize,
+    sample: u64,
+    left: DelayLine,
+    right: DelayLine,
+    minimum: VecDeque<(u64, f64)>,  //Candidates for the lowest gain of the lookahead window, ascending in gain
+    release: f64,
+    average: Vec<f64>,
+    average_sum: f64,
+    gain: f64,
+}
+
+impl Limiter {
+
+    pub fn new() -> Limiter {
+        return Limiter {
+            info: DeviceInfo {
+                name: "Limiter",
+                type_identifier: "synthi_sam_limiter",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: LimiterConfig::default(),
+            time_step: 1.0/44100.0,
+            lookahead: 1,
+            sample: 0,
+            left: DelayLine::default(),
+            right: DelayLine::default(),
+            minimum: VecDeque::new(),
+            release: 1.0,
+            average: Vec::new(),
+            average_sum: 0.0,
+            gain: 1.0,
+        };
+    }
+
+    /// Current gain reduction in dB, for metering
+    #[inline]
+    pub fn gain_reduction(&self) -> f64 {
+        return -gain_to_db(self.gain);
+    }
+
+}
+
+impl Device for Limiter {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.time_step = info.time_step;
+        self.lookahead = ((self.config.lookahead.clamp(0.0, MAX_LOOKAHEAD) * 0.001 * info.sample_rate as f64).round() as usize).max(1);
+        self.sample = 0;
+        self.left.resize(self.lookahead + 1);
+        self.right.resize(self.lookahead + 1);
+        self.minimum = VecDeque::with_capacity(self.lookahead + 2);
+        self.release = 1.0;
+        self.average = vec![1.0; self.lookahead];
+        self.average_sum = self.lookahead as f64;
+        self.gain = 1.0;
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let input = db_to_gain(self.config.input);
+        let (in_l, in_r) = (channels[0] * input, channels[1] * input);
+        self.left.push(in_l);
+        self.right.push(in_r);
+
+        //Gain this sample needs to stay below the ceiling
+        let ceiling = db_to_gain(self.config.ceiling.min(0.0));
+        let peak = in_l.abs().max(in_r.abs());
+        let needed = if peak > ceiling { ceiling/peak } else { 1.0 };
+
+        //Lowest needed gain of the last lookahead + 1 samples
+        while self.minimum.back().is_some_and(|(_, gain)| *gain >= needed) {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.sample, needed));
+        while self.minimum.front().is_some_and(|(index, _)| *index + (self.lookahead as u64) < self.sample) {
+            self.minimum.pop_front();
+        }
+        let held = self.minimum.front().map_or(1.0, |(_, gain)| *gain);
+
+        //Instant attack, exponential release
+        let release = 1.0 - (-self.time_step/(self.config.release.max(1.0) * 0.001)).exp();
+        self.release = held.min(self.release + (1.0 - self.release) * release);
+
+        //Moving average over the lookahead
+        let index = (self.sample % self.lookahead as u64) as usize;
+        self.average_sum += self.release - self.average[index];
+        self.average[index] = self.release;
+        self.gain = (self.average_sum/self.lookahead as f64).min(1.0);
+        if index == self.lookahead - 1 {
+            //Remove rounding errors that accumulate in the running sum
+            self.average_sum = self.average.iter().sum();
+        }
+        self.sample += 1;
+
+        self.output.port.take_input(&[self.left.tap(self.lookahead) * self.gain, self.right.tap(self.lookahead) * self.gain]);
+    }
+
+    fn latency(&self) -> usize {
+        return self.lookahead;
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
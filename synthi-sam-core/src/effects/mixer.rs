@@ -0,0 +1,260 @@
+use std::{f64::consts::FRAC_PI_4, fmt::Display};
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{smooth::Smoother, dynamics::db_to_gain}};
+
+pub const MAX_MIXER_CHANNELS: usize = 16;
+pub const MAX_AUX_SENDS: usize = 4;
+/// Time of the control smoothing in seconds
+const CONTROL_SMOOTHING: f64 = 0.01;
+
+const CHANNEL_NAMES: [(&str, &str); MAX_MIXER_CHANNELS] = [
+    ("Channel 1 In", "channel_1_in"), ("Channel 2 In", "channel_2_in"), ("Channel 3 In", "channel_3_in"), ("Channel 4 In", "channel_4_in"),
+    ("Channel 5 In", "channel_5_in"), ("Channel 6 In", "channel_6_in"), ("Channel 7 In", "channel_7_in"), ("Channel 8 In", "channel_8_in"),
+    ("Channel 9 In", "channel_9_in"), ("Channel 10 In", "channel_10_in"), ("Channel 11 In", "channel_11_in"), ("Channel 12 In", "channel_12_in"),
+    ("Channel 13 In", "channel_13_in"), ("Channel 14 In", "channel_14_in"), ("Channel 15 In", "channel_15_in"), ("Channel 16 In", "channel_16_in"),
+];
+const AUX_NAMES: [(&str, &str); MAX_AUX_SENDS] = [
+    ("Aux 1 Out", "aux_1_out"), ("Aux 2 Out", "aux_2_out"), ("Aux 3 Out", "aux_3_out"), ("Aux 4 Out", "aux_4_out"),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PanLaw {
+    Balance,        //The center is at unity gain, panning only turns the other side down
+    ConstantPower,  //-3 dB in the center
+    Compromise,     //-4.5 dB in the center
+    Linear,         //-6 dB in the center
+}
+
+impl Default for PanLaw {
+    fn default() -> Self {
+        return PanLaw::Balance;
+    }
+}
+
+impl Display for PanLaw {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            PanLaw::Balance => "Balance",
+            PanLaw::ConstantPower => "Constant Power (-3 dB)",
+            PanLaw::Compromise => "Compromise (-4.5 dB)",
+            PanLaw::Linear => "Linear (-6 dB)",
+        })
+    }
+}
+
+impl PanLaw {
+
+    /// Returns the left and right gain for a pan position from -1 (left) to 1 (right)
+    #[inline]
+    pub fn gains(&self, pan: f64) -> (f64, f64) {
+        let pan = pan.clamp(-1.0, 1.0);
+        let linear = ((1.0 - pan) * 0.5, (1.0 + pan) * 0.5);
+        let angle = (pan + 1.0) * FRAC_PI_4;
+        let power = (angle.cos(), angle.sin());
+        return match self {
+            PanLaw::Balance => ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0)),
+            PanLaw::ConstantPower => power,
+            PanLaw::Compromise => ((linear.0 * power.0).sqrt(), (linear.1 * power.1).sqrt()),
+            PanLaw::Linear => linear,
+        }
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AuxSend {
+    pub level: f64,         //dB
+    pub pre_fader: bool,    //The send is taken before gain and pan, mute still applies
+}
+
+impl Default for AuxSend {
+    fn default() -> Self {
+        return AuxSend {
+            level: f64::NEG_INFINITY,
+            pre_fader: false,
+        };
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MixerChannel {
+    pub gain: f64,          //dB
+    pub pan: f64,           //-1 (left) to 1 (right)
+    pub pan_law: PanLaw,
+    pub mute: bool,
+    pub solo: bool,
+    pub sends: [AuxSend; MAX_AUX_SENDS],
+}
+
+impl Default for MixerChannel {
+    fn default() -> Self {
+        return MixerChannel {
+            gain: 0.0,
+            pan: 0.0,
+            pan_law: PanLaw::default(),
+            mute: false,
+            solo: false,
+            sends: [AuxSend::default(); MAX_AUX_SENDS],
+        };
+    }
+}
+
+/// Settings of all channels, only the first channels up to the input count of the mixer are used
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MixerConfig {
+    pub channels: [MixerChannel; MAX_MIXER_CHANNELS],
+    pub master: f64,        //dB
+}
+
+impl Default for MixerConfig {
+    fn default() -> Self {
+        return MixerConfig {
+            channels: [MixerChannel::default(); MAX_MIXER_CHANNELS],
+            master: 0.0,
+        };
+    }
+}
+
+/// Smoothed gains of one channel
+#[derive(Default)]
+struct ChannelState {
+    fader: (Smoother, Smoother),    //Gain, pan and mute for each side
+    pre_sends: [Smoother; MAX_AUX_SENDS],
+    post_sends: [Smoother; MAX_AUX_SENDS],
+}
+
+/// Sums several stereo inputs into a master output and aux buses
+///
+/// The inputs are the first ports, the master is output 0 and the aux buses follow it.
+/// Every control is smoothed, so they can be changed while playing without clicks.
+pub struct Mixer {
+    info: DeviceInfo,
+    inputs: Vec<NamedAudioPort>,
+    master_output: NamedAudioPort,
+    aux_outputs: Vec<NamedAudioPort>,
+    pub config: MixerConfig,
+    states: Vec<ChannelState>,
+    master: Smoother,
+    target_config: Option<MixerConfig>, //Config the smoother targets were calculated for
+}
+
+impl Mixer {
+
+    /// Creates a mixer with the given amount of input channels (up to 16) and aux buses (up to 4)
+    pub fn new(channels: usize, aux: usize) -> Mixer {
+        let channels = channels.clamp(1, MAX_MIXER_CHANNELS);
+        let aux = aux.min(MAX_AUX_SENDS);
+        return Mixer {
+            info: DeviceInfo {
+                name: "Mixer",
+                type_identifier: "synthi_sam_mixer",
+            },
+            inputs: CHANNEL_NAMES[..channels].iter().map(|(name, id)| NamedAudioPort::new(name, id, 2)).collect(),
+            master_output: NamedAudioPort::new("Master Out", "master_out", 2),
+            aux_outputs: AUX_NAMES[..aux].iter().map(|(name, id)| NamedAudioPort::new(name, id, 2)).collect(),
+            config: MixerConfig::default(),
+            states: (0..channels).map(|_| ChannelState::default()).collect(),
+            master: Smoother::new(1.0),
+            target_config: None,
+        };
+    }
+
+    /// Number of input channels
+    #[inline(always)]
+    pub fn channel_count(&self) -> usize {
+        return self.inputs.len();
+    }
+
+    /// Calculates the target gains of every control if the config changed
+    fn update_targets(&mut self) {
+        if self.target_config == Some(self.config) {
+            return;
+        }
+        self.target_config = Some(self.config);
+        let channels = &self.config.channels[..self.states.len()];
+        let solo = channels.iter().any(|c| c.solo);
+        for (channel, state) in channels.iter().zip(self.states.iter_mut()) {
+            let audible = !channel.mute && (!solo || channel.solo);
+            let gain = if audible { db_to_gain(channel.gain) } else { 0.0 };
+            let (pan_l, pan_r) = channel.pan_law.gains(channel.pan);
+            state.fader.0.set(gain * pan_l);
+            state.fader.1.set(gain * pan_r);
+            for (i, send) in channel.sends.iter().enumerate() {
+                let level = if audible { db_to_gain(send.level) } else { 0.0 };
+                state.pre_sends[i].set(if send.pre_fader { level } else { 0.0 });
+                state.post_sends[i].set(if send.pre_fader { 0.0 } else { level });
+            }
+        }
+        self.master.set(db_to_gain(self.config.master));
+    }
+
+}
+
+impl Device for Mixer {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        for port in self.inputs.iter_mut().chain(self.aux_outputs.iter_mut()) {
+            port.port.reset();
+        }
+        self.master_output.port.reset();
+        self.target_config = None;
+        self.update_targets();
+        for state in self.states.iter_mut() {
+            let smoothers = [&mut state.fader.0, &mut state.fader.1].into_iter().chain(state.pre_sends.iter_mut()).chain(state.post_sends.iter_mut());
+            for smoother in smoothers {
+                smoother.set_time(CONTROL_SMOOTHING, info.time_step);
+                smoother.reset(smoother.target());
+            }
+        }
+        self.master.set_time(CONTROL_SMOOTHING, info.time_step);
+        self.master.reset(self.master.target());
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        self.update_targets();
+        let mut master = (0.0, 0.0);
+        let mut aux = [(0.0, 0.0); MAX_AUX_SENDS];
+        for (input, state) in self.inputs.iter().zip(self.states.iter_mut()) {
+            let channels = input.port.channels();
+            let (in_l, in_r) = (channels[0], channels[1]);
+            let (out_l, out_r) = (in_l * state.fader.0.process(), in_r * state.fader.1.process());
+            master.0 += out_l;
+            master.1 += out_r;
+            for (i, bus) in aux.iter_mut().enumerate().take(self.aux_outputs.len()) {
+                let pre = state.pre_sends[i].process();
+                let post = state.post_sends[i].process();
+                bus.0 += in_l * pre + out_l * post;
+                bus.1 += in_r * pre + out_r * post;
+            }
+        }
+        let gain = self.master.process();
+        self.master_output.port.take_input(&[master.0 * gain, master.1 * gain]);
+        for (port, bus) in self.aux_outputs.iter_mut().zip(aux.iter()) {
+            port.port.take_input(&[bus.0, bus.1]);
+        }
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return self.inputs.get_mut(index);
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.master_output),
+            _ => self.aux_outputs.get_mut(index - 1),
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
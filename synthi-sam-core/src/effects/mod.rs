@@ -0,0 +1,11 @@
+pub mod delay;
+pub mod reverb;
+pub mod chorus;
+pub mod flanger;
+pub mod phaser;
+pub mod distortion;
+pub mod compressor;
+pub mod limiter;
+pub mod gate;
+pub mod equalizer;
+pub mod mixer;
\ No newline at end of file
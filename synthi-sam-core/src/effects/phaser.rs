@@ -0,0 +1,185 @@
+use std::{f64::consts::PI, fmt::Display};
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{oscillator::{Oscillator, WaveForm}, lfo::LfoRate}};
+
+const MAX_PHASER_STAGES: usize = 12;
+const MAX_FEEDBACK: f64 = 0.95;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PhaserStages {
+    Four,
+    Eight,
+    Twelve,
+}
+
+impl Default for PhaserStages {
+    fn default() -> Self {
+        return PhaserStages::Four;
+    }
+}
+
+impl Display for PhaserStages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.count())
+    }
+}
+
+impl PhaserStages {
+
+    #[inline]
+    pub fn count(&self) -> usize {
+        return match self {
+            PhaserStages::Four => 4,
+            PhaserStages::Eight => 8,
+            PhaserStages::Twelve => 12,
+        }
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PhaserConfig {
+    pub stages: PhaserStages,
+    pub rate: LfoRate,
+    pub min_freq: f64,      //Hz, lowest point of the sweep
+    pub max_freq: f64,      //Hz, highest point of the sweep
+    pub feedback: f64,      //-0.95 to 0.95
+    pub stereo_phase: f64,  //LFO offset of the right channel (0 to 1)
+    pub mix: f64,           //0 is dry, 1 is wet only, 0.5 gives the deepest notches
+}
+
+impl Default for PhaserConfig {
+    fn default() -> Self {
+        return PhaserConfig {
+            stages: PhaserStages::Four,
+            rate: LfoRate::Free(0.5),
+            min_freq: 200.0,
+            max_freq: 4000.0,
+            feedback: 0.3,
+            stereo_phase: 0.25,
+            mix: 0.5,
+        };
+    }
+}
+
+#[derive(Default)]
+struct PhaserChannel {
+    states: [f64; MAX_PHASER_STAGES],
+    feedback: f64,
+}
+
+impl PhaserChannel {
+
+    /// Runs the sample through a chain of first order allpass filters with the same coefficient
+    #[inline]
+    fn process(&mut self, sample: f64, coeff: f64, stages: usize, feedback: f64) -> f64 {
+        let mut value = sample + self.feedback * feedback;
+        for state in self.states[..stages].iter_mut() {
+            let out = coeff * value + *state;
+            *state = value - coeff * out;
+            value = out;
+        }
+        self.feedback = value;
+        return value;
+    }
+
+}
+
+/// Phaser with 4, 8 or 12 allpass stages swept exponentially between two frequencies
+pub struct Phaser {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: PhaserConfig,
+    pub bpm: f64,
+    time_step: f64,
+    lfo: Oscillator,
+    left: PhaserChannel,
+    right: PhaserChannel,
+}
+
+impl Phaser {
+
+    pub fn new() -> Phaser {
+        return Phaser {
+            info: DeviceInfo {
+                name: "Phaser",
+                type_identifier: "synthi_sam_phaser",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: PhaserConfig::default(),
+            bpm: 120.0,
+            time_step: 1.0/44100.0,
+            lfo: Oscillator::default(),
+            left: PhaserChannel::default(),
+            right: PhaserChannel::default(),
+        };
+    }
+
+    /// Allpass coefficient for the break frequency at an LFO position from 0 to 1
+    #[inline]
+    fn coefficient(&self, position: f64) -> f64 {
+        let nyquist = 0.49/self.time_step;
+        let min = self.config.min_freq.clamp(10.0, nyquist);
+        let max = self.config.max_freq.clamp(min, nyquist);
+        let freq = min * (max/min).powf(position);
+        let t = (PI * freq * self.time_step).tan();
+        return (t - 1.0)/(t + 1.0);
+    }
+
+}
+
+impl Device for Phaser {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.time_step = info.time_step;
+        self.lfo.reset(0.0);
+        self.left = PhaserChannel::default();
+        self.right = PhaserChannel::default();
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let (in_l, in_r) = (channels[0], channels[1]);
+        self.lfo.advance(self.config.rate.freq(self.bpm), self.time_step);
+        let position_l = (WaveForm::Sine.synthesize(self.lfo.phase(), 0.5) + 1.0) * 0.5;
+        let position_r = (WaveForm::Sine.synthesize((self.lfo.phase() + self.config.stereo_phase).rem_euclid(1.0), 0.5) + 1.0) * 0.5;
+
+        let stages = self.config.stages.count();
+        let feedback = self.config.feedback.clamp(-MAX_FEEDBACK, MAX_FEEDBACK);
+        let wet_l = self.left.process(in_l, self.coefficient(position_l), stages, feedback);
+        let wet_r = self.right.process(in_r, self.coefficient(position_r), stages, feedback);
+        let mix = self.config.mix.clamp(0.0, 1.0);
+        self.output.port.take_input(&[in_l * (1.0 - mix) + wet_l * mix, in_r * (1.0 - mix) + wet_r * mix]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
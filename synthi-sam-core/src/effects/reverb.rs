@@ -0,0 +1,250 @@
+use std::f64::consts::PI;
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}}, dsp::{delay::{DelayLine, Allpass}, smooth::Smoother, oscillator::{Oscillator, WaveForm}}};
+
+const FDN_LINES: usize = 8;
+/// Lengths of the feedback lines in milliseconds at a size of 1
+const LINE_LENGTHS: [f64; FDN_LINES] = [29.7, 37.1, 41.1, 43.7, 53.3, 59.9, 67.7, 79.3];
+/// Rates of the line modulation in Hz, different for each line so they don't move together
+const LINE_MOD_RATES: [f64; FDN_LINES] = [0.31, 0.43, 0.53, 0.67, 0.71, 0.83, 0.97, 1.07];
+/// Lengths of the input diffusers in milliseconds
+const DIFFUSER_LENGTHS: [f64; 4] = [4.77, 3.59, 12.73, 9.31];
+/// The diffusers of the right channel are a bit longer to decorrelate the channels
+const DIFFUSER_SPREAD: f64 = 1.13;
+const MIN_SIZE: f64 = 0.25;
+const MAX_SIZE: f64 = 2.0;
+const MAX_PRE_DELAY: f64 = 0.5;     //Seconds
+const MAX_MOD_DEPTH: f64 = 0.001;   //Seconds
+const MAX_DIFFUSION: f64 = 0.75;
+const SIZE_SMOOTHING: f64 = 0.2;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReverbConfig {
+    pub pre_delay: f64,     //Milliseconds
+    pub decay: f64,         //Seconds until the tail fell by 60 dB
+    pub damping: f64,       //0 to 1, how much faster high frequencies decay
+    pub size: f64,          //0 to 1
+    pub diffusion: f64,     //0 to 1
+    pub modulation: f64,    //0 to 1
+    pub mix: f64,           //0 is dry, 1 is wet only
+}
+
+impl Default for ReverbConfig {
+    fn default() -> Self {
+        return ReverbConfig {
+            pre_delay: 10.0,
+            decay: 2.0,
+            damping: 0.4,
+            size: 0.6,
+            diffusion: 0.7,
+            modulation: 0.3,
+            mix: 0.25,
+        };
+    }
+}
+
+impl ReverbConfig {
+
+    /// Factor the line lengths are multiplied with
+    #[inline]
+    fn scale(&self) -> f64 {
+        return MIN_SIZE + (MAX_SIZE - MIN_SIZE) * self.size.clamp(0.0, 1.0);
+    }
+
+    /// Cutoff of the damping filters in Hz
+    #[inline]
+    fn damping_cutoff(&self) -> f64 {
+        return 200.0 * f64::from(100.0).powf(1.0 - self.damping.clamp(0.0, 1.0));
+    }
+
+}
+
+/// One line of the feedback delay network
+#[derive(Default)]
+struct FeedbackLine {
+    line: DelayLine,
+    length: Smoother,   //Seconds
+    lfo: Oscillator,
+    damping: f64,       //State of the damping low pass
+    gain: f64,
+}
+
+/// Stereo reverb built as a feedback delay network with eight lines mixed by a Hadamard matrix
+///
+/// The input is diffused by allpass filters before it enters the network.
+/// Every line has a damping low pass and a slowly modulated length to avoid metallic resonances.
+/// The amount of work per sample doesn't depend on the settings or the sample rate.
+pub struct Reverb {
+    info: DeviceInfo,
+    input: NamedAudioPort,
+    output: NamedAudioPort,
+    pub config: ReverbConfig,
+    sample_rate: f64,
+    time_step: f64,
+    pre_delay: DelayLine,
+    pre_delay_time: Smoother,
+    diffusers: [[Allpass; 4]; 2],
+    lines: [FeedbackLine; FDN_LINES],
+    gain_config: (f64, f64),    //Decay and size the gains were calculated for
+}
+
+impl Reverb {
+
+    pub fn new() -> Reverb {
+        return Reverb {
+            info: DeviceInfo {
+                name: "Reverb",
+                type_identifier: "synthi_sam_reverb",
+            },
+            input: NamedAudioPort::new("Stereo In", "stereo_in", 2),
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            config: ReverbConfig::default(),
+            sample_rate: 44100.0,
+            time_step: 1.0/44100.0,
+            pre_delay: DelayLine::default(),
+            pre_delay_time: Smoother::default(),
+            diffusers: Default::default(),
+            lines: Default::default(),
+            gain_config: (0.0, 0.0),
+        };
+    }
+
+    /// Calculates the feedback gain of every line so the tail decays by 60 dB within the decay time
+    fn update_gains(&mut self) {
+        let config = (self.config.decay.max(0.05), self.config.scale());
+        if config != self.gain_config {
+            self.gain_config = config;
+            let (decay, scale) = config;
+            for (line, length) in self.lines.iter_mut().zip(LINE_LENGTHS.iter()) {
+                line.gain = f64::from(10.0).powf(-3.0 * length * 0.001 * scale/decay);
+            }
+        }
+    }
+
+    /// In place fast Walsh-Hadamard transform, normalized so it doesn't change the energy
+    #[inline]
+    fn hadamard(values: &mut [f64; FDN_LINES]) {
+        let mut size = 1;
+        while size < FDN_LINES {
+            for start in (0..FDN_LINES).step_by(size * 2) {
+                for i in start..start + size {
+                    let (a, b) = (values[i], values[i + size]);
+                    values[i] = a + b;
+                    values[i + size] = a - b;
+                }
+            }
+            size *= 2;
+        }
+        let norm = 1.0/(FDN_LINES as f64).sqrt();
+        for v in values.iter_mut() {
+            *v *= norm;
+        }
+    }
+
+}
+
+impl Device for Reverb {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.sample_rate = info.sample_rate as f64;
+        self.time_step = info.time_step;
+        let samples = |seconds: f64| (seconds * self.sample_rate).ceil() as usize + 2;
+
+        self.pre_delay.resize(samples(MAX_PRE_DELAY));
+        self.pre_delay_time.set_time(SIZE_SMOOTHING, info.time_step);
+        self.pre_delay_time.reset(self.config.pre_delay * 0.001);
+        for diffusers in self.diffusers.iter_mut() {
+            for (diffuser, length) in diffusers.iter_mut().zip(DIFFUSER_LENGTHS.iter()) {
+                diffuser.resize(samples(length * 0.001 * DIFFUSER_SPREAD));
+            }
+        }
+        let scale = self.config.scale();
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            line.line.resize(samples(LINE_LENGTHS[i] * 0.001 * MAX_SIZE + 2.0 * MAX_MOD_DEPTH));
+            line.length.set_time(SIZE_SMOOTHING, info.time_step);
+            line.length.reset(LINE_LENGTHS[i] * 0.001 * scale);
+            line.lfo.reset(i as f64/(FDN_LINES as f64));
+            line.damping = 0.0;
+        }
+        self.gain_config = (0.0, 0.0);
+        self.update_gains();
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        let channels = self.input.port.channels();
+        let (in_l, in_r) = (channels[0], channels[1]);
+        self.update_gains();
+
+        //Pre-delay (mono)
+        self.pre_delay.push((in_l + in_r) * 0.5);
+        self.pre_delay_time.set((self.config.pre_delay * 0.001).clamp(0.0, MAX_PRE_DELAY));
+        let pre = self.pre_delay.read(self.pre_delay_time.process() * self.sample_rate);
+
+        //Diffusion, the two channels use different signs and lengths
+        let diffusion = self.config.diffusion.clamp(0.0, 1.0) * MAX_DIFFUSION;
+        let mut diffused = [pre, pre];
+        for (c, diffusers) in self.diffusers.iter_mut().enumerate() {
+            let sign = if c == 0 { 1.0 } else { -1.0 };
+            for (diffuser, length) in diffusers.iter_mut().zip(DIFFUSER_LENGTHS.iter()) {
+                let length = length * 0.001 * if c == 0 { 1.0 } else { DIFFUSER_SPREAD };
+                diffused[c] = diffuser.process(diffused[c], length * self.sample_rate, diffusion * sign);
+            }
+        }
+
+        //Read the lines
+        let scale = self.config.scale();
+        let depth = self.config.modulation.clamp(0.0, 1.0) * MAX_MOD_DEPTH;
+        let damping = 1.0 - (-2.0 * PI * self.config.damping_cutoff() * self.time_step).exp();
+        let mut outputs = [0.0; FDN_LINES];
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            line.length.set(LINE_LENGTHS[i] * 0.001 * scale);
+            line.lfo.advance(LINE_MOD_RATES[i], self.time_step);
+            let modulation = WaveForm::Sine.synthesize(line.lfo.phase(), 0.5) * depth;
+            let delayed = line.line.read((line.length.process() + modulation + depth) * self.sample_rate);
+            line.damping += (delayed - line.damping) * damping;
+            outputs[i] = line.damping * line.gain;
+        }
+        let (wet_l, wet_r) = (
+            outputs.iter().step_by(2).sum::<f64>() * 0.5,
+            outputs.iter().skip(1).step_by(2).sum::<f64>() * 0.5,
+        );
+
+        //Mix and feed back
+        Self::hadamard(&mut outputs);
+        for (i, line) in self.lines.iter_mut().enumerate() {
+            line.line.push(outputs[i] + diffused[i % 2]);
+        }
+
+        let mix = self.config.mix.clamp(0.0, 1.0);
+        self.output.port.take_input(&[in_l * (1.0 - mix) + wet_l * mix, in_r * (1.0 - mix) + wet_r * mix]);
+    }
+
+    fn audio_input_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
@@ -1,3 +1,6 @@
 pub mod core;
 pub mod dsp;
 pub mod util;
+pub mod midifx;
+pub mod effects;
+pub mod sample;
\ No newline at end of file
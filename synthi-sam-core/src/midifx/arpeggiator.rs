@@ -0,0 +1,401 @@
+use std::fmt::Display;
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessage, MidiMessageContent}}, util::{tempo::{NoteDivision, ClockTempo, CLOCK_PPQN}, random::Random}};
+
+const MIDI_NOTES: usize = 128;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ArpOrder {
+    Up,
+    Down,
+    UpDown,     //Doesn't repeat the highest and lowest note
+    Random,
+    AsPlayed,
+}
+
+impl Default for ArpOrder {
+    fn default() -> Self {
+        return ArpOrder::Up;
+    }
+}
+
+impl Display for ArpOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ArpOrder::Up => "Up",
+            ArpOrder::Down => "Down",
+            ArpOrder::UpDown => "Up/Down",
+            ArpOrder::Random => "Random",
+            ArpOrder::AsPlayed => "As Played",
+        })
+    }
+}
+
+/// Where the arpeggiator takes it's timing from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ArpSync {
+    Tempo,  //Runs at the configured bpm as soon as a note is pressed
+    Clock,  //Follows incoming MIDI clock, start, continue and stop messages
+}
+
+impl Default for ArpSync {
+    fn default() -> Self {
+        return ArpSync::Tempo;
+    }
+}
+
+impl Display for ArpSync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ArpSync::Tempo => "Tempo",
+            ArpSync::Clock => "MIDI Clock",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ArpVelocity {
+    PassThrough,
+    Fixed(f64),
+}
+
+impl Default for ArpVelocity {
+    fn default() -> Self {
+        return ArpVelocity::PassThrough;
+    }
+}
+
+impl Display for ArpVelocity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            ArpVelocity::PassThrough => write!(f, "Pass Through"),
+            ArpVelocity::Fixed(v) => write!(f, "Fixed ({})", v),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ArpeggiatorConfig {
+    pub order: ArpOrder,
+    pub octaves: u8,            //Number of octaves the pattern is repeated in (at least 1)
+    pub rate: NoteDivision,
+    pub gate: f64,              //Note length relative to the step length, 1 plays legato
+    pub swing: f64,             //0 is straight, 1 delays every second step by half a step
+    pub latch: bool,            //Keeps playing after the keys are released until a new chord is pressed
+    pub velocity: ArpVelocity,
+    pub sync: ArpSync,
+    pub bpm: f64,
+}
+
+impl Default for ArpeggiatorConfig {
+    fn default() -> Self {
+        return ArpeggiatorConfig {
+            order: ArpOrder::Up,
+            octaves: 1,
+            rate: NoteDivision::new(1, 16),
+            gate: 0.5,
+            swing: 0.0,
+            latch: false,
+            velocity: ArpVelocity::PassThrough,
+            sync: ArpSync::Tempo,
+            bpm: 120.0,
+        };
+    }
+}
+
+/// Plays the held notes one after another
+///
+/// All timing is done in beats. In tempo mode the position advances with the configured bpm,
+/// in clock mode it is set by the clock messages and interpolated in between using the measured tempo.
+pub struct Arpeggiator {
+    info: DeviceInfo,
+    input: NamedMidiPort,
+    output: NamedMidiPort,
+    pub config: ArpeggiatorConfig,
+    time_step: f64,
+    random: Random,
+    notes: Vec<(u8, f64)>,          //Pattern notes and their velocity in the order they were played
+    sorted: Vec<(u8, f64)>,
+    keys_down: [bool; MIDI_NOTES],
+    channel: u8,
+    //Timing
+    position: f64,
+    running: bool,
+    step_pos: f64,                  //Unswung position of the next step
+    step_count: u64,
+    pattern_step: usize,
+    playing: Option<(u8, u8)>,      //Note and channel
+    note_off_pos: f64,
+    //Clock
+    transport: bool,
+    clock_pos: f64,
+    clock_rate: f64,                //Beats per second
+    clock_tempo: ClockTempo,
+}
+
+impl Arpeggiator {
+
+    pub fn new() -> Arpeggiator {
+        return Arpeggiator {
+            info: DeviceInfo {
+                name: "Arpeggiator",
+                type_identifier: "synthi_sam_arpeggiator",
+            },
+            input: NamedMidiPort::new("MIDI In", "midi_in"),
+            output: NamedMidiPort::new("MIDI Out", "midi_out"),
+            config: ArpeggiatorConfig::default(),
+            time_step: 1.0/44100.0,
+            random: Random::default(),
+            notes: Vec::with_capacity(MIDI_NOTES),
+            sorted: Vec::with_capacity(MIDI_NOTES),
+            keys_down: [false; MIDI_NOTES],
+            channel: 0,
+            position: 0.0,
+            running: false,
+            step_pos: 0.0,
+            step_count: 0,
+            pattern_step: 0,
+            playing: None,
+            note_off_pos: 0.0,
+            transport: false,
+            clock_pos: 0.0,
+            clock_rate: 2.0,
+            clock_tempo: ClockTempo::new(),
+        };
+    }
+
+    fn press(&mut self, channel: u8, note: u8, velocity: f64) {
+        let note = note & 0x7F;
+        //A new chord replaces the latched one
+        if self.config.latch && !self.keys_down.iter().any(|k| *k) {
+            self.notes.clear();
+        }
+        self.keys_down[note as usize] = true;
+        self.channel = channel;
+        self.notes.retain(|(n, _)| *n != note);
+        if self.notes.len() < MIDI_NOTES {
+            self.notes.push((note, velocity));
+        }
+    }
+
+    fn release(&mut self, note: u8) {
+        let note = note & 0x7F;
+        self.keys_down[note as usize] = false;
+        if !self.config.latch {
+            self.notes.retain(|(n, _)| *n != note);
+        }
+    }
+
+    fn stop_note(&mut self) {
+        if let Some((note, channel)) = self.playing.take() {
+            self.output.port.queue(MidiMessage::note_off(channel, note, 0.0));
+        }
+    }
+
+    #[inline]
+    fn step_length(&self) -> f64 {
+        return self.config.rate.beats().max(1.0/CLOCK_PPQN);
+    }
+
+    /// Starts the pattern from the beginning
+    fn start(&mut self) {
+        self.running = true;
+        self.pattern_step = 0;
+        self.step_count = 0;
+        self.step_pos = match self.config.sync {
+            ArpSync::Tempo => {
+                self.position = 0.0;
+                0.0
+            },
+            //Wait for the next step on the grid of the clock
+            ArpSync::Clock => {
+                let length = self.step_length();
+                (self.position/length - 1e-9).ceil() * length
+            },
+        };
+    }
+
+    /// Returns the note of the current pattern step
+    fn pattern_note(&mut self) -> Option<(u8, f64)> {
+        let count = self.notes.len();
+        if count == 0 {
+            return None;
+        }
+        let octaves = self.config.octaves.max(1) as usize;
+        let total = count * octaves;
+        let step = self.pattern_step;
+        self.sorted.clear();
+        self.sorted.extend_from_slice(&self.notes);
+        if self.config.order != ArpOrder::AsPlayed {
+            self.sorted.sort_by_key(|(n, _)| *n);
+        }
+        let index = match self.config.order {
+            ArpOrder::Up | ArpOrder::AsPlayed => step % total,
+            ArpOrder::Down => total - 1 - step % total,
+            ArpOrder::UpDown => {
+                if total < 2 {
+                    0
+                }
+                else {
+                    let cycle = step % (2 * total - 2);
+                    if cycle < total { cycle } else { 2 * total - 2 - cycle }
+                }
+            },
+            ArpOrder::Random => (self.random.next_u64() % total as u64) as usize,
+        };
+        let (note, velocity) = self.sorted[index % count];
+        let note = note as usize + (index / count) * 12;
+        return if note < MIDI_NOTES { Some((note as u8, velocity)) } else { None };
+    }
+
+    fn play_step(&mut self) {
+        let length = self.step_length();
+        self.stop_note();
+        if let Some((note, velocity)) = self.pattern_note() {
+            let velocity = match self.config.velocity {
+                ArpVelocity::PassThrough => velocity,
+                ArpVelocity::Fixed(v) => v,
+            };
+            self.output.port.queue(MidiMessage::note_on(self.channel, note, velocity));
+            self.playing = Some((note, self.channel));
+            self.note_off_pos = self.position + length * self.config.gate.max(0.0);
+        }
+        self.pattern_step = self.pattern_step.wrapping_add(1);
+        self.step_count += 1;
+        self.step_pos += length;
+    }
+
+    fn clock(&mut self, time: f64) {
+        self.clock_tempo.tick(time);
+        if let Some(bpm) = self.clock_tempo.bpm() {
+            self.clock_rate = bpm/60.0;
+        }
+        if self.transport {
+            self.clock_pos += 1.0/CLOCK_PPQN;
+            self.position = self.clock_pos;
+        }
+    }
+
+    fn transport(&mut self, start: bool, reset: bool) {
+        self.transport = start;
+        if reset {
+            self.clock_pos = 0.0;
+            self.position = 0.0;
+            self.running = false;
+        }
+        if !start {
+            self.stop_note();
+            self.running = false;
+        }
+    }
+
+}
+
+impl Device for Arpeggiator {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.time_step = info.time_step;
+        self.input.port.reset();
+        self.output.port.reset();
+        self.notes.clear();
+        self.keys_down = [false; MIDI_NOTES];
+        self.playing = None;
+        self.running = false;
+        self.transport = false;
+        self.clock_tempo.reset();
+    }
+
+    fn process(&mut self, info: SampleInfo) {
+        while let Some(msg) = self.input.port.pop() {
+            match msg.message {
+                MidiMessageContent::NoteOn(ref note) => self.press(msg.channel, note.note, note.velocity),
+                MidiMessageContent::NoteOff(ref note) => self.release(note.note),
+                //Clock and transport are forwarded so following devices stay in sync
+                MidiMessageContent::Clock => {
+                    self.clock(info.time);
+                    self.output.port.queue(msg);
+                },
+                MidiMessageContent::Start => {
+                    self.transport(true, true);
+                    self.output.port.queue(msg);
+                },
+                MidiMessageContent::Continue => {
+                    self.transport(true, false);
+                    self.output.port.queue(msg);
+                },
+                MidiMessageContent::Stop => {
+                    self.transport(false, false);
+                    self.output.port.queue(msg);
+                },
+                _ => self.output.port.queue(msg),
+            }
+        }
+        //Latch was switched off
+        if !self.config.latch && self.notes.len() > 0 {
+            let keys_down = self.keys_down;
+            self.notes.retain(|(n, _)| keys_down[*n as usize]);
+        }
+
+        //Advance
+        let advancing = match self.config.sync {
+            ArpSync::Tempo => {
+                self.position += self.config.bpm.max(1.0)/60.0 * self.time_step;
+                true
+            },
+            ArpSync::Clock => {
+                if self.transport {
+                    //Never run ahead of the next clock tick
+                    self.position = (self.position + self.clock_rate * self.time_step).min(self.clock_pos + 1.0/CLOCK_PPQN);
+                }
+                self.transport
+            },
+        };
+
+        if self.notes.is_empty() {
+            if self.running {
+                self.running = false;
+                self.stop_note();
+            }
+            return;
+        }
+        if !self.running && advancing {
+            self.start();
+        }
+        if self.playing.is_some() && self.position >= self.note_off_pos && self.config.gate < 1.0 {
+            self.stop_note();
+        }
+        if self.running {
+            let swing = if self.step_count % 2 == 1 { self.config.swing.clamp(0.0, 1.0) * 0.5 * self.step_length() } else { 0.0 };
+            if self.position >= self.step_pos + swing {
+                self.play_step();
+            }
+        }
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+}
@@ -0,0 +1,155 @@
+use std::fmt::Display;
+
+use crate::core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessage, MidiMessageContent, PitchBendEvent, MonophonicAftertouchEvent}};
+
+/// Message a controller is turned into
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CCTarget {
+    Control(u8),
+    PitchBend,
+    ChannelAftertouch,
+}
+
+impl Default for CCTarget {
+    fn default() -> Self {
+        return CCTarget::Control(1);
+    }
+}
+
+impl Display for CCTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            CCTarget::Control(c) => write!(f, "CC {}", c),
+            CCTarget::PitchBend => write!(f, "Pitch Bend"),
+            CCTarget::ChannelAftertouch => write!(f, "Channel Aftertouch"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CCMapping {
+    pub channel: Option<u8>,    //None maps the controller on all channels
+    pub control: u8,
+    pub target: CCTarget,
+    pub min: f64,               //Output value for a controller value of 0
+    pub max: f64,               //Output value for a controller value of 1, can be lower than min to invert
+}
+
+impl Default for CCMapping {
+    fn default() -> Self {
+        return CCMapping {
+            channel: None,
+            control: 1,
+            target: CCTarget::Control(1),
+            min: 0.0,
+            max: 1.0,
+        };
+    }
+}
+
+impl CCMapping {
+
+    pub fn new(control: u8, target: CCTarget) -> CCMapping {
+        return CCMapping {
+            control: control,
+            target: target,
+            ..Default::default()
+        };
+    }
+
+    #[inline]
+    fn accepts(&self, channel: u8, control: u8) -> bool {
+        return self.control == control && self.channel.is_none_or(|c| c == channel);
+    }
+
+    fn message(&self, channel: u8, value: f64) -> MidiMessage {
+        let value = (self.min + (self.max - self.min) * value).clamp(0.0, 1.0);
+        return match self.target {
+            CCTarget::Control(control) => MidiMessage::control_change(channel, control & 0x7F, value),
+            CCTarget::PitchBend => MidiMessage::from_content(channel, MidiMessageContent::PitchBend(PitchBendEvent { pitch_bend: value * 2.0 - 1.0 })),
+            CCTarget::ChannelAftertouch => MidiMessage::from_content(channel, MidiMessageContent::MonophonicAftertouch(MonophonicAftertouchEvent { aftertouch: value })),
+        }
+    }
+
+}
+
+/// Sends controllers to other controllers, pitch bend or aftertouch with a scaled range
+///
+/// A controller can have multiple mappings, controllers without a mapping are passed through unless they are blocked.
+pub struct CCRemapper {
+    info: DeviceInfo,
+    input: NamedMidiPort,
+    output: NamedMidiPort,
+    pub mappings: Vec<CCMapping>,
+    pub block_unmapped: bool,
+}
+
+impl CCRemapper {
+
+    pub fn new() -> CCRemapper {
+        return CCRemapper {
+            info: DeviceInfo {
+                name: "CC Remapper",
+                type_identifier: "synthi_sam_cc_remapper",
+            },
+            input: NamedMidiPort::new("MIDI In", "midi_in"),
+            output: NamedMidiPort::new("MIDI Out", "midi_out"),
+            mappings: Vec::new(),
+            block_unmapped: false,
+        };
+    }
+
+}
+
+impl Device for CCRemapper {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, _info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        while let Some(msg) = self.input.port.pop() {
+            match msg.message {
+                MidiMessageContent::ControlChange(ref cc) => {
+                    let mut mapped = false;
+                    for mapping in self.mappings.iter().filter(|m| m.accepts(msg.channel, cc.control)) {
+                        self.output.port.queue(mapping.message(msg.channel, cc.value));
+                        mapped = true;
+                    }
+                    if !mapped && !self.block_unmapped {
+                        self.output.port.queue(msg);
+                    }
+                },
+                _ => self.output.port.queue(msg),
+            }
+        }
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+}
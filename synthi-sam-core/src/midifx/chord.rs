@@ -0,0 +1,234 @@
+use std::fmt::Display;
+
+use crate::core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessage, MidiMessageContent}};
+
+pub const MAX_CHORD_NOTES: usize = 8;
+const MIDI_CHANNELS: usize = 16;
+const MIDI_NOTES: usize = 128;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChordType {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Seventh,
+    Major7,
+    Minor7,
+    Power,
+    Octave,
+}
+
+impl Default for ChordType {
+    fn default() -> Self {
+        return ChordType::Major;
+    }
+}
+
+impl Display for ChordType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ChordType::Major => "Major",
+            ChordType::Minor => "Minor",
+            ChordType::Diminished => "Diminished",
+            ChordType::Augmented => "Augmented",
+            ChordType::Sus2 => "Sus2",
+            ChordType::Sus4 => "Sus4",
+            ChordType::Seventh => "7",
+            ChordType::Major7 => "Maj7",
+            ChordType::Minor7 => "Min7",
+            ChordType::Power => "Power",
+            ChordType::Octave => "Octave",
+        })
+    }
+}
+
+impl ChordType {
+
+    /// Semitones of the chord notes relative to the root
+    pub fn intervals(&self) -> &'static [i8] {
+        return match self {
+            ChordType::Major => &[0, 4, 7],
+            ChordType::Minor => &[0, 3, 7],
+            ChordType::Diminished => &[0, 3, 6],
+            ChordType::Augmented => &[0, 4, 8],
+            ChordType::Sus2 => &[0, 2, 7],
+            ChordType::Sus4 => &[0, 5, 7],
+            ChordType::Seventh => &[0, 4, 7, 10],
+            ChordType::Major7 => &[0, 4, 7, 11],
+            ChordType::Minor7 => &[0, 3, 7, 10],
+            ChordType::Power => &[0, 7, 12],
+            ChordType::Octave => &[0, 12],
+        }
+    }
+
+}
+
+/// Plays a chord for every pressed note
+///
+/// The chord is either set from a chord type or learned from the keyboard (chord memory).
+/// Chord notes that are played by several keys are only released after the last of them.
+pub struct ChordGenerator {
+    info: DeviceInfo,
+    input: NamedMidiPort,
+    output: NamedMidiPort,
+    intervals: [i8; MAX_CHORD_NOTES],
+    interval_count: usize,
+    learning: bool,
+    learned: Vec<u8>,
+    held: Box<[[Option<u8>; MAX_CHORD_NOTES]]>,     //Output notes for every input channel and note
+    counts: Box<[u8]>,                              //Number of keys playing every output channel and note
+}
+
+impl ChordGenerator {
+
+    pub fn new() -> ChordGenerator {
+        let mut chord = ChordGenerator {
+            info: DeviceInfo {
+                name: "Chord Generator",
+                type_identifier: "synthi_sam_chord_generator",
+            },
+            input: NamedMidiPort::new("MIDI In", "midi_in"),
+            output: NamedMidiPort::new("MIDI Out", "midi_out"),
+            intervals: [0; MAX_CHORD_NOTES],
+            interval_count: 0,
+            learning: false,
+            learned: Vec::with_capacity(MAX_CHORD_NOTES),
+            held: vec![[None; MAX_CHORD_NOTES]; MIDI_CHANNELS * MIDI_NOTES].into_boxed_slice(),
+            counts: vec![0; MIDI_CHANNELS * MIDI_NOTES].into_boxed_slice(),
+        };
+        chord.set_chord_type(ChordType::Major);
+        return chord;
+    }
+
+    pub fn set_chord_type(&mut self, chord: ChordType) {
+        self.set_intervals(chord.intervals());
+    }
+
+    /// Sets the chord as semitones relative to the pressed note, only the first 8 intervals are used
+    pub fn set_intervals(&mut self, intervals: &[i8]) {
+        self.interval_count = intervals.len().min(MAX_CHORD_NOTES);
+        self.intervals[..self.interval_count].copy_from_slice(&intervals[..self.interval_count]);
+    }
+
+    #[inline(always)]
+    pub fn intervals(&self) -> &[i8] {
+        return &self.intervals[..self.interval_count];
+    }
+
+    /// The next notes that are pressed together become the chord, relative to the lowest of them
+    pub fn learn(&mut self) {
+        self.learning = true;
+        self.learned.clear();
+    }
+
+    #[inline(always)]
+    pub fn is_learning(&self) -> bool {
+        return self.learning;
+    }
+
+    #[inline(always)]
+    fn index(channel: u8, note: u8) -> usize {
+        return (channel as usize & 0x0F) * MIDI_NOTES + (note as usize & 0x7F);
+    }
+
+    fn press(&mut self, channel: u8, note: u8, velocity: f64) {
+        self.release(channel, note, velocity);
+        //While learning the notes are played as they are
+        let (intervals, count) = if self.learning {
+            if self.learned.len() < MAX_CHORD_NOTES && !self.learned.contains(&note) {
+                self.learned.push(note);
+            }
+            ([0; MAX_CHORD_NOTES], 1)
+        }
+        else {
+            (self.intervals, self.interval_count)
+        };
+        let mut notes = [None; MAX_CHORD_NOTES];
+        for (slot, interval) in notes.iter_mut().zip(intervals[..count].iter()) {
+            let out = note as i32 + *interval as i32;
+            if (0..MIDI_NOTES as i32).contains(&out) {
+                let out = out as u8;
+                self.counts[Self::index(channel, out)] += 1;
+                self.output.port.queue(MidiMessage::note_on(channel, out, velocity));
+                *slot = Some(out);
+            }
+        }
+        self.held[Self::index(channel, note)] = notes;
+    }
+
+    fn release(&mut self, channel: u8, note: u8, velocity: f64) {
+        let notes = std::mem::replace(&mut self.held[Self::index(channel, note)], [None; MAX_CHORD_NOTES]);
+        for out in notes.iter().flatten() {
+            let count = &mut self.counts[Self::index(channel, *out)];
+            if *count > 0 {
+                *count -= 1;
+                if *count == 0 {
+                    self.output.port.queue(MidiMessage::note_off(channel, *out, velocity));
+                }
+            }
+        }
+        //The learned chord is complete when the first key is released
+        if self.learning && notes.iter().any(|n| n.is_some()) {
+            if let Some(root) = self.learned.iter().min().copied() {
+                let mut intervals = [0; MAX_CHORD_NOTES];
+                self.learned.sort();
+                for (interval, note) in intervals.iter_mut().zip(self.learned.iter()) {
+                    *interval = (*note - root) as i8;
+                }
+                self.set_intervals(&intervals[..self.learned.len()]);
+                self.learning = false;
+            }
+        }
+    }
+
+}
+
+impl Device for ChordGenerator {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, _info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.held.fill([None; MAX_CHORD_NOTES]);
+        self.counts.fill(0);
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        while let Some(msg) = self.input.port.pop() {
+            match msg.message {
+                MidiMessageContent::NoteOn(ref note) => self.press(msg.channel, note.note, note.velocity),
+                MidiMessageContent::NoteOff(ref note) => self.release(msg.channel, note.note, note.velocity),
+                _ => self.output.port.queue(msg),
+            }
+        }
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+}
@@ -0,0 +1,8 @@
+pub mod router;
+pub mod arpeggiator;
+pub mod sequencer;
+pub mod chord;
+pub mod scale;
+pub mod velocity;
+pub mod note_filter;
+pub mod cc_remap;
\ No newline at end of file
@@ -0,0 +1,130 @@
+use crate::core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::MidiMessageContent};
+
+const MIDI_CHANNELS: usize = 16;
+const MIDI_NOTES: usize = 128;
+pub const ALL_CHANNELS: u16 = 0xFFFF;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NoteFilterConfig {
+    pub channels: u16,      //Bit n lets channel n through
+    pub low_key: u8,
+    pub high_key: u8,
+    pub low_velocity: f64,
+    pub high_velocity: f64,
+}
+
+impl Default for NoteFilterConfig {
+    fn default() -> Self {
+        return NoteFilterConfig {
+            channels: ALL_CHANNELS,
+            low_key: 0,
+            high_key: 127,
+            low_velocity: 0.0,
+            high_velocity: 1.0,
+        };
+    }
+}
+
+impl NoteFilterConfig {
+
+    #[inline]
+    pub fn accepts_channel(&self, channel: u8) -> bool {
+        return self.channels & (1 << (channel & 0x0F)) != 0;
+    }
+
+    #[inline]
+    pub fn accepts_note(&self, note: u8, velocity: f64) -> bool {
+        return note >= self.low_key && note <= self.high_key && velocity >= self.low_velocity && velocity <= self.high_velocity;
+    }
+
+}
+
+/// Lets only the messages of the selected channels and the notes inside the key and velocity range through
+///
+/// Note offs and polyphonic aftertouch follow their note on, so changing the filter doesn't cause hanging notes.
+pub struct NoteFilter {
+    info: DeviceInfo,
+    input: NamedMidiPort,
+    output: NamedMidiPort,
+    pub config: NoteFilterConfig,
+    passed: Box<[bool]>,
+}
+
+impl NoteFilter {
+
+    pub fn new() -> NoteFilter {
+        return NoteFilter {
+            info: DeviceInfo {
+                name: "Note Filter",
+                type_identifier: "synthi_sam_note_filter",
+            },
+            input: NamedMidiPort::new("MIDI In", "midi_in"),
+            output: NamedMidiPort::new("MIDI Out", "midi_out"),
+            config: NoteFilterConfig::default(),
+            passed: vec![false; MIDI_CHANNELS * MIDI_NOTES].into_boxed_slice(),
+        };
+    }
+
+    #[inline(always)]
+    fn index(channel: u8, note: u8) -> usize {
+        return (channel as usize & 0x0F) * MIDI_NOTES + (note as usize & 0x7F);
+    }
+
+}
+
+impl Device for NoteFilter {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, _info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.passed.fill(false);
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        while let Some(msg) = self.input.port.pop() {
+            let pass = match msg.message {
+                MidiMessageContent::NoteOn(ref note) => {
+                    let pass = self.config.accepts_channel(msg.channel) && self.config.accepts_note(note.note, note.velocity);
+                    //A repeated note on still needs the note off of the first one
+                    let passed = &mut self.passed[Self::index(msg.channel, note.note)];
+                    *passed |= pass;
+                    pass
+                },
+                MidiMessageContent::NoteOff(ref note) => std::mem::replace(&mut self.passed[Self::index(msg.channel, note.note)], false),
+                MidiMessageContent::PolyphonicAftertouch(ref at) => self.passed[Self::index(msg.channel, at.note)],
+                MidiMessageContent::SysEx(_) | MidiMessageContent::Clock | MidiMessageContent::Start | MidiMessageContent::Continue | MidiMessageContent::Stop => true,
+                _ => self.config.accepts_channel(msg.channel),
+            };
+            if pass {
+                self.output.port.queue(msg);
+            }
+        }
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+}
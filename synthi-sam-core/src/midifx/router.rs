@@ -0,0 +1,271 @@
+use crate::core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessage, MidiMessageContent, PolyphonicAftertouchEvent}};
+
+pub const MAX_ROUTER_OUTPUTS: usize = 8;
+const MIDI_CHANNELS: usize = 16;
+const MIDI_NOTES: usize = 128;
+
+const OUTPUT_PORTS: [(&str, &str); MAX_ROUTER_OUTPUTS] = [
+    ("MIDI Out 1", "midi_out_1"),
+    ("MIDI Out 2", "midi_out_2"),
+    ("MIDI Out 3", "midi_out_3"),
+    ("MIDI Out 4", "midi_out_4"),
+    ("MIDI Out 5", "midi_out_5"),
+    ("MIDI Out 6", "midi_out_6"),
+    ("MIDI Out 7", "midi_out_7"),
+    ("MIDI Out 8", "midi_out_8"),
+];
+
+/// A part of the keyboard that is sent to one output, overlapping zones create layers
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyboardZone {
+    pub enabled: bool,
+    pub input_channel: Option<u8>,  //None accepts all channels
+    pub low_key: u8,
+    pub high_key: u8,
+    pub low_velocity: f64,
+    pub high_velocity: f64,
+    pub transpose: i32,             //Semitones
+    pub octave: i32,
+    pub output_channel: Option<u8>, //None keeps the input channel
+    pub output: usize,              //Index of the output port
+}
+
+impl Default for KeyboardZone {
+    fn default() -> Self {
+        return KeyboardZone {
+            enabled: true,
+            input_channel: None,
+            low_key: 0,
+            high_key: 127,
+            low_velocity: 0.0,
+            high_velocity: 1.0,
+            transpose: 0,
+            octave: 0,
+            output_channel: None,
+            output: 0,
+        };
+    }
+}
+
+impl KeyboardZone {
+
+    /// Creates a zone for a key range that is sent to an output
+    pub fn new(low_key: u8, high_key: u8, output: usize) -> KeyboardZone {
+        return KeyboardZone {
+            low_key: low_key,
+            high_key: high_key,
+            output: output,
+            ..Default::default()
+        };
+    }
+
+    #[inline]
+    fn accepts_channel(&self, channel: u8) -> bool {
+        return self.enabled && self.input_channel.is_none_or(|c| c == channel);
+    }
+
+    #[inline]
+    fn accepts_key(&self, note: u8) -> bool {
+        return note >= self.low_key && note <= self.high_key;
+    }
+
+    #[inline]
+    fn map_note(&self, note: u8) -> Option<u8> {
+        let note = note as i32 + self.transpose + self.octave * 12;
+        return if (0..MIDI_NOTES as i32).contains(&note) { Some(note as u8) } else { None };
+    }
+
+    #[inline]
+    fn map_channel(&self, channel: u8) -> u8 {
+        return self.output_channel.unwrap_or(channel) & 0x0F;
+    }
+
+}
+
+/// Output note, channel and port of a zone for every held input key
+struct ZoneNotes {
+    notes: Box<[Option<(u8, u8, usize)>]>,
+}
+
+impl ZoneNotes {
+
+    fn new() -> ZoneNotes {
+        return ZoneNotes {
+            notes: vec![None; MIDI_CHANNELS * MIDI_NOTES].into_boxed_slice(),
+        };
+    }
+
+    #[inline(always)]
+    fn index(channel: u8, note: u8) -> usize {
+        return (channel as usize & 0x0F) * MIDI_NOTES + (note as usize & 0x7F);
+    }
+
+}
+
+/// Splits and layers the incoming MIDI into zones by key range, velocity range and channel
+///
+/// Note offs are always sent to the zones that recieved the note on, even when the zones were changed in between.
+pub struct ZoneRouter {
+    info: DeviceInfo,
+    input: NamedMidiPort,
+    outputs: Vec<NamedMidiPort>,
+    zones: Vec<KeyboardZone>,
+    zone_notes: Vec<ZoneNotes>,
+}
+
+impl ZoneRouter {
+
+    /// Creates a router with the given amount of output ports (up to 8)
+    pub fn new(outputs: usize) -> ZoneRouter {
+        return ZoneRouter {
+            info: DeviceInfo {
+                name: "Zone Router",
+                type_identifier: "synthi_sam_zone_router",
+            },
+            input: NamedMidiPort::new("MIDI In", "midi_in"),
+            outputs: OUTPUT_PORTS[..outputs.clamp(1, MAX_ROUTER_OUTPUTS)].iter().map(|(name, id)| NamedMidiPort::new(name, id)).collect(),
+            zones: Vec::new(),
+            zone_notes: Vec::new(),
+        };
+    }
+
+    #[inline(always)]
+    pub fn zones(&self) -> &[KeyboardZone] {
+        return &self.zones;
+    }
+
+    /// Adds a zone and returns it's index
+    pub fn add_zone(&mut self, zone: KeyboardZone) -> usize {
+        self.zones.push(zone);
+        self.zone_notes.push(ZoneNotes::new());
+        return self.zones.len() - 1;
+    }
+
+    /// Changes a zone, held notes are still released on their original output
+    pub fn set_zone(&mut self, index: usize, zone: KeyboardZone) {
+        if let Some(z) = self.zones.get_mut(index) {
+            *z = zone;
+        }
+    }
+
+    /// Removes a zone, notes that are still held in it are released
+    pub fn remove_zone(&mut self, index: usize) {
+        if index < self.zones.len() {
+            self.release_zone(index);
+            self.zones.remove(index);
+            self.zone_notes.remove(index);
+        }
+    }
+
+    fn release_zone(&mut self, index: usize) {
+        for slot in self.zone_notes[index].notes.iter_mut() {
+            if let Some((note, channel, output)) = slot.take() {
+                self.outputs[output].port.queue(MidiMessage::note_off(channel, note, 0.0));
+            }
+        }
+    }
+
+    fn route_note_on(&mut self, msg: &MidiMessage, note: u8, velocity: f64) {
+        for (i, zone) in self.zones.iter().enumerate() {
+            if zone.accepts_channel(msg.channel) && zone.accepts_key(note) && velocity >= zone.low_velocity && velocity <= zone.high_velocity {
+                if let Some(out_note) = zone.map_note(note).filter(|_| zone.output < self.outputs.len()) {
+                    let channel = zone.map_channel(msg.channel);
+                    let slot = &mut self.zone_notes[i].notes[ZoneNotes::index(msg.channel, note)];
+                    //Release a previous press of the key, it may have been sent to a different note or output
+                    if let Some((prev_note, prev_channel, prev_output)) = slot.replace((out_note, channel, zone.output)) {
+                        self.outputs[prev_output].port.queue(MidiMessage::note_off(prev_channel, prev_note, 0.0));
+                    }
+                    self.outputs[zone.output].port.queue(MidiMessage::note_on(channel, out_note, velocity));
+                }
+            }
+        }
+    }
+
+    fn route_note_off(&mut self, msg: &MidiMessage, note: u8, velocity: f64) {
+        for notes in self.zone_notes.iter_mut() {
+            if let Some((out_note, channel, output)) = notes.notes[ZoneNotes::index(msg.channel, note)].take() {
+                self.outputs[output].port.queue(MidiMessage::note_off(channel, out_note, velocity));
+            }
+        }
+    }
+
+    fn route_poly_aftertouch(&mut self, msg: &MidiMessage, note: u8, aftertouch: f64) {
+        for notes in self.zone_notes.iter() {
+            if let Some((out_note, channel, output)) = notes.notes[ZoneNotes::index(msg.channel, note)] {
+                self.outputs[output].port.queue(MidiMessage::from_content(channel, MidiMessageContent::PolyphonicAftertouch(PolyphonicAftertouchEvent {
+                    note: out_note,
+                    aftertouch: aftertouch,
+                })));
+            }
+        }
+    }
+
+    /// Sends a message to every output and channel of a matching zone, but only once per output channel
+    fn route_channel_message(&mut self, msg: &MidiMessage) {
+        let mut sent = [0u16; MAX_ROUTER_OUTPUTS];
+        for zone in self.zones.iter() {
+            if zone.accepts_channel(msg.channel) && zone.output < self.outputs.len() {
+                let channel = zone.map_channel(msg.channel);
+                if sent[zone.output] & (1 << channel) == 0 {
+                    sent[zone.output] |= 1 << channel;
+                    self.outputs[zone.output].port.queue(MidiMessage::from_content(channel, msg.message.clone()));
+                }
+            }
+        }
+    }
+
+}
+
+impl Device for ZoneRouter {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, _info: ProcessingInfo) {
+        self.input.port.reset();
+        for output in self.outputs.iter_mut() {
+            output.port.reset();
+        }
+        for notes in self.zone_notes.iter_mut() {
+            notes.notes.fill(None);
+        }
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        while let Some(msg) = self.input.port.pop() {
+            match msg.message {
+                MidiMessageContent::NoteOn(ref note) => self.route_note_on(&msg, note.note, note.velocity),
+                MidiMessageContent::NoteOff(ref note) => self.route_note_off(&msg, note.note, note.velocity),
+                MidiMessageContent::PolyphonicAftertouch(ref at) => self.route_poly_aftertouch(&msg, at.note, at.aftertouch),
+                //System messages aren't bound to a channel
+                MidiMessageContent::SysEx(_) | MidiMessageContent::Clock | MidiMessageContent::Start | MidiMessageContent::Continue | MidiMessageContent::Stop => {
+                    for output in self.outputs.iter_mut() {
+                        output.port.queue(msg.clone());
+                    }
+                },
+                _ => self.route_channel_message(&msg),
+            }
+        }
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return self.outputs.get_mut(index);
+    }
+
+}
@@ -0,0 +1,258 @@
+use std::fmt::Display;
+
+use crate::core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessage, MidiMessageContent, PolyphonicAftertouchEvent}};
+
+const MIDI_CHANNELS: usize = 16;
+const MIDI_NOTES: usize = 128;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    NaturalMinor,
+    HarmonicMinor,
+    MelodicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+    Custom(u16),    //Bit n is set when the note n semitones above the root is in the scale
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        return Scale::Chromatic;
+    }
+}
+
+impl Display for Scale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Scale::Chromatic => write!(f, "Chromatic"),
+            Scale::Major => write!(f, "Major"),
+            Scale::NaturalMinor => write!(f, "Natural Minor"),
+            Scale::HarmonicMinor => write!(f, "Harmonic Minor"),
+            Scale::MelodicMinor => write!(f, "Melodic Minor"),
+            Scale::Dorian => write!(f, "Dorian"),
+            Scale::Phrygian => write!(f, "Phrygian"),
+            Scale::Lydian => write!(f, "Lydian"),
+            Scale::Mixolydian => write!(f, "Mixolydian"),
+            Scale::Locrian => write!(f, "Locrian"),
+            Scale::MajorPentatonic => write!(f, "Major Pentatonic"),
+            Scale::MinorPentatonic => write!(f, "Minor Pentatonic"),
+            Scale::Blues => write!(f, "Blues"),
+            Scale::Custom(mask) => write!(f, "Custom ({:012b})", mask),
+        }
+    }
+}
+
+impl Scale {
+
+    /// Returns the notes of the scale as bits relative to the root
+    pub fn mask(&self) -> u16 {
+        let notes: &[u8] = match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+            Scale::Custom(mask) => return *mask & 0x0FFF,
+        };
+        return notes.iter().fold(0, |mask, n| mask | 1 << n);
+    }
+
+    /// Returns wether the note is in the scale starting at the root (0 is C)
+    #[inline]
+    pub fn contains(&self, root: u8, note: i32) -> bool {
+        return self.mask() & (1 << (note - root as i32).rem_euclid(12)) != 0;
+    }
+
+}
+
+/// What happens to notes that aren't in the scale
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QuantizeMode {
+    Nearest,    //Prefers the lower note when both are equally far away
+    Up,
+    Down,
+    Drop,       //Notes outside the scale are filtered out
+}
+
+impl Default for QuantizeMode {
+    fn default() -> Self {
+        return QuantizeMode::Nearest;
+    }
+}
+
+impl Display for QuantizeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            QuantizeMode::Nearest => "Nearest",
+            QuantizeMode::Up => "Up",
+            QuantizeMode::Down => "Down",
+            QuantizeMode::Drop => "Drop",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct ScaleConfig {
+    pub root: u8,               //0 (C) to 11 (B)
+    pub scale: Scale,
+    pub mode: QuantizeMode,
+    pub scale_transpose: i32,   //Steps within the scale, e.g. 2 turns C into E in C major
+    pub transpose: i32,         //Semitones applied after quantizing
+}
+
+impl ScaleConfig {
+
+    /// Maps an input note to the output note, None if it is dropped or out of range
+    pub fn map_note(&self, note: u8) -> Option<u8> {
+        let root = self.root % 12;
+        let mask = self.scale.mask();
+        if mask == 0 {
+            return None;
+        }
+        let mut note = note as i32;
+        if !self.scale.contains(root, note) {
+            note = match self.mode {
+                QuantizeMode::Drop => return None,
+                QuantizeMode::Up => Self::next(self.scale, root, note, 1),
+                QuantizeMode::Down => Self::next(self.scale, root, note, -1),
+                QuantizeMode::Nearest => {
+                    let down = Self::next(self.scale, root, note, -1);
+                    let up = Self::next(self.scale, root, note, 1);
+                    if up - note < note - down { up } else { down }
+                },
+            };
+        }
+        let direction = self.scale_transpose.signum();
+        for _ in 0..self.scale_transpose.abs() {
+            note = Self::next(self.scale, root, note + direction, direction);
+        }
+        note += self.transpose;
+        return if (0..MIDI_NOTES as i32).contains(&note) { Some(note as u8) } else { None };
+    }
+
+    /// Returns the first note of the scale starting at the given note in the direction
+    #[inline]
+    fn next(scale: Scale, root: u8, note: i32, direction: i32) -> i32 {
+        let mut note = note;
+        while !scale.contains(root, note) {
+            note += direction;
+        }
+        return note;
+    }
+
+}
+
+/// Quantizes the played notes to a scale and transposes them chromatically or within the scale
+pub struct ScaleQuantizer {
+    info: DeviceInfo,
+    input: NamedMidiPort,
+    output: NamedMidiPort,
+    pub config: ScaleConfig,
+    held: Box<[Option<u8>]>,    //Output note for every input channel and note
+}
+
+impl ScaleQuantizer {
+
+    pub fn new() -> ScaleQuantizer {
+        return ScaleQuantizer {
+            info: DeviceInfo {
+                name: "Scale Quantizer",
+                type_identifier: "synthi_sam_scale_quantizer",
+            },
+            input: NamedMidiPort::new("MIDI In", "midi_in"),
+            output: NamedMidiPort::new("MIDI Out", "midi_out"),
+            config: ScaleConfig::default(),
+            held: vec![None; MIDI_CHANNELS * MIDI_NOTES].into_boxed_slice(),
+        };
+    }
+
+    #[inline(always)]
+    fn index(channel: u8, note: u8) -> usize {
+        return (channel as usize & 0x0F) * MIDI_NOTES + (note as usize & 0x7F);
+    }
+
+}
+
+impl Device for ScaleQuantizer {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, _info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+        self.held.fill(None);
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        while let Some(msg) = self.input.port.pop() {
+            match msg.message {
+                MidiMessageContent::NoteOn(ref note) => {
+                    let index = Self::index(msg.channel, note.note);
+                    if let Some(previous) = self.held[index].take() {
+                        self.output.port.queue(MidiMessage::note_off(msg.channel, previous, 0.0));
+                    }
+                    if let Some(out) = self.config.map_note(note.note) {
+                        self.held[index] = Some(out);
+                        self.output.port.queue(MidiMessage::note_on(msg.channel, out, note.velocity));
+                    }
+                },
+                //Released with the note it was pressed with, even if the scale changed in between
+                MidiMessageContent::NoteOff(ref note) => {
+                    if let Some(out) = self.held[Self::index(msg.channel, note.note)].take() {
+                        self.output.port.queue(MidiMessage::note_off(msg.channel, out, note.velocity));
+                    }
+                },
+                MidiMessageContent::PolyphonicAftertouch(ref at) => {
+                    if let Some(out) = self.held[Self::index(msg.channel, at.note)] {
+                        self.output.port.queue(MidiMessage::from_content(msg.channel, MidiMessageContent::PolyphonicAftertouch(PolyphonicAftertouchEvent {
+                            note: out,
+                            aftertouch: at.aftertouch,
+                        })));
+                    }
+                },
+                _ => self.output.port.queue(msg),
+            }
+        }
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+}
@@ -0,0 +1,508 @@
+use std::{fmt::Display, str::FromStr, path::Path};
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessage, MidiMessageContent}}, util::{tempo::{NoteDivision, ClockTempo, CLOCK_PPQN}, random::Random}};
+
+pub const MAX_STEPS: usize = 64;
+pub const MAX_PATTERNS: usize = 128;
+pub const MAX_RATCHETS: u8 = 8;
+const BAR_BEATS: f64 = 4.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SequencerStep {
+    pub enabled: bool,
+    pub note: u8,
+    pub velocity: f64,
+    pub gate: f64,          //Note length relative to the step (or ratchet) length
+    pub probability: f64,   //Chance from 0 to 1 that the step is played
+    pub ratchet: u8,        //Number of repeats within the step
+    pub slide: bool,        //Holds the note until the next one started
+    pub cc: Option<f64>,    //Value sent to the control of the pattern
+}
+
+impl Default for SequencerStep {
+    fn default() -> Self {
+        return SequencerStep {
+            enabled: false,
+            note: 60,
+            velocity: 0.8,
+            gate: 0.5,
+            probability: 1.0,
+            ratchet: 1,
+            slide: false,
+            cc: None,
+        };
+    }
+}
+
+impl Display for SequencerStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {} {} {} {} ", if self.enabled { "on" } else { "off" }, self.note, self.velocity, self.gate, self.probability, self.ratchet, if self.slide { "slide" } else { "-" })?;
+        return match self.cc {
+            Some(cc) => write!(f, "{}", cc),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+impl FromStr for SequencerStep {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let enabled = match parts.next() {
+            Some("on") => true,
+            Some("off") => false,
+            _ => return Err("Invalid step state!"),
+        };
+        let note = parts.next().ok_or("Missing step note!")?.parse::<u8>().map_err(|_| "Invalid step note!")?;
+        let velocity = parts.next().ok_or("Missing step velocity!")?.parse::<f64>().map_err(|_| "Invalid step velocity!")?;
+        let gate = parts.next().ok_or("Missing step gate!")?.parse::<f64>().map_err(|_| "Invalid step gate!")?;
+        let probability = parts.next().ok_or("Missing step probability!")?.parse::<f64>().map_err(|_| "Invalid step probability!")?;
+        let ratchet = parts.next().ok_or("Missing step ratchet!")?.parse::<u8>().map_err(|_| "Invalid step ratchet!")?;
+        let slide = match parts.next() {
+            Some("slide") => true,
+            Some("-") => false,
+            _ => return Err("Invalid step slide!"),
+        };
+        let cc = match parts.next() {
+            Some("-") | None => None,
+            Some(cc) => Some(cc.parse::<f64>().map_err(|_| "Invalid step cc!")?),
+        };
+        if note > 127 {
+            return Err("Invalid step note!");
+        }
+        if !(1..=MAX_RATCHETS).contains(&ratchet) {
+            return Err("Invalid step ratchet!");
+        }
+        return Ok(SequencerStep {
+            enabled: enabled,
+            note: note,
+            velocity: velocity.clamp(0.0, 1.0),
+            gate: gate.clamp(0.0, 1.0),
+            probability: probability.clamp(0.0, 1.0),
+            ratchet: ratchet,
+            slide: slide,
+            cc: cc.map(|cc| cc.clamp(0.0, 1.0)),
+        });
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SequencerPattern {
+    pub steps: [SequencerStep; MAX_STEPS],
+    pub length: usize,      //Number of played steps from 1 to 64
+    pub rate: NoteDivision,
+    pub cc_control: u8,
+}
+
+impl Default for SequencerPattern {
+    fn default() -> Self {
+        return SequencerPattern {
+            steps: [SequencerStep::default(); MAX_STEPS],
+            length: 16,
+            rate: NoteDivision::new(1, 16),
+            cc_control: 74,
+        };
+    }
+}
+
+impl Display for SequencerPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "pattern {} {} {}", self.length, self.rate, self.cc_control)?;
+        //Steps after the end are kept if they were edited
+        let default = SequencerStep::default();
+        let count = self.steps.iter().rposition(|s| *s != default).map_or(0, |i| i + 1).max(self.length);
+        for step in self.steps[..count].iter() {
+            writeln!(f, "step {}", step)?;
+        }
+        return Ok(());
+    }
+}
+
+impl SequencerPattern {
+
+    fn parse_header(s: &str) -> Result<SequencerPattern, &'static str> {
+        let mut parts = s.split_whitespace();
+        let length = parts.next().ok_or("Missing pattern length!")?.parse::<usize>().map_err(|_| "Invalid pattern length!")?;
+        let rate = parts.next().ok_or("Missing pattern rate!")?.parse()?;
+        let cc_control = parts.next().ok_or("Missing pattern cc control!")?.parse::<u8>().map_err(|_| "Invalid pattern cc control!")?;
+        if !(1..=MAX_STEPS).contains(&length) {
+            return Err("Invalid pattern length!");
+        }
+        return Ok(SequencerPattern {
+            length: length,
+            rate: rate,
+            cc_control: cc_control & 0x7F,
+            ..Default::default()
+        });
+    }
+
+}
+
+/// All patterns of a sequencer, stored as text with a pattern line followed by it's step lines:
+///
+/// ```text
+/// pattern 16 1/16 74
+/// step on 60 0.8 0.5 1 1 - 0.25
+/// step off 60 0.8 0.5 1 1 slide -
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternBank {
+    pub patterns: Vec<SequencerPattern>,
+}
+
+impl Default for PatternBank {
+    fn default() -> Self {
+        return PatternBank {
+            patterns: vec![SequencerPattern::default()],
+        };
+    }
+}
+
+impl Display for PatternBank {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for pattern in self.patterns.iter() {
+            write!(f, "{}", pattern)?;
+        }
+        return Ok(());
+    }
+}
+
+impl FromStr for PatternBank {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut patterns: Vec<SequencerPattern> = Vec::new();
+        let mut step = 0;
+        for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            if let Some(header) = line.strip_prefix("pattern ") {
+                if patterns.len() >= MAX_PATTERNS {
+                    return Err("Too many patterns!");
+                }
+                patterns.push(SequencerPattern::parse_header(header)?);
+                step = 0;
+            }
+            else if let Some(s) = line.strip_prefix("step ") {
+                let pattern = patterns.last_mut().ok_or("Step outside of a pattern!")?;
+                *pattern.steps.get_mut(step).ok_or("Too many steps!")? = s.parse()?;
+                step += 1;
+            }
+            else {
+                return Err("Invalid pattern line!");
+            }
+        }
+        if patterns.is_empty() {
+            return Err("No patterns!");
+        }
+        return Ok(PatternBank { patterns: patterns });
+    }
+}
+
+impl PatternBank {
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), &'static str> {
+        return std::fs::write(path, self.to_string()).map_err(|_| "Couldn't write pattern file!");
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PatternBank, &'static str> {
+        return std::fs::read_to_string(path).map_err(|_| "Couldn't read pattern file!")?.parse();
+    }
+
+}
+
+/// Where the sequencer takes it's timing from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SequencerSync {
+    Tempo,  //Runs at the configured bpm
+    Clock,  //Steps with incoming MIDI clock
+}
+
+impl Default for SequencerSync {
+    fn default() -> Self {
+        return SequencerSync::Tempo;
+    }
+}
+
+impl Display for SequencerSync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SequencerSync::Tempo => "Tempo",
+            SequencerSync::Clock => "MIDI Clock",
+        })
+    }
+}
+
+/// Plays the steps of a pattern at the configured tempo or following MIDI clock
+///
+/// The sequencer runs while it is started, either by calling start or by a MIDI start message.
+/// In clock mode the position is set by the clock messages and interpolated in between using the measured tempo.
+/// Program changes select the pattern that is played from the next bar on.
+/// Clock and transport messages are passed on, so following devices stay in sync.
+pub struct StepSequencer {
+    info: DeviceInfo,
+    input: NamedMidiPort,
+    output: NamedMidiPort,
+    pub bank: PatternBank,
+    pub bpm: f64,
+    pub sync: SequencerSync,
+    pub channel: u8,
+    time_step: f64,
+    random: Random,
+    running: bool,
+    position: f64,                  //Beats since the start
+    pattern: usize,
+    next_pattern: Option<usize>,
+    step: usize,
+    next_step_pos: f64,
+    bar: i64,
+    ratchets_left: u8,
+    ratchet_pos: f64,
+    ratchet_length: f64,
+    ratchet_step: SequencerStep,
+    note: Option<u8>,
+    hold: bool,                     //The current note slides into the next one
+    note_off_pos: f64,
+    //Clock
+    clock_pos: f64,
+    clock_rate: f64,                //Beats per second
+    clock_tempo: ClockTempo,
+}
+
+impl StepSequencer {
+
+    pub fn new() -> StepSequencer {
+        return StepSequencer {
+            info: DeviceInfo {
+                name: "Step Sequencer",
+                type_identifier: "synthi_sam_step_sequencer",
+            },
+            input: NamedMidiPort::new("MIDI In", "midi_in"),
+            output: NamedMidiPort::new("MIDI Out", "midi_out"),
+            bank: PatternBank::default(),
+            bpm: 120.0,
+            sync: SequencerSync::Tempo,
+            channel: 0,
+            time_step: 1.0/44100.0,
+            random: Random::default(),
+            running: false,
+            position: 0.0,
+            pattern: 0,
+            next_pattern: None,
+            step: 0,
+            next_step_pos: 0.0,
+            bar: -1,
+            ratchets_left: 0,
+            ratchet_pos: 0.0,
+            ratchet_length: 0.0,
+            ratchet_step: SequencerStep::default(),
+            note: None,
+            hold: false,
+            note_off_pos: 0.0,
+            clock_pos: 0.0,
+            clock_rate: 2.0,
+            clock_tempo: ClockTempo::new(),
+        };
+    }
+
+    /// Starts playing from the first step
+    pub fn start(&mut self) {
+        self.stop_note();
+        self.running = true;
+        self.position = 0.0;
+        self.clock_pos = 0.0;
+        self.step = 0;
+        self.next_step_pos = 0.0;
+        self.bar = -1;
+        self.ratchets_left = 0;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.ratchets_left = 0;
+        self.stop_note();
+    }
+
+    #[inline(always)]
+    pub fn is_running(&self) -> bool {
+        return self.running;
+    }
+
+    #[inline(always)]
+    pub fn pattern(&self) -> usize {
+        return self.pattern;
+    }
+
+    /// Selects the pattern that is played from the next bar on, while stopped it is selected immediately
+    pub fn select_pattern(&mut self, index: usize) {
+        if index < self.bank.patterns.len() {
+            if self.running {
+                self.next_pattern = Some(index);
+            }
+            else {
+                self.pattern = index;
+                self.step = 0;
+            }
+        }
+    }
+
+    fn stop_note(&mut self) {
+        if let Some(note) = self.note.take() {
+            self.output.port.queue(MidiMessage::note_off(self.channel, note, 0.0));
+        }
+        self.hold = false;
+    }
+
+    fn clock(&mut self, time: f64) {
+        self.clock_tempo.tick(time);
+        if let Some(bpm) = self.clock_tempo.bpm() {
+            self.clock_rate = bpm/60.0;
+        }
+        if self.running && self.sync == SequencerSync::Clock {
+            self.clock_pos += 1.0/CLOCK_PPQN;
+            self.position = self.clock_pos;
+        }
+    }
+
+    fn trigger(&mut self, step: SequencerStep, length: f64, last: bool) {
+        let previous = self.note.take();
+        if self.hold {
+            //Legato, the new note starts before the previous one ends
+            self.output.port.queue(MidiMessage::note_on(self.channel, step.note, step.velocity));
+            if let Some(previous) = previous.filter(|n| *n != step.note) {
+                self.output.port.queue(MidiMessage::note_off(self.channel, previous, 0.0));
+            }
+        }
+        else {
+            if let Some(previous) = previous {
+                self.output.port.queue(MidiMessage::note_off(self.channel, previous, 0.0));
+            }
+            self.output.port.queue(MidiMessage::note_on(self.channel, step.note, step.velocity));
+        }
+        self.note = Some(step.note);
+        self.hold = step.slide && last;
+        self.note_off_pos = self.position + length * step.gate;
+    }
+
+    fn play_step(&mut self) {
+        //Pattern changes wait for the first step of a bar
+        let bar = (self.next_step_pos/BAR_BEATS + 1e-9).floor() as i64;
+        if bar != self.bar {
+            self.bar = bar;
+            if let Some(next) = self.next_pattern.take() {
+                self.pattern = next;
+                self.step = 0;
+            }
+        }
+        let pattern = &self.bank.patterns[self.pattern.min(self.bank.patterns.len() - 1)];
+        let length = pattern.rate.beats();
+        let cc_control = pattern.cc_control;
+        let pattern_length = pattern.length.clamp(1, MAX_STEPS);
+        let step = pattern.steps[self.step % pattern_length];
+
+        if let Some(cc) = step.cc {
+            self.output.port.queue(MidiMessage::control_change(self.channel, cc_control, cc));
+        }
+        if step.enabled && self.random.next_f64() < step.probability {
+            let ratchets = step.ratchet.clamp(1, MAX_RATCHETS);
+            self.ratchet_length = length/(ratchets as f64);
+            self.ratchet_pos = self.next_step_pos + self.ratchet_length;
+            self.ratchets_left = ratchets - 1;
+            self.ratchet_step = step;
+            self.trigger(step, self.ratchet_length, ratchets == 1);
+        }
+        else if self.hold {
+            //A slide into a pause ends the note
+            self.ratchets_left = 0;
+            self.stop_note();
+        }
+
+        self.step = (self.step + 1) % pattern_length;
+        self.next_step_pos += length;
+    }
+
+}
+
+impl Device for StepSequencer {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.time_step = info.time_step;
+        self.input.port.reset();
+        self.output.port.reset();
+        self.note = None;
+        self.hold = false;
+        self.running = false;
+        self.clock_tempo.reset();
+    }
+
+    fn process(&mut self, info: SampleInfo) {
+        while let Some(msg) = self.input.port.pop() {
+            match msg.message {
+                MidiMessageContent::ProgramChange(ref program) => self.select_pattern(program.program as usize),
+                MidiMessageContent::Start => {
+                    self.start();
+                    self.output.port.queue(msg);
+                },
+                MidiMessageContent::Continue => {
+                    self.running = true;
+                    self.output.port.queue(msg);
+                },
+                MidiMessageContent::Stop => {
+                    self.stop();
+                    self.output.port.queue(msg);
+                },
+                MidiMessageContent::Clock => {
+                    self.clock(info.time);
+                    self.output.port.queue(msg);
+                },
+                _ => self.output.port.queue(msg),
+            }
+        }
+        if !self.running || self.bank.patterns.is_empty() {
+            return;
+        }
+
+        if self.note.is_some() && !self.hold && self.position >= self.note_off_pos {
+            self.stop_note();
+        }
+        if self.ratchets_left > 0 && self.position >= self.ratchet_pos {
+            self.ratchets_left -= 1;
+            self.ratchet_pos += self.ratchet_length;
+            self.trigger(self.ratchet_step, self.ratchet_length, self.ratchets_left == 0);
+        }
+        if self.position >= self.next_step_pos {
+            self.play_step();
+        }
+        self.position = match self.sync {
+            SequencerSync::Tempo => self.position + self.bpm.max(1.0)/60.0 * self.time_step,
+            //Never run ahead of the next clock tick
+            SequencerSync::Clock => (self.position + self.clock_rate * self.time_step).min(self.clock_pos + 1.0/CLOCK_PPQN),
+        };
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+}
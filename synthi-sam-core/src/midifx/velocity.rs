@@ -0,0 +1,134 @@
+use std::fmt::Display;
+
+use crate::core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessage, MidiMessageContent}};
+
+const CURVE_STEEPNESS: f64 = 3.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VelocityCurve {
+    Linear,
+    Fixed(f64),                 //Every note gets the same velocity
+    Compress(f64),              //Lifts soft notes, 0 is linear and 1 is the strongest curve
+    Expand(f64),                //Lowers soft notes, 0 is linear and 1 is the strongest curve
+    Custom(Vec<(f64, f64)>),    //Input and output points sorted by input, linearly interpolated
+}
+
+impl Default for VelocityCurve {
+    fn default() -> Self {
+        return VelocityCurve::Linear;
+    }
+}
+
+impl Display for VelocityCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            VelocityCurve::Linear => write!(f, "Linear"),
+            VelocityCurve::Fixed(v) => write!(f, "Fixed ({})", v),
+            VelocityCurve::Compress(a) => write!(f, "Compress ({})", a),
+            VelocityCurve::Expand(a) => write!(f, "Expand ({})", a),
+            VelocityCurve::Custom(points) => write!(f, "Custom ({} points)", points.len()),
+        }
+    }
+}
+
+impl VelocityCurve {
+
+    /// Maps a velocity from 0 to 1 through the curve
+    pub fn apply(&self, velocity: f64) -> f64 {
+        let velocity = velocity.clamp(0.0, 1.0);
+        return match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Fixed(v) => *v,
+            VelocityCurve::Compress(a) => velocity.powf(1.0/(1.0 + CURVE_STEEPNESS * a.clamp(0.0, 1.0))),
+            VelocityCurve::Expand(a) => velocity.powf(1.0 + CURVE_STEEPNESS * a.clamp(0.0, 1.0)),
+            VelocityCurve::Custom(points) => {
+                match points.iter().position(|(x, _)| *x >= velocity) {
+                    None => points.last().map_or(velocity, |(_, y)| *y),
+                    Some(0) => points[0].1,
+                    Some(i) => {
+                        let (x1, y1) = points[i - 1];
+                        let (x2, y2) = points[i];
+                        if x2 > x1 { y1 + (y2 - y1) * (velocity - x1)/(x2 - x1) } else { y2 }
+                    }
+                }
+            },
+        }.clamp(0.0, 1.0);
+    }
+
+}
+
+/// Remaps the velocity of note ons through a curve into an output range
+pub struct VelocityCurveDevice {
+    info: DeviceInfo,
+    input: NamedMidiPort,
+    output: NamedMidiPort,
+    pub curve: VelocityCurve,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl VelocityCurveDevice {
+
+    pub fn new() -> VelocityCurveDevice {
+        return VelocityCurveDevice {
+            info: DeviceInfo {
+                name: "Velocity Curve",
+                type_identifier: "synthi_sam_velocity_curve",
+            },
+            input: NamedMidiPort::new("MIDI In", "midi_in"),
+            output: NamedMidiPort::new("MIDI Out", "midi_out"),
+            curve: VelocityCurve::Linear,
+            min: 0.0,
+            max: 1.0,
+        };
+    }
+
+}
+
+impl Device for VelocityCurveDevice {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, _info: ProcessingInfo) {
+        self.input.port.reset();
+        self.output.port.reset();
+    }
+
+    fn process(&mut self, _info: SampleInfo) {
+        while let Some(msg) = self.input.port.pop() {
+            match msg.message {
+                MidiMessageContent::NoteOn(ref note) => {
+                    //Keep a tiny velocity so the note on doesn't turn into a note off
+                    let velocity = (self.min + (self.max - self.min) * self.curve.apply(note.velocity)).clamp(1.0/127.0, 1.0);
+                    self.output.port.queue(MidiMessage::note_on(msg.channel, note.note, velocity));
+                },
+                _ => self.output.port.queue(msg),
+            }
+        }
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.input),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+}
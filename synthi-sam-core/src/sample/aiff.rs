@@ -0,0 +1,211 @@
+use super::SampleBuffer;
+
+#[derive(Copy, Clone, PartialEq)]
+enum AiffEncoding {
+    BigEndian,
+    LittleEndian,   //"sowt" compression of AIFF-C
+    Float32,
+    Float64,
+}
+
+struct CommonChunk {
+    channels: usize,
+    frames: usize,
+    bits: usize,
+    sample_rate: f64,
+    encoding: AiffEncoding,
+}
+
+#[inline]
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    return data.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+}
+
+#[inline]
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    return data.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+}
+
+/// Converts an 80 bit IEEE 754 extended precision number
+fn read_extended(data: &[u8]) -> f64 {
+    let exponent = (((data[0] as i32) & 0x7F) << 8) | data[1] as i32;
+    let mantissa = u64::from_be_bytes([data[2], data[3], data[4], data[5], data[6], data[7], data[8], data[9]]);
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    let value = mantissa as f64 * f64::from(2.0).powi(exponent - 16383 - 63);
+    return if data[0] & 0x80 != 0 { -value } else { value };
+}
+
+fn parse_common(chunk: &[u8], compressed: bool) -> Result<CommonChunk, &'static str> {
+    if chunk.len() < 18 {
+        return Err("Invalid AIFF common chunk");
+    }
+    let bits = read_u16(chunk, 6).unwrap_or(0) as usize;
+    let encoding = if compressed {
+        match chunk.get(18..22) {
+            Some(b"NONE") | Some(b"twos") => AiffEncoding::BigEndian,
+            Some(b"sowt") => AiffEncoding::LittleEndian,
+            Some(b"fl32") | Some(b"FL32") => AiffEncoding::Float32,
+            Some(b"fl64") | Some(b"FL64") => AiffEncoding::Float64,
+            _ => return Err("Unsupported AIFF-C compression"),
+        }
+    }
+    else {
+        AiffEncoding::BigEndian
+    };
+    let bits = match encoding {
+        AiffEncoding::Float32 => 32,
+        AiffEncoding::Float64 => 64,
+        _ => bits,
+    };
+    if bits == 0 || (bits > 32 && encoding != AiffEncoding::Float64) {
+        return Err("Unsupported AIFF bit depth");
+    }
+    return Ok(CommonChunk {
+        channels: read_u16(chunk, 0).unwrap_or(0) as usize,
+        frames: read_u32(chunk, 2).unwrap_or(0) as usize,
+        bits: bits,
+        sample_rate: read_extended(&chunk[8..18]),
+        encoding: encoding,
+    });
+}
+
+/// Decodes an AIFF or uncompressed AIFF-C file
+pub fn decode(data: &[u8]) -> Result<SampleBuffer, &'static str> {
+    let compressed = match data.get(8..12) {
+        Some(b"AIFF") => false,
+        Some(b"AIFC") => true,
+        _ => return Err("Invalid AIFF file"),
+    };
+    let mut common = None;
+    let mut sound = None;
+    let mut pos = 12;
+    while let (Some(id), Some(size)) = (data.get(pos..pos + 4), read_u32(data, pos + 4)) {
+        let start = pos + 8;
+        let end = (start + size as usize).min(data.len());
+        match id {
+            b"COMM" => common = Some(parse_common(&data[start..end], compressed)?),
+            b"SSND" => {
+                let offset = read_u32(data, start).ok_or("Invalid AIFF sound chunk")? as usize;
+                sound = Some(&data[(start + 8 + offset).min(end)..end]);
+            },
+            _ => {},
+        }
+        //Chunks are padded to an even length
+        pos = start + size as usize + (size as usize & 1);
+    }
+
+    let common = common.ok_or("AIFF file has no common chunk")?;
+    let sound = sound.ok_or("AIFF file has no sound chunk")?;
+    if common.channels == 0 {
+        return Err("Sample has no channels");
+    }
+    let bytes = common.bits.div_ceil(8);
+    let frames = common.frames.min(sound.len()/(bytes * common.channels));
+    let samples: Vec<f32> = sound.chunks_exact(bytes).take(frames * common.channels).map(|b| {
+        match common.encoding {
+            AiffEncoding::Float32 => f32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+            AiffEncoding::Float64 => f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32,
+            AiffEncoding::BigEndian | AiffEncoding::LittleEndian => {
+                //Samples are left justified, so reading them into the top of an i32 scales them to the same range
+                let mut value: u32 = 0;
+                for i in 0..bytes {
+                    let byte = if common.encoding == AiffEncoding::BigEndian { b[i] } else { b[bytes - 1 - i] };
+                    value |= (byte as u32) << (24 - i * 8);
+                }
+                (value as i32 as f64/2147483648.0) as f32
+            },
+        }
+    }).collect();
+    return SampleBuffer::from_interleaved(&samples, common.channels, common.sample_rate.round() as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 80 bit extended representation of a whole sample rate
+    fn extended(rate: u32) -> [u8; 10] {
+        let exponent = 31 - rate.leading_zeros();
+        let mut data = [0; 10];
+        data[0..2].copy_from_slice(&((16383 + exponent) as u16).to_be_bytes());
+        data[2..10].copy_from_slice(&((rate as u64) << (63 - exponent)).to_be_bytes());
+        return data;
+    }
+
+    /// Builds an AIFF file, AIFF-C if a compression type is given
+    fn aiff(compression: Option<&[u8; 4]>, channels: u16, bits: u16, sound: &[u8]) -> Vec<u8> {
+        let bytes = (bits as usize).div_ceil(8);
+        let frames = (sound.len()/(bytes * channels as usize)) as u32;
+        let mut comm = Vec::new();
+        comm.extend_from_slice(&channels.to_be_bytes());
+        comm.extend_from_slice(&frames.to_be_bytes());
+        comm.extend_from_slice(&bits.to_be_bytes());
+        comm.extend_from_slice(&extended(44100));
+        if let Some(compression) = compression {
+            comm.extend_from_slice(compression);
+            comm.extend_from_slice(&[0, 0]);   //Empty name, padded to an even length
+        }
+        let mut data = Vec::new();
+        data.extend_from_slice(if compression.is_some() { b"AIFC" } else { b"AIFF" });
+        data.extend_from_slice(b"COMM");
+        data.extend_from_slice(&(comm.len() as u32).to_be_bytes());
+        data.extend_from_slice(&comm);
+        data.extend_from_slice(b"SSND");
+        data.extend_from_slice(&(sound.len() as u32 + 8).to_be_bytes());
+        data.extend_from_slice(&[0; 8]);       //Offset and block size
+        data.extend_from_slice(sound);
+        let mut file = b"FORM".to_vec();
+        file.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        file.extend_from_slice(&data);
+        return file;
+    }
+
+    #[test]
+    fn decode_16_bit() {
+        let buffer = decode(&aiff(None, 2, 16, &[0x40, 0x00, 0xC0, 0x00, 0x7F, 0xFF, 0x80, 0x00])).unwrap();
+        assert_eq!(buffer.sample_rate(), 44100);
+        assert_eq!(buffer.channel(0), &[0.5, 32767.0/32768.0]);
+        assert_eq!(buffer.channel(1), &[-0.5, -1.0]);
+    }
+
+    #[test]
+    fn decode_24_bit() {
+        let buffer = decode(&aiff(None, 1, 24, &[0x40, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x01])).unwrap();
+        assert_eq!(buffer.channel(0), &[0.5, -0.5, 1.0/8388608.0]);
+    }
+
+    #[test]
+    fn decode_sowt() {
+        let buffer = decode(&aiff(Some(b"sowt"), 1, 16, &[0x00, 0x40, 0x00, 0xC0])).unwrap();
+        assert_eq!(buffer.channel(0), &[0.5, -0.5]);
+    }
+
+    #[test]
+    fn decode_float() {
+        let mut sound = Vec::new();
+        sound.extend_from_slice(&0.25f32.to_be_bytes());
+        sound.extend_from_slice(&(-0.75f32).to_be_bytes());
+        let buffer = decode(&aiff(Some(b"fl32"), 1, 32, &sound)).unwrap();
+        assert_eq!(buffer.channel(0), &[0.25, -0.75]);
+
+        let mut sound = Vec::new();
+        sound.extend_from_slice(&0.5f64.to_be_bytes());
+        let buffer = decode(&aiff(Some(b"fl64"), 1, 64, &sound)).unwrap();
+        assert_eq!(buffer.channel(0), &[0.5]);
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let file = aiff(None, 1, 16, &[0x40, 0x00, 0xC0, 0x00, 0x20, 0x00]);
+        //Frames cut off at the end are dropped, including a half frame
+        let buffer = decode(&file[..file.len() - 3]).unwrap();
+        assert_eq!(buffer.channel(0), &[0.5]);
+        //A cut inside the common chunk or before it is an error
+        assert!(decode(&file[..30]).is_err());
+        assert!(decode(&file[..10]).is_err());
+        assert!(decode(&aiff(Some(b"ulaw"), 1, 8, &[0])).is_err());
+    }
+
+}
@@ -0,0 +1,12 @@
+use claxon::FlacReader;
+
+use super::SampleBuffer;
+
+pub fn decode(data: &[u8]) -> Result<SampleBuffer, &'static str> {
+    let mut reader = FlacReader::new(data).map_err(|_| "Invalid FLAC file")?;
+    let info = reader.streaminfo();
+    let scale = 1.0/f64::from(2.0).powi(info.bits_per_sample as i32 - 1);
+    let samples: Result<Vec<f32>, claxon::Error> = reader.samples().map(|s| s.map(|s| (s as f64 * scale) as f32)).collect();
+    let samples = samples.map_err(|_| "Invalid FLAC sample data")?;
+    return SampleBuffer::from_interleaved(&samples, info.channels as usize, info.sample_rate);
+}
@@ -0,0 +1,184 @@
+pub mod wav;
+pub mod aiff;
+pub mod flac;
+pub mod player;
+pub mod sampler;
+pub mod sfz;
+pub mod sfz_sampler;
+pub mod sf2;
+pub mod sf2_sampler;
+
+use crate::dsp::interpolation::windowed_sinc;
+
+/// Zero crossings of the resampling kernel on each side, higher than for playback since resampling isn't done in real time
+const RESAMPLE_HALF_WIDTH: f64 = 16.0;
+
+/// Converts a velocity from 0 to 1 to the MIDI range used by the velocity ranges of instruments
+#[inline]
+pub fn midi_velocity(velocity: f64) -> u8 {
+    return ((velocity * 127.0).round() as u8).clamp(1, 127);
+}
+
+/// Decoded audio file, every channel is stored in it's own buffer
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SampleBuffer {
+    channels: Vec<Vec<f32>>,
+    sample_rate: u32,
+}
+
+impl SampleBuffer {
+
+    /// Creates a buffer from channels of the same length
+    pub fn new(channels: Vec<Vec<f32>>, sample_rate: u32) -> Result<SampleBuffer, &'static str> {
+        if channels.is_empty() {
+            return Err("Sample has no channels");
+        }
+        if channels.iter().any(|c| c.len() != channels[0].len()) {
+            return Err("Channels have different lengths");
+        }
+        if sample_rate == 0 {
+            return Err("Invalid sample rate");
+        }
+        return Ok(SampleBuffer {
+            channels: channels,
+            sample_rate: sample_rate,
+        });
+    }
+
+    /// Creates a buffer from interleaved frames
+    pub fn from_interleaved(data: &[f32], channels: usize, sample_rate: u32) -> Result<SampleBuffer, &'static str> {
+        if channels == 0 {
+            return Err("Sample has no channels");
+        }
+        let frames = data.len()/channels;
+        let buffers = (0..channels).map(|c| (0..frames).map(|i| data[i * channels + c]).collect()).collect();
+        return SampleBuffer::new(buffers, sample_rate);
+    }
+
+    /// Decodes a WAV, AIFF or FLAC file, the format is detected from the header
+    pub fn decode(data: &[u8]) -> Result<SampleBuffer, &'static str> {
+        return match data.get(0..4) {
+            Some(b"RIFF") => wav::decode(data),
+            Some(b"FORM") => aiff::decode(data),
+            Some(b"fLaC") => flac::decode(data),
+            _ => Err("Unknown audio file format"),
+        }
+    }
+
+    /// Loads and decodes a file, this blocks so it shouldn't be called from the audio thread
+    pub fn load(path: &str) -> Result<SampleBuffer, &'static str> {
+        let data = std::fs::read(path).map_err(|_| "Couldn't read sample file")?;
+        return SampleBuffer::decode(&data);
+    }
+
+    #[inline(always)]
+    pub fn sample_rate(&self) -> u32 {
+        return self.sample_rate;
+    }
+
+    #[inline(always)]
+    pub fn channel_count(&self) -> usize {
+        return self.channels.len();
+    }
+
+    /// Length in frames
+    #[inline(always)]
+    pub fn frames(&self) -> usize {
+        return self.channels.first().map_or(0, |c| c.len());
+    }
+
+    /// Length in seconds
+    #[inline]
+    pub fn duration(&self) -> f64 {
+        return self.frames() as f64/self.sample_rate as f64;
+    }
+
+    #[inline(always)]
+    pub fn channel(&self, channel: usize) -> &[f32] {
+        return &self.channels[channel];
+    }
+
+    /// Returns a sample or silence outside of the buffer, missing channels repeat the last one (e.g. mono on both sides)
+    #[inline(always)]
+    pub fn get(&self, channel: usize, index: isize) -> f64 {
+        let data = &self.channels[channel.min(self.channels.len() - 1)];
+        return if index >= 0 && (index as usize) < data.len() { data[index as usize] as f64 } else { 0.0 };
+    }
+
+    /// Converts the buffer to another sample rate with a windowed sinc filter
+    ///
+    /// When the rate is lowered the cutoff is lowered as well to remove frequencies that would alias.
+    pub fn resample(&self, sample_rate: u32) -> SampleBuffer {
+        if sample_rate == self.sample_rate || sample_rate == 0 {
+            return self.clone();
+        }
+        let ratio = sample_rate as f64/self.sample_rate as f64;
+        let cutoff = ratio.min(1.0);
+        let half_width = RESAMPLE_HALF_WIDTH/cutoff;
+        let frames = (self.frames() as f64 * ratio).ceil() as usize;
+
+        let channels = self.channels.iter().map(|data| {
+            (0..frames).map(|i| {
+                let position = i as f64/ratio;
+                let first = (position - half_width).floor().max(0.0) as usize;
+                let last = ((position + half_width).ceil() as usize).min(data.len());
+                let mut sum = 0.0;
+                for (j, sample) in data.iter().enumerate().take(last).skip(first) {
+                    sum += *sample as f64 * windowed_sinc((j as f64 - position) * cutoff, RESAMPLE_HALF_WIDTH);
+                }
+                (sum * cutoff) as f32
+            }).collect()
+        }).collect();
+        return SampleBuffer {
+            channels: channels,
+            sample_rate: sample_rate,
+        };
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, sample_rate: u32, frames: usize) -> Vec<f32> {
+        return (0..frames).map(|i| (2.0 * std::f64::consts::PI * freq * i as f64/sample_rate as f64).sin() as f32).collect();
+    }
+
+    /// Peak amplitude away from the edges of the buffer
+    fn peak(data: &[f32]) -> f32 {
+        return data[data.len()/4..data.len() * 3/4].iter().fold(0.0, |acc, s| acc.max(s.abs()));
+    }
+
+    #[test]
+    fn from_interleaved() {
+        let buffer = SampleBuffer::from_interleaved(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0], 2, 48000).unwrap();
+        assert_eq!(buffer.channel_count(), 2);
+        assert_eq!(buffer.channel(0), &[1.0, 3.0, 5.0]);
+        assert_eq!(buffer.channel(1), &[2.0, 4.0, 6.0]);
+        assert!(SampleBuffer::from_interleaved(&[1.0], 0, 48000).is_err());
+        assert!(SampleBuffer::from_interleaved(&[1.0], 1, 0).is_err());
+    }
+
+    #[test]
+    fn resample() {
+        let buffer = SampleBuffer::new(vec![sine(1000.0, 48000, 4800), sine(20000.0, 48000, 4800)], 48000).unwrap();
+        assert_eq!(buffer.resample(48000), buffer);
+
+        //Frequencies above the new nyquist frequency are removed
+        let down = buffer.resample(24000);
+        assert_eq!(down.sample_rate(), 24000);
+        assert_eq!(down.frames(), 2400);
+        assert!((peak(down.channel(0)) - 1.0).abs() < 0.01);
+        assert!(peak(down.channel(1)) < 0.01);
+
+        let up = buffer.resample(96000);
+        assert_eq!(up.frames(), 9600);
+        assert!((peak(up.channel(0)) - 1.0).abs() < 0.01);
+        //The original samples are kept
+        for i in (1000..3000).step_by(7) {
+            assert!((up.channel(0)[i * 2] - buffer.channel(0)[i]).abs() < 1e-3);
+        }
+    }
+
+}
@@ -0,0 +1,254 @@
+use std::fmt::Display;
+
+use crate::dsp::interpolation::{Interpolation, SincTable};
+
+use super::SampleBuffer;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LoopMode {
+    NoLoop,
+    Forward,
+    PingPong,
+}
+
+impl Default for LoopMode {
+    fn default() -> Self {
+        return LoopMode::NoLoop;
+    }
+}
+
+impl Display for LoopMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            LoopMode::NoLoop => "No Loop",
+            LoopMode::Forward => "Forward",
+            LoopMode::PingPong => "Ping-Pong",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SampleLoop {
+    pub mode: LoopMode,
+    pub start: usize,       //Frames
+    pub end: usize,         //Frames, the first frame after the loop
+    pub crossfade: usize,   //Frames before the end that are faded into the frames before the start, forward loops only
+    pub sustain: bool,      //Only loop while the key is held, the rest of the sample plays after the release
+}
+
+impl SampleLoop {
+
+    /// Returns a copy with the loop points converted to a buffer with another sample rate
+    pub fn scale(&self, ratio: f64) -> SampleLoop {
+        let scale = |frames: usize| (frames as f64 * ratio).round() as usize;
+        return SampleLoop {
+            start: scale(self.start),
+            end: scale(self.end),
+            crossfade: scale(self.crossfade),
+            ..*self
+        };
+    }
+
+}
+
+/// Reads a sample buffer at a variable speed
+#[derive(Copy, Clone, Default)]
+pub struct SamplePlayer {
+    position: f64,      //Frames
+    reverse: bool,      //Ping-pong loops are played backwards
+    looping: bool,      //False once a sustain loop was released
+    in_loop: bool,      //The position reached the loop start, so reads before the start are mirrored in ping-pong loops
+    finished: bool,
+}
+
+impl SamplePlayer {
+
+    /// Starts playing at a position in frames
+    pub fn start(&mut self, offset: f64) {
+        self.position = offset.max(0.0);
+        self.reverse = false;
+        self.looping = true;
+        self.in_loop = false;
+        self.finished = false;
+    }
+
+    /// Leaves sustain loops
+    pub fn release(&mut self, sample_loop: &SampleLoop) {
+        if sample_loop.sustain {
+            self.looping = false;
+        }
+    }
+
+    /// Stops playing
+    pub fn stop(&mut self) {
+        self.finished = true;
+    }
+
+    #[inline(always)]
+    pub fn is_finished(&self) -> bool {
+        return self.finished;
+    }
+
+    /// Current position in frames
+    #[inline(always)]
+    pub fn position(&self) -> f64 {
+        return self.position;
+    }
+
+    /// Returns the loop bounds if the loop is currently active
+    #[inline]
+    fn active_loop(&self, buffer: &SampleBuffer, sample_loop: &SampleLoop) -> Option<(usize, usize)> {
+        let end = sample_loop.end.min(buffer.frames());
+        if !self.looping || sample_loop.mode == LoopMode::NoLoop || end < sample_loop.start + 2 {
+            return None;
+        }
+        return Some((sample_loop.start, end));
+    }
+
+    /// Maps an index read by the interpolation into the loop
+    #[inline]
+    fn wrap(&self, index: isize, mode: LoopMode, bounds: Option<(usize, usize)>) -> isize {
+        let (start, end) = match bounds {
+            Some((start, end)) => (start as isize, end as isize),
+            None => return index,
+        };
+        return match mode {
+            LoopMode::Forward if index >= end => start + (index - start).rem_euclid(end - start),
+            LoopMode::PingPong if index >= end => (2 * (end - 1) - index).max(start),
+            LoopMode::PingPong if index < start && self.in_loop => (2 * start - index).min(end - 1),
+            _ => index,
+        }
+    }
+
+    /// Returns the current stereo frame and advances by step frames
+    #[inline]
+    pub fn process(&mut self, buffer: &SampleBuffer, sample_loop: &SampleLoop, interpolation: Interpolation, table: &SincTable, step: f64) -> (f64, f64) {
+        if self.finished {
+            return (0.0, 0.0);
+        }
+        let bounds = self.active_loop(buffer, sample_loop);
+        let mode = sample_loop.mode;
+        let read = |channel: usize, position: f64| interpolation.interpolate(table, position, |i| buffer.get(channel, self.wrap(i, mode, bounds)));
+        let mut frame = [read(0, self.position), read(1, self.position)];
+
+        //Fade the end of forward loops into the audio before the loop start, so the jump is seamless
+        if let (Some((start, end)), LoopMode::Forward) = (bounds, mode) {
+            let length = (end - start) as f64;
+            let crossfade = sample_loop.crossfade.min(start).min(end - start) as f64;
+            let fade_start = end as f64 - crossfade;
+            if crossfade > 0.0 && self.position >= fade_start {
+                let t = ((self.position - fade_start)/crossfade).min(1.0);
+                for (channel, value) in frame.iter_mut().enumerate() {
+                    let other = read(channel, self.position - length);
+                    *value = *value * (1.0 - t) + other * t;
+                }
+            }
+        }
+
+        //Advance
+        match bounds {
+            Some((start, end)) => {
+                let (start, end) = (start as f64, end as f64);
+                if self.position >= start {
+                    self.in_loop = true;
+                }
+                match mode {
+                    LoopMode::PingPong => {
+                        if self.reverse {
+                            self.position -= step;
+                            if self.position <= start {
+                                self.position = (2.0 * start - self.position).min(end - 1.0);
+                                self.reverse = false;
+                            }
+                        }
+                        else {
+                            self.position += step;
+                            if self.position >= end - 1.0 && self.in_loop {
+                                self.position = (2.0 * (end - 1.0) - self.position).max(start);
+                                self.reverse = true;
+                            }
+                        }
+                    },
+                    _ => {
+                        self.position += step;
+                        if self.position >= end && self.in_loop {
+                            self.position = start + (self.position - start).rem_euclid(end - start);
+                        }
+                    },
+                }
+            },
+            None => {
+                //A released ping-pong loop plays on forwards
+                self.reverse = false;
+                self.position += step;
+            },
+        }
+        if self.position >= buffer.frames() as f64 {
+            self.finished = true;
+        }
+        return (frame[0], frame[1]);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays a ramp where every frame holds it's own index and returns the read values
+    fn play(sample_loop: SampleLoop, frames: usize, count: usize) -> Vec<f64> {
+        let buffer = SampleBuffer::new(vec![(0..frames).map(|i| i as f32).collect()], 48000).unwrap();
+        let table = SincTable::new();
+        let mut player = SamplePlayer::default();
+        player.start(0.0);
+        return (0..count).map(|_| player.process(&buffer, &sample_loop, Interpolation::Linear, &table, 1.0).0).collect();
+    }
+
+    #[test]
+    fn no_loop_finishes() {
+        let out = play(SampleLoop::default(), 4, 6);
+        assert_eq!(out, vec![0.0, 1.0, 2.0, 3.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn forward_loop() {
+        let sample_loop = SampleLoop { mode: LoopMode::Forward, start: 4, end: 8, ..Default::default() };
+        let out = play(sample_loop, 12, 16);
+        assert_eq!(out, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 4.0, 5.0, 6.0, 7.0, 4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn ping_pong_loop() {
+        let sample_loop = SampleLoop { mode: LoopMode::PingPong, start: 4, end: 8, ..Default::default() };
+        let out = play(sample_loop, 12, 18);
+        //The end and start frames are played once when the direction reverses
+        assert_eq!(out, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 6.0, 5.0, 4.0, 5.0, 6.0, 7.0, 6.0, 5.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn crossfaded_loop() {
+        let sample_loop = SampleLoop { mode: LoopMode::Forward, start: 8, end: 16, crossfade: 4, ..Default::default() };
+        let out = play(sample_loop, 20, 22);
+        //The last frames of the loop fade towards the frames before the start, so the jump lands on the next value
+        assert_eq!(out[10..], [10.0, 11.0, 12.0, 11.0, 10.0, 9.0, 8.0, 9.0, 10.0, 11.0, 12.0, 11.0]);
+    }
+
+    #[test]
+    fn released_sustain_loop_plays_on() {
+        let sample_loop = SampleLoop { mode: LoopMode::PingPong, start: 2, end: 4, sustain: true, ..Default::default() };
+        let buffer = SampleBuffer::new(vec![(0..6).map(|i| i as f32).collect()], 48000).unwrap();
+        let table = SincTable::new();
+        let mut player = SamplePlayer::default();
+        player.start(0.0);
+        let mut out = Vec::new();
+        for i in 0..9 {
+            if i == 5 {
+                player.release(&sample_loop);
+            }
+            out.push(player.process(&buffer, &sample_loop, Interpolation::Linear, &table, 1.0).0);
+        }
+        assert_eq!(out, vec![0.0, 1.0, 2.0, 3.0, 2.0, 3.0, 4.0, 5.0, 0.0]);
+        assert!(player.is_finished());
+    }
+
+}
@@ -0,0 +1,227 @@
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::MidiMessageContent}, dsp::{envelope::{ADSREnvelope, ADSREnvelopeConfig, ADSRStage}, interpolation::{Interpolation, SincTable}, tuning::Tuning, dynamics::db_to_gain, note_to_freq_transpose}, util::voice::{self, VoiceManager}};
+
+use super::{SampleBuffer, player::{SamplePlayer, SampleLoop}};
+
+#[derive(Copy, Clone)]
+pub struct SamplerConfig {
+    pub root_key: u8,               //Note at which the sample plays at it's original pitch
+    pub tune: f64,                  //Cents
+    pub key_tracking: f64,          //1 follows the keyboard, 0 plays every note at the root pitch
+    pub sample_loop: SampleLoop,    //Frames of the loaded file
+    pub interpolation: Interpolation,
+    pub amp_envelope: ADSREnvelopeConfig,
+    pub gain: f64,                  //dB
+    pub pitch_bend_range: f64,      //Semitones
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        return SamplerConfig {
+            root_key: 60,
+            tune: 0.0,
+            key_tracking: 1.0,
+            sample_loop: SampleLoop::default(),
+            interpolation: Interpolation::Cubic,
+            amp_envelope: ADSREnvelopeConfig {
+                velocity_amount: 1.0,
+                ..ADSREnvelopeConfig::default()
+            },
+            gain: 0.0,
+            pitch_bend_range: 2.0,
+        };
+    }
+}
+
+#[derive(Default)]
+pub struct SamplerVoice {
+    pub player: SamplePlayer,
+    pub amp_env: ADSREnvelope,
+}
+
+pub struct SamplerProcessor {
+    pub config: SamplerConfig,
+    pub tuning: Tuning,
+    source: Option<SampleBuffer>,   //As loaded from the file
+    sample: Option<SampleBuffer>,   //Converted to the processing sample rate
+    table: SincTable,
+    sample_rate: u32,
+    time_step: f64,
+    pitch_bend: f64,
+}
+
+impl SamplerProcessor {
+
+    /// Playback speed of a note relative to the original pitch
+    fn step(&self, note: u8) -> f64 {
+        let note = self.config.root_key as f64 + (note as f64 - self.config.root_key as f64) * self.config.key_tracking;
+        let pitch = self.tuning.freq(note)/self.tuning.freq(self.config.root_key as f64);
+        return pitch * note_to_freq_transpose(self.config.tune * 0.01 + self.pitch_bend * self.config.pitch_bend_range);
+    }
+
+    /// Loop points converted to the processing sample rate
+    #[inline]
+    fn sample_loop(&self) -> SampleLoop {
+        return match (&self.source, &self.sample) {
+            (Some(source), Some(sample)) => self.config.sample_loop.scale(sample.sample_rate() as f64/source.sample_rate() as f64),
+            _ => self.config.sample_loop,
+        }
+    }
+
+}
+
+impl voice::VoiceProcessor<SamplerVoice> for SamplerProcessor {
+
+    fn process_voice(&mut self, voice: &mut voice::Voice<SamplerVoice>, _info: SampleInfo) -> (f64, f64) {
+        let amp = voice.data.amp_env.process(&self.config.amp_envelope, self.time_step);
+        let step = self.step(voice.note);
+        let sample_loop = self.sample_loop();
+        let (left, right) = match &self.sample {
+            Some(sample) => voice.data.player.process(sample, &sample_loop, self.config.interpolation, &self.table, step),
+            None => (0.0, 0.0),
+        };
+        let gain = amp * db_to_gain(self.config.gain);
+        return (left * gain, right * gain);
+    }
+
+    fn voice_on(&mut self, voice: &mut voice::Voice<SamplerVoice>, _info: SampleInfo) {
+        voice.data.player.start(0.0);
+        voice.data.amp_env.press(&self.config.amp_envelope, voice.velocity);
+    }
+
+    fn voice_off(&mut self, voice: &mut voice::Voice<SamplerVoice>, _info: SampleInfo) {
+        voice.data.amp_env.release();
+        voice.data.player.release(&self.config.sample_loop);
+    }
+
+    fn voice_kill(&mut self, voice: &mut voice::Voice<SamplerVoice>, _info: SampleInfo) {
+        voice.data.amp_env.kill();
+    }
+
+    fn check_attack_finished(&mut self, voice: &voice::Voice<SamplerVoice>, _info: SampleInfo) -> bool {
+        return voice.data.amp_env.stage() != ADSRStage::Attack;
+    }
+
+    fn voice_level(&self, voice: &voice::Voice<SamplerVoice>) -> f64 {
+        return voice.data.amp_env.level();
+    }
+
+    fn check_inactive(&mut self, voice: &voice::Voice<SamplerVoice>, _info: SampleInfo) -> bool {
+        return !voice.data.amp_env.is_active() || voice.data.player.is_finished();
+    }
+
+}
+
+/// Plays a single sample over the keyboard
+pub struct Sampler {
+    info: DeviceInfo,
+    output: NamedAudioPort,
+    midiin: NamedMidiPort,
+    voice_mgr: VoiceManager<SamplerVoice>,
+    pub proc: SamplerProcessor,
+}
+
+impl Sampler {
+
+    pub fn new() -> Sampler {
+        return Sampler {
+            info: DeviceInfo {
+                name: "Sampler",
+                type_identifier: "synthi_sam_sampler",
+            },
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            midiin: NamedMidiPort::new("MIDI In", "midi_in"),
+            voice_mgr: VoiceManager::new(32),
+            proc: SamplerProcessor {
+                config: SamplerConfig::default(),
+                tuning: Tuning::default(),
+                source: None,
+                sample: None,
+                table: SincTable::new(),
+                sample_rate: 0,
+                time_step: 0.0,
+                pitch_bend: 0.0,
+            },
+        };
+    }
+
+    /// Replaces the sample, it is converted to the processing sample rate if the device was already set up
+    ///
+    /// This allocates, so it shouldn't be called from the audio thread.
+    pub fn set_sample(&mut self, sample: SampleBuffer) {
+        self.proc.sample = if self.proc.sample_rate > 0 { Some(sample.resample(self.proc.sample_rate)) } else { None };
+        self.proc.source = Some(sample);
+    }
+
+    /// Loads a WAV, AIFF or FLAC file
+    pub fn load(&mut self, path: &str) -> Result<(), &'static str> {
+        self.set_sample(SampleBuffer::load(path)?);
+        return Ok(());
+    }
+
+}
+
+impl Device for Sampler {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.output.port.reset();
+        self.midiin.port.reset();
+        if info.sample_rate != self.proc.sample_rate {
+            self.proc.sample = self.proc.source.as_ref().map(|s| s.resample(info.sample_rate));
+        }
+        self.proc.sample_rate = info.sample_rate;
+        self.proc.time_step = info.time_step;
+        self.proc.pitch_bend = 0.0;
+        let i = SampleInfo {
+            sample_count: 0,
+            time: 0.0,
+            jitter: false,
+        };
+        self.voice_mgr.reset(&mut self.proc, i);
+    }
+
+    fn process(&mut self, info: SampleInfo) {
+        while let Some(msg) = self.midiin.port.pop() {
+            match msg.message {
+                MidiMessageContent::NoteOn(note) => self.voice_mgr.press_note(&mut self.proc, note.note, note.velocity, info),
+                MidiMessageContent::NoteOff(note) => self.voice_mgr.release_note(&mut self.proc, note.note, info),
+                MidiMessageContent::PitchBend(bend) => self.proc.pitch_bend = bend.pitch_bend,
+                MidiMessageContent::ControlChange(cc) => {
+                    self.voice_mgr.control_change(&mut self.proc, cc.control, cc.value, info); //Pedals
+                },
+                MidiMessageContent::SysEx(sysex) => {
+                    self.proc.tuning.apply_sysex(&sysex.data);
+                },
+                _ => {},
+            }
+        }
+        let (left, right) = self.voice_mgr.process_voices(&mut self.proc, info);
+        self.output.port.take_input(&[left, right]);
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.midiin),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
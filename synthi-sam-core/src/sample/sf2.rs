@@ -0,0 +1,696 @@
+use super::SampleBuffer;
+
+/// Number of generators, including the unused slots of the specification
+pub const GENERATOR_COUNT: usize = 61;
+
+pub const GEN_START_OFFSET: usize = 0;
+pub const GEN_LOOP_START_OFFSET: usize = 2;
+pub const GEN_LOOP_END_OFFSET: usize = 3;
+pub const GEN_START_COARSE_OFFSET: usize = 4;
+pub const GEN_MOD_LFO_TO_PITCH: usize = 5;
+pub const GEN_VIB_LFO_TO_PITCH: usize = 6;
+pub const GEN_MOD_ENV_TO_PITCH: usize = 7;
+pub const GEN_FILTER_FC: usize = 8;
+pub const GEN_FILTER_Q: usize = 9;
+pub const GEN_MOD_LFO_TO_FILTER_FC: usize = 10;
+pub const GEN_MOD_ENV_TO_FILTER_FC: usize = 11;
+pub const GEN_MOD_LFO_TO_VOLUME: usize = 13;
+pub const GEN_PAN: usize = 17;
+pub const GEN_MOD_LFO_DELAY: usize = 21;
+pub const GEN_MOD_LFO_FREQ: usize = 22;
+pub const GEN_VIB_LFO_DELAY: usize = 23;
+pub const GEN_VIB_LFO_FREQ: usize = 24;
+pub const GEN_MOD_ENV_DELAY: usize = 25;   //Followed by attack, hold, decay, sustain, release, key to hold and key to decay
+pub const GEN_VOL_ENV_DELAY: usize = 33;   //Same order as the modulation envelope
+pub const GEN_INSTRUMENT: usize = 41;
+pub const GEN_KEY_RANGE: usize = 43;
+pub const GEN_VEL_RANGE: usize = 44;
+pub const GEN_LOOP_START_COARSE_OFFSET: usize = 45;
+pub const GEN_KEYNUM: usize = 46;
+pub const GEN_VELOCITY: usize = 47;
+pub const GEN_ATTENUATION: usize = 48;
+pub const GEN_LOOP_END_COARSE_OFFSET: usize = 50;
+pub const GEN_COARSE_TUNE: usize = 51;
+pub const GEN_FINE_TUNE: usize = 52;
+pub const GEN_SAMPLE_ID: usize = 53;
+pub const GEN_SAMPLE_MODES: usize = 54;
+pub const GEN_SCALE_TUNING: usize = 56;
+pub const GEN_EXCLUSIVE_CLASS: usize = 57;
+pub const GEN_ROOT_KEY: usize = 58;
+/// Unused slot that receives the pitch wheel modulator (cents), the specification calls it "initial pitch"
+pub const GEN_PITCH: usize = 59;
+
+/// Generators that only exist at instrument level, presets can't offset them
+const INSTRUMENT_ONLY: [usize; 13] = [0, 1, 2, 3, 4, 12, 45, 46, 47, 50, 54, 57, 58];
+
+/// Values of generators that aren't set by a zone
+fn default_generators() -> [i32; GENERATOR_COUNT] {
+    let mut generators = [0; GENERATOR_COUNT];
+    generators[GEN_FILTER_FC] = 13500;
+    for i in [GEN_MOD_LFO_DELAY, GEN_VIB_LFO_DELAY] {
+        generators[i] = -12000;
+    }
+    //Delay, attack, hold, decay and release of both envelopes
+    for offset in [0, 1, 2, 3, 5] {
+        generators[GEN_MOD_ENV_DELAY + offset] = -12000;
+        generators[GEN_VOL_ENV_DELAY + offset] = -12000;
+    }
+    generators[GEN_SCALE_TUNING] = 100;
+    generators[GEN_KEYNUM] = -1;
+    generators[GEN_VELOCITY] = -1;
+    generators[GEN_ROOT_KEY] = -1;
+    return generators;
+}
+
+/// Values of the MIDI controllers that modulators read, all from 0 to 1
+#[derive(Copy, Clone)]
+pub struct Sf2Controllers {
+    pub controllers: [f64; 128],
+    pub channel_pressure: f64,
+    pub pitch_wheel: f64,           //0.5 is the center
+    pub pitch_wheel_range: f64,     //Semitones
+}
+
+impl Default for Sf2Controllers {
+    fn default() -> Self {
+        let mut controllers = [0.0; 128];
+        controllers[7] = 100.0/127.0;   //Volume
+        controllers[10] = 64.0/127.0;   //Pan
+        controllers[11] = 1.0;          //Expression
+        return Sf2Controllers {
+            controllers: controllers,
+            channel_pressure: 0.0,
+            pitch_wheel: 0.5,
+            pitch_wheel_range: 2.0,
+        };
+    }
+}
+
+/// Concave curve of the specification, rising slowly at first
+#[inline]
+fn concave(x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    return (-20.0/96.0 * (1.0 - x).powi(2).log10()).min(1.0);
+}
+
+/// Connects a controller to a generator
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sf2Modulator {
+    pub source: u16,
+    pub destination: u16,
+    pub amount: i16,
+    pub amount_source: u16,
+    pub transform: u16,
+}
+
+impl Sf2Modulator {
+
+    /// Modulators that every instrument has unless it overrides them
+    pub fn defaults() -> [Sf2Modulator; 10] {
+        let modulator = |source: u16, destination: usize, amount: i16, amount_source: u16| Sf2Modulator {
+            source: source,
+            destination: destination as u16,
+            amount: amount,
+            amount_source: amount_source,
+            transform: 0,
+        };
+        return [
+            modulator(0x0502, GEN_ATTENUATION, 960, 0),     //Velocity
+            modulator(0x0102, GEN_FILTER_FC, -2400, 0),     //Velocity
+            modulator(0x000D, GEN_VIB_LFO_TO_PITCH, 50, 0), //Channel pressure
+            modulator(0x0081, GEN_VIB_LFO_TO_PITCH, 50, 0), //Modulation wheel
+            modulator(0x0587, GEN_ATTENUATION, 960, 0),     //Volume
+            modulator(0x028A, GEN_PAN, 1000, 0),            //Pan
+            modulator(0x058B, GEN_ATTENUATION, 960, 0),     //Expression
+            modulator(0x00DB, 16, 200, 0),                  //Reverb send
+            modulator(0x00DD, 15, 200, 0),                  //Chorus send
+            modulator(0x020E, GEN_PITCH, 12700, 0x0010),    //Pitch wheel scaled by it's range
+        ];
+    }
+
+    /// Modulators with the same sources, destination and transform replace each other
+    #[inline]
+    pub fn identical(&self, other: &Sf2Modulator) -> bool {
+        return self.source == other.source && self.destination == other.destination && self.amount_source == other.amount_source && self.transform == other.transform;
+    }
+
+    /// Reads a source and maps it with it's curve, direction and polarity
+    pub fn source_value(source: u16, controllers: &Sf2Controllers, key: u8, velocity: u8) -> f64 {
+        let index = (source & 0x7F) as usize;
+        let mut x = if source & 0x80 != 0 {
+            controllers.controllers[index]
+        }
+        else {
+            match index {
+                0 => 1.0,
+                2 => velocity as f64/127.0,
+                3 => key as f64/127.0,
+                13 => controllers.channel_pressure,
+                14 => controllers.pitch_wheel,
+                16 => controllers.pitch_wheel_range/127.0,
+                _ => 0.0,
+            }
+        };
+        if source & 0x100 != 0 {
+            x = 1.0 - x;
+        }
+        let curve = |x: f64| match source >> 10 {
+            1 => concave(x),
+            2 => 1.0 - concave(1.0 - x),
+            3 => if x >= 0.5 { 1.0 } else { 0.0 },
+            _ => x,
+        };
+        //Bipolar sources apply the curve to both halves
+        if source & 0x200 != 0 {
+            let x = x * 2.0 - 1.0;
+            if source >> 10 == 3 {
+                return if x >= 0.0 { 1.0 } else { -1.0 };
+            }
+            return curve(x.abs()) * x.signum();
+        }
+        return curve(x);
+    }
+
+    /// Returns the amount added to the destination generator
+    pub fn value(&self, controllers: &Sf2Controllers, key: u8, velocity: u8) -> f64 {
+        let value = self.amount as f64 * Self::source_value(self.source, controllers, key, velocity) * Self::source_value(self.amount_source, controllers, key, velocity);
+        return if self.transform == 2 { value.abs() } else { value };
+    }
+
+}
+
+/// A sample with it's settings of a preset, the instrument and preset levels are already combined
+#[derive(Clone)]
+pub struct Sf2Zone {
+    pub keys: (u8, u8),
+    pub velocities: (u8, u8),
+    pub sample: usize,
+    pub generators: [i32; GENERATOR_COUNT],
+    pub modulators: Vec<Sf2Modulator>,
+}
+
+impl Sf2Zone {
+
+    #[inline]
+    pub fn matches(&self, note: u8, velocity: u8) -> bool {
+        return note >= self.keys.0 && note <= self.keys.1 && velocity >= self.velocities.0 && velocity <= self.velocities.1;
+    }
+
+}
+
+#[derive(Clone)]
+pub struct Sf2Preset {
+    pub name: String,
+    pub bank: u16,
+    pub program: u16,
+    pub zones: Vec<Sf2Zone>,
+}
+
+#[derive(Clone)]
+pub struct Sf2Sample {
+    pub name: String,
+    pub buffer: SampleBuffer,
+    pub loop_start: usize,      //Frames
+    pub loop_end: usize,        //Frames, the first frame after the loop
+    pub root_key: u8,
+    pub correction: i8,         //Cents
+}
+
+/// Zone of a preset or instrument as stored in the file
+#[derive(Clone)]
+struct RawZone {
+    generators: [Option<i32>; GENERATOR_COUNT],
+    keys: Option<(u8, u8)>,
+    velocities: Option<(u8, u8)>,
+    modulators: Vec<Sf2Modulator>,
+}
+
+impl RawZone {
+
+    /// Adds the values that are missing in this zone from the global zone
+    fn inherit(&self, global: Option<&RawZone>) -> RawZone {
+        let mut zone = self.clone();
+        if let Some(global) = global {
+            for (value, global) in zone.generators.iter_mut().zip(global.generators.iter()) {
+                *value = value.or(*global);
+            }
+            zone.keys = zone.keys.or(global.keys);
+            zone.velocities = zone.velocities.or(global.velocities);
+            zone.modulators = merge_modulators(&global.modulators, &self.modulators);
+        }
+        return zone;
+    }
+
+}
+
+/// Adds modulators to a list, replacing identical ones
+fn merge_modulators(base: &[Sf2Modulator], modulators: &[Sf2Modulator]) -> Vec<Sf2Modulator> {
+    let mut result = base.to_vec();
+    for modulator in modulators {
+        match result.iter_mut().find(|m| m.identical(modulator)) {
+            Some(m) => *m = *modulator,
+            None => result.push(*modulator),
+        }
+    }
+    return result;
+}
+
+#[inline]
+fn intersect(a: (u8, u8), b: (u8, u8)) -> Option<(u8, u8)> {
+    let range = (a.0.max(b.0), a.1.min(b.1));
+    return if range.0 <= range.1 { Some(range) } else { None };
+}
+
+#[inline]
+fn read_u16(data: &[u8], pos: usize) -> u16 {
+    return u16::from_le_bytes([data[pos], data[pos + 1]]);
+}
+
+#[inline]
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    return u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+}
+
+/// Reads a zero terminated name of 20 bytes
+fn read_name(data: &[u8]) -> String {
+    let name = &data[..20];
+    let end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+    return String::from_utf8_lossy(&name[..end]).trim().to_string();
+}
+
+/// Splits RIFF data into it's chunks
+fn read_chunks(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size = read_u32(data, pos + 4) as usize;
+        let start = pos + 8;
+        let end = (start + size).min(data.len());
+        chunks.push((&data[pos..pos + 4], &data[start..end]));
+        pos = start + size + (size & 1);
+    }
+    return chunks;
+}
+
+/// The hydra chunks of the preset data list
+#[derive(Default)]
+struct PresetData<'a> {
+    phdr: &'a [u8],
+    pbag: &'a [u8],
+    pmod: &'a [u8],
+    pgen: &'a [u8],
+    inst: &'a [u8],
+    ibag: &'a [u8],
+    imod: &'a [u8],
+    igen: &'a [u8],
+    shdr: &'a [u8],
+}
+
+/// Reads the zones with the bags from first to last (exclusive)
+fn read_zones(bags: &[u8], generators: &[u8], modulators: &[u8], first: usize, last: usize) -> Result<Vec<RawZone>, &'static str> {
+    let bag = |i: usize| -> Result<(usize, usize), &'static str> {
+        let data = bags.get(i * 4..i * 4 + 4).ok_or("Invalid SF2 zone")?;
+        return Ok((read_u16(data, 0) as usize, read_u16(data, 2) as usize));
+    };
+    let mut zones = Vec::new();
+    for i in first..last {
+        let (gen_start, mod_start) = bag(i)?;
+        let (gen_end, mod_end) = bag(i + 1)?;
+        let mut zone = RawZone {
+            generators: [None; GENERATOR_COUNT],
+            keys: None,
+            velocities: None,
+            modulators: Vec::new(),
+        };
+        for g in generators.get(gen_start * 4..gen_end * 4).ok_or("Invalid SF2 generator")?.chunks_exact(4) {
+            let operator = read_u16(g, 0) as usize;
+            match operator {
+                GEN_KEY_RANGE => zone.keys = Some((g[2].min(127), g[3].min(127))),
+                GEN_VEL_RANGE => zone.velocities = Some((g[2].min(127), g[3].min(127))),
+                GEN_INSTRUMENT | GEN_SAMPLE_ID => zone.generators[operator] = Some(read_u16(g, 2) as i32),
+                _ if operator < GENERATOR_COUNT => zone.generators[operator] = Some(read_u16(g, 2) as i16 as i32),
+                _ => {},
+            }
+        }
+        for m in modulators.get(mod_start * 10..mod_end * 10).ok_or("Invalid SF2 modulator")?.chunks_exact(10) {
+            let modulator = Sf2Modulator {
+                source: read_u16(m, 0),
+                destination: read_u16(m, 2),
+                amount: read_u16(m, 4) as i16,
+                amount_source: read_u16(m, 6),
+                transform: read_u16(m, 8),
+            };
+            zone.modulators = merge_modulators(&zone.modulators, &[modulator]);
+        }
+        zones.push(zone);
+    }
+    return Ok(zones);
+}
+
+/// Splits zones into the global zone and the zones that end with the given generator
+fn split_global(zones: &[RawZone], terminal: usize) -> (Option<&RawZone>, impl Iterator<Item = &RawZone>) {
+    let global = zones.first().filter(|z| z.generators[terminal].is_none());
+    let skip = if global.is_some() { 1 } else { 0 };
+    return (global, zones.iter().skip(skip).filter(move |z| z.generators[terminal].is_some()));
+}
+
+/// Instrument zones with the global zone and the default values applied
+struct InstrumentZone {
+    zone: RawZone,
+    generators: [i32; GENERATOR_COUNT],
+}
+
+/// SoundFont 2 bank with all samples decoded
+pub struct SoundFont {
+    pub name: String,
+    pub presets: Vec<Sf2Preset>,
+    pub samples: Vec<Sf2Sample>,
+}
+
+impl SoundFont {
+
+    pub fn decode(data: &[u8]) -> Result<SoundFont, &'static str> {
+        if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"sfbk") {
+            return Err("Invalid SF2 file");
+        }
+        let mut name = String::new();
+        let mut samples: &[u8] = &[];
+        let mut pdta = PresetData::default();
+        for (id, list) in read_chunks(&data[12..]) {
+            if id != b"LIST" || list.len() < 4 {
+                continue;
+            }
+            for (id, chunk) in read_chunks(&list[4..]) {
+                match (&list[0..4], id) {
+                    (b"INFO", b"INAM") => name = String::from_utf8_lossy(chunk).trim_end_matches('\0').to_string(),
+                    (b"sdta", b"smpl") => samples = chunk,
+                    (b"pdta", b"phdr") => pdta.phdr = chunk,
+                    (b"pdta", b"pbag") => pdta.pbag = chunk,
+                    (b"pdta", b"pmod") => pdta.pmod = chunk,
+                    (b"pdta", b"pgen") => pdta.pgen = chunk,
+                    (b"pdta", b"inst") => pdta.inst = chunk,
+                    (b"pdta", b"ibag") => pdta.ibag = chunk,
+                    (b"pdta", b"imod") => pdta.imod = chunk,
+                    (b"pdta", b"igen") => pdta.igen = chunk,
+                    (b"pdta", b"shdr") => pdta.shdr = chunk,
+                    _ => {},
+                }
+            }
+        }
+
+        //Samples, the last header is a terminator
+        let headers: Vec<&[u8]> = pdta.shdr.chunks_exact(46).collect();
+        let mut sample_list = Vec::new();
+        for header in headers.iter().take(headers.len().saturating_sub(1)) {
+            let frames = samples.len()/2;
+            let start = (read_u32(header, 20) as usize).min(frames);
+            let end = (read_u32(header, 24) as usize).clamp(start, frames);
+            let rate = read_u32(header, 36);
+            //Samples in ROM aren't part of the file
+            let data: Vec<f32> = if read_u16(header, 44) & 0x8000 == 0 {
+                (start..end).map(|i| read_u16(samples, i * 2) as i16 as f32/32768.0).collect()
+            }
+            else {
+                Vec::new()
+            };
+            sample_list.push(Sf2Sample {
+                name: read_name(header),
+                buffer: SampleBuffer::new(vec![data], if rate > 0 { rate } else { 44100 })?,
+                loop_start: (read_u32(header, 28) as usize).saturating_sub(start),
+                loop_end: (read_u32(header, 32) as usize).saturating_sub(start),
+                root_key: header[40].min(127),
+                correction: header[41] as i8,
+            });
+        }
+
+        //Instruments, the last header is a terminator
+        let instrument_headers: Vec<&[u8]> = pdta.inst.chunks_exact(22).collect();
+        let mut instruments: Vec<Vec<InstrumentZone>> = Vec::new();
+        for pair in instrument_headers.windows(2) {
+            let zones = read_zones(pdta.ibag, pdta.igen, pdta.imod, read_u16(pair[0], 20) as usize, read_u16(pair[1], 20) as usize)?;
+            let (global, locals) = split_global(&zones, GEN_SAMPLE_ID);
+            let mut list = Vec::new();
+            for local in locals {
+                let mut zone = local.inherit(global);
+                zone.modulators = merge_modulators(&Sf2Modulator::defaults(), &zone.modulators);
+                let mut generators = default_generators();
+                for (value, set) in generators.iter_mut().zip(zone.generators.iter()) {
+                    if let Some(set) = set {
+                        *value = *set;
+                    }
+                }
+                if (generators[GEN_SAMPLE_ID] as usize) < sample_list.len() {
+                    list.push(InstrumentZone {
+                        zone: zone,
+                        generators: generators,
+                    });
+                }
+            }
+            instruments.push(list);
+        }
+
+        //Presets, the instrument zones are combined with the preset zones so playback doesn't need to know about the levels
+        let preset_headers: Vec<&[u8]> = pdta.phdr.chunks_exact(38).collect();
+        let mut presets = Vec::new();
+        for pair in preset_headers.windows(2) {
+            let zones = read_zones(pdta.pbag, pdta.pgen, pdta.pmod, read_u16(pair[0], 24) as usize, read_u16(pair[1], 24) as usize)?;
+            let (global, locals) = split_global(&zones, GEN_INSTRUMENT);
+            let mut preset = Sf2Preset {
+                name: read_name(pair[0]),
+                program: read_u16(pair[0], 20),
+                bank: read_u16(pair[0], 22),
+                zones: Vec::new(),
+            };
+            for local in locals {
+                let zone = local.inherit(global);
+                let instrument = match zone.generators[GEN_INSTRUMENT].and_then(|i| instruments.get(i as usize)) {
+                    Some(instrument) => instrument,
+                    None => continue,
+                };
+                for inst in instrument {
+                    let keys = intersect(inst.zone.keys.unwrap_or((0, 127)), zone.keys.unwrap_or((0, 127)));
+                    let velocities = intersect(inst.zone.velocities.unwrap_or((0, 127)), zone.velocities.unwrap_or((0, 127)));
+                    let (keys, velocities) = match (keys, velocities) {
+                        (Some(k), Some(v)) => (k, v),
+                        _ => continue,
+                    };
+                    //Preset generators are offsets to the instrument values
+                    let mut generators = inst.generators;
+                    for (i, value) in zone.generators.iter().enumerate() {
+                        if let Some(value) = value {
+                            if !INSTRUMENT_ONLY.contains(&i) && i != GEN_INSTRUMENT && i != GEN_SAMPLE_ID {
+                                generators[i] += *value;
+                            }
+                        }
+                    }
+                    let mut modulators = inst.zone.modulators.clone();
+                    modulators.extend_from_slice(&zone.modulators);
+                    preset.zones.push(Sf2Zone {
+                        keys: keys,
+                        velocities: velocities,
+                        sample: inst.generators[GEN_SAMPLE_ID] as usize,
+                        generators: generators,
+                        modulators: modulators,
+                    });
+                }
+            }
+            presets.push(preset);
+        }
+        if presets.is_empty() {
+            return Err("SF2 file has no presets");
+        }
+        return Ok(SoundFont {
+            name: name,
+            presets: presets,
+            samples: sample_list,
+        });
+    }
+
+    /// Loads and decodes a file, this blocks so it shouldn't be called from the audio thread
+    pub fn load(path: &str) -> Result<SoundFont, &'static str> {
+        let data = std::fs::read(path).map_err(|_| "Couldn't read SF2 file")?;
+        return SoundFont::decode(&data);
+    }
+
+    /// Returns the index of a preset
+    pub fn find_preset(&self, bank: u16, program: u16) -> Option<usize> {
+        return self.presets.iter().position(|p| p.bank == bank && p.program == program);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() & 1 != 0 {
+            chunk.push(0);
+        }
+        return chunk;
+    }
+
+    fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = kind.to_vec();
+        for c in chunks {
+            data.extend_from_slice(c);
+        }
+        return chunk(b"LIST", &data);
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut data = name.as_bytes().to_vec();
+        data.resize(20, 0);
+        return data;
+    }
+
+    fn records(records: &[Vec<u8>]) -> Vec<u8> {
+        return records.concat();
+    }
+
+    fn generator(operator: usize, amount: i16) -> Vec<u8> {
+        return [(operator as u16).to_le_bytes(), amount.to_le_bytes()].concat();
+    }
+
+    fn range(operator: usize, low: u8, high: u8) -> Vec<u8> {
+        return [(operator as u16).to_le_bytes().to_vec(), vec![low, high]].concat();
+    }
+
+    fn bag(generator: u16, modulator: u16) -> Vec<u8> {
+        return [generator.to_le_bytes(), modulator.to_le_bytes()].concat();
+    }
+
+    fn preset_header(preset: &str, program: u16, bank: u16, bag: u16) -> Vec<u8> {
+        return [name(preset), program.to_le_bytes().to_vec(), bank.to_le_bytes().to_vec(), bag.to_le_bytes().to_vec(), vec![0; 12]].concat();
+    }
+
+    fn instrument_header(instrument: &str, bag: u16) -> Vec<u8> {
+        return [name(instrument), bag.to_le_bytes().to_vec()].concat();
+    }
+
+    fn sample_header(sample: &str, offsets: [u32; 4], rate: u32, root_key: u8, correction: i8) -> Vec<u8> {
+        let mut data = name(sample);
+        for value in offsets.iter().chain([rate].iter()) {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[root_key, correction as u8, 0, 0, 1, 0]);
+        return data;
+    }
+
+    /// Bank with one sample, one instrument with a global zone and two presets, the second one doesn't overlap the instrument
+    fn bank() -> Vec<u8> {
+        let samples: Vec<u8> = (0..16i16).flat_map(|i| (i * 1000).to_le_bytes()).collect();
+        let velocity_modulator = [0x0502u16, GEN_ATTENUATION as u16, 480, 0, 0].iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+        let pdta = list(b"pdta", &[
+            chunk(b"phdr", &records(&[preset_header("Piano", 5, 1, 0), preset_header("Disjoint", 6, 1, 2), preset_header("EOP", 0, 0, 3)])),
+            chunk(b"pbag", &records(&[bag(0, 0), bag(2, 0), bag(6, 0), bag(8, 0)])),
+            chunk(b"pmod", &[0; 10]),
+            chunk(b"pgen", &records(&[
+                //Global zone
+                generator(GEN_ATTENUATION, 50),
+                generator(GEN_COARSE_TUNE, 2),
+                //Instrument only generators are ignored at preset level
+                range(GEN_KEY_RANGE, 60, 127),
+                generator(GEN_ROOT_KEY, 70),
+                generator(GEN_EXCLUSIVE_CLASS, 9),
+                generator(GEN_INSTRUMENT, 0),
+                range(GEN_KEY_RANGE, 0, 20),
+                generator(GEN_INSTRUMENT, 0),
+                generator(0, 0),
+            ])),
+            chunk(b"inst", &records(&[instrument_header("Inst", 0), instrument_header("EOI", 3)])),
+            chunk(b"ibag", &records(&[bag(0, 0), bag(3, 1), bag(7, 1), bag(10, 1)])),
+            chunk(b"imod", &[velocity_modulator, vec![0; 10]].concat()),
+            chunk(b"igen", &records(&[
+                //Global zone
+                range(GEN_KEY_RANGE, 36, 84),
+                generator(GEN_ATTENUATION, 100),
+                generator(GEN_PAN, -200),
+                range(GEN_VEL_RANGE, 0, 100),
+                generator(GEN_PAN, 300),
+                generator(GEN_SAMPLE_MODES, 1),
+                generator(GEN_SAMPLE_ID, 0),
+                range(GEN_KEY_RANGE, 70, 100),
+                generator(GEN_EXCLUSIVE_CLASS, 3),
+                generator(GEN_SAMPLE_ID, 0),
+                generator(0, 0),
+            ])),
+            chunk(b"shdr", &records(&[sample_header("Ramp", [4, 12, 6, 10], 22050, 60, -5), sample_header("EOS", [0; 4], 0, 0, 0)])),
+        ]);
+        let body = [b"sfbk".to_vec(), list(b"INFO", &[chunk(b"INAM", b"Test\0\0")]), list(b"sdta", &[chunk(b"smpl", &samples)]), pdta].concat();
+        return [b"RIFF".to_vec(), (body.len() as u32).to_le_bytes().to_vec(), body].concat();
+    }
+
+    #[test]
+    fn samples() {
+        let font = SoundFont::decode(&bank()).unwrap();
+        assert_eq!(font.name, "Test");
+        assert_eq!(font.samples.len(), 1);
+        let sample = &font.samples[0];
+        assert_eq!(sample.name, "Ramp");
+        assert_eq!(sample.buffer.sample_rate(), 22050);
+        assert_eq!(sample.buffer.frames(), 8);
+        assert_eq!(sample.buffer.channel(0)[0], 4000.0/32768.0);
+        //Loop points are relative to the sample start
+        assert_eq!((sample.loop_start, sample.loop_end), (2, 6));
+        assert_eq!((sample.root_key, sample.correction), (60, -5));
+    }
+
+    #[test]
+    fn zones() {
+        let font = SoundFont::decode(&bank()).unwrap();
+        let preset = &font.presets[font.find_preset(1, 5).unwrap()];
+        assert_eq!(preset.name, "Piano");
+        assert_eq!(preset.zones.len(), 2);
+
+        //Key ranges of the global instrument zone and the preset zone intersect
+        let first = &preset.zones[0];
+        assert_eq!(first.keys, (60, 84));
+        assert_eq!(first.velocities, (0, 100));
+        assert_eq!(first.sample, 0);
+        assert_eq!(first.generators[GEN_ATTENUATION], 150);
+        assert_eq!(first.generators[GEN_PAN], 300);
+        assert_eq!(first.generators[GEN_COARSE_TUNE], 2);
+        assert_eq!(first.generators[GEN_SAMPLE_MODES], 1);
+        assert_eq!(first.generators[GEN_ROOT_KEY], -1);
+        assert_eq!(first.generators[GEN_FILTER_FC], 13500);
+
+        let second = &preset.zones[1];
+        assert_eq!(second.keys, (70, 100));
+        assert_eq!(second.velocities, (0, 127));
+        assert_eq!(second.generators[GEN_PAN], -200);
+        assert_eq!(second.generators[GEN_EXCLUSIVE_CLASS], 3);
+        assert_eq!(second.generators[GEN_ATTENUATION], 150);
+
+        //Zones without overlap are left out
+        let disjoint = &font.presets[font.find_preset(1, 6).unwrap()];
+        assert!(disjoint.zones.is_empty());
+    }
+
+    #[test]
+    fn modulators_replace_defaults() {
+        let font = SoundFont::decode(&bank()).unwrap();
+        for zone in font.presets[0].zones.iter() {
+            assert_eq!(zone.modulators.len(), Sf2Modulator::defaults().len());
+            let velocity: Vec<&Sf2Modulator> = zone.modulators.iter().filter(|m| m.source == 0x0502 && m.destination == GEN_ATTENUATION as u16).collect();
+            assert_eq!(velocity.len(), 1);
+            assert_eq!(velocity[0].amount, 480);
+        }
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(SoundFont::decode(b"RIFF\0\0\0\0WAVE").is_err());
+        let data = bank();
+        assert!(SoundFont::decode(&data[..40]).is_err());
+    }
+
+}
@@ -0,0 +1,772 @@
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::MidiMessageContent}, dsp::{envelope::KILL_TIME, filter::{StateVariableFilter, SVFilterConfig, SVFilterType}, interpolation::{Interpolation, SincTable}, lfo::{Lfo, LfoConfig, LfoMode, LfoRate, LfoShape}, dynamics::{db_to_gain, gain_to_db}, note_to_freq_transpose, pan_equal_power}, util::voice::{self, VoiceManager, VoiceState}};
+
+use super::{midi_velocity, player::{SamplePlayer, SampleLoop, LoopMode}, sf2::*};
+
+/// Maximum number of zones a single note can start
+const MAX_ZONES: usize = 8;
+const CHANNEL_COUNT: usize = 16;
+/// Channel 10 plays the drum kits of bank 128
+const PERCUSSION_CHANNEL: usize = 9;
+/// Range of the volume envelope, it is linear in dB except for the attack
+const ENVELOPE_RANGE: f64 = 96.0;
+/// Frequency of 0 absolute cents
+const ABSOLUTE_CENTS_FREQ: f64 = 8.176;
+
+/// Converts timecents to seconds, the minimum value means no time at all
+#[inline]
+fn timecents(value: f64) -> f64 {
+    return if value <= -12000.0 { 0.0 } else { f64::from(2.0).powf(value.min(8000.0)/1200.0) };
+}
+
+#[inline]
+fn absolute_cents(value: f64) -> f64 {
+    return ABSOLUTE_CENTS_FREQ * f64::from(2.0).powf(value/1200.0);
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Sf2EnvelopeStage {
+    Idle,
+    Delay,
+    Attack,
+    Hold,
+    Decay,
+    Sustain,
+    Release,
+}
+
+impl Default for Sf2EnvelopeStage {
+    fn default() -> Self {
+        return Sf2EnvelopeStage::Idle;
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Sf2EnvelopeParams {
+    delay: f64,     //Seconds
+    attack: f64,    //Seconds
+    hold: f64,      //Seconds
+    decay: f64,     //Seconds from the maximum to zero
+    sustain: f64,   //Level from 0 to 1
+    release: f64,   //Seconds from the maximum to zero
+}
+
+impl Sf2EnvelopeParams {
+
+    /// Reads the parameters from the generators of an envelope
+    fn new(generators: &[f64; GENERATOR_COUNT], first: usize, key: u8, volume: bool) -> Sf2EnvelopeParams {
+        let key_offset = 60.0 - key as f64;
+        let sustain = if volume { 1.0 - generators[first + 4]/(ENVELOPE_RANGE * 10.0) } else { 1.0 - generators[first + 4]/1000.0 };
+        return Sf2EnvelopeParams {
+            delay: timecents(generators[first]),
+            attack: timecents(generators[first + 1]),
+            hold: timecents(generators[first + 2] + generators[first + 6] * key_offset),
+            decay: timecents(generators[first + 3] + generators[first + 7] * key_offset),
+            sustain: sustain.clamp(0.0, 1.0),
+            release: timecents(generators[first + 5]),
+        };
+    }
+
+}
+
+/// Delay-Attack-Hold-Decay-Sustain-Release envelope of the SF2 format
+///
+/// The volume envelope attacks linearly and decays linearly in dB, the modulation envelope is linear.
+#[derive(Default)]
+struct Sf2Envelope {
+    stage: Sf2EnvelopeStage,
+    level: f64,
+    time: f64,
+    killed: bool,
+}
+
+impl Sf2Envelope {
+
+    fn start(&mut self) {
+        self.stage = Sf2EnvelopeStage::Delay;
+        self.level = 0.0;
+        self.time = 0.0;
+        self.killed = false;
+    }
+
+    fn release(&mut self, volume: bool) {
+        if self.stage != Sf2EnvelopeStage::Idle && self.stage != Sf2EnvelopeStage::Release {
+            //The attack of the volume envelope is linear in amplitude
+            if volume && self.stage == Sf2EnvelopeStage::Attack {
+                self.level = (1.0 + gain_to_db(self.level)/ENVELOPE_RANGE).max(0.0);
+            }
+            if volume && self.stage == Sf2EnvelopeStage::Delay {
+                self.level = 0.0;
+            }
+            self.stage = Sf2EnvelopeStage::Release;
+        }
+    }
+
+    fn kill(&mut self, volume: bool) {
+        self.release(volume);
+        self.killed = true;
+    }
+
+    #[inline(always)]
+    fn is_active(&self) -> bool {
+        return self.stage != Sf2EnvelopeStage::Idle;
+    }
+
+    /// Advances the envelope by one sample and returns it's level, as a gain for volume envelopes
+    fn process(&mut self, params: &Sf2EnvelopeParams, volume: bool, time_step: f64) -> f64 {
+        match self.stage {
+            Sf2EnvelopeStage::Idle => {},
+            Sf2EnvelopeStage::Delay => {
+                self.time += time_step;
+                if self.time >= params.delay {
+                    self.stage = Sf2EnvelopeStage::Attack;
+                }
+            },
+            Sf2EnvelopeStage::Attack => {
+                self.level = if params.attack > 0.0 { self.level + time_step/params.attack } else { 1.0 };
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.time = 0.0;
+                    self.stage = Sf2EnvelopeStage::Hold;
+                }
+            },
+            Sf2EnvelopeStage::Hold => {
+                self.time += time_step;
+                if self.time >= params.hold {
+                    self.stage = Sf2EnvelopeStage::Decay;
+                }
+            },
+            Sf2EnvelopeStage::Decay => {
+                self.level = if params.decay > 0.0 { self.level - time_step/params.decay } else { params.sustain };
+                if self.level <= params.sustain {
+                    self.level = params.sustain;
+                    self.stage = Sf2EnvelopeStage::Sustain;
+                }
+            },
+            Sf2EnvelopeStage::Sustain => self.level = params.sustain,
+            Sf2EnvelopeStage::Release => {
+                let release = if self.killed { KILL_TIME } else { params.release };
+                self.level = if release > 0.0 { self.level - time_step/release } else { 0.0 };
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Sf2EnvelopeStage::Idle;
+                }
+            },
+        }
+        if !volume || self.stage == Sf2EnvelopeStage::Attack || self.stage == Sf2EnvelopeStage::Delay {
+            return self.level;
+        }
+        return if self.level > 0.0 { db_to_gain((self.level - 1.0) * ENVELOPE_RANGE) } else { 0.0 };
+    }
+
+}
+
+/// A single zone played by a voice
+pub struct Sf2Layer {
+    preset: usize,
+    zone: usize,
+    key: u8,
+    velocity: u8,
+    generators: [f64; GENERATOR_COUNT],     //Zone generators with the modulators applied
+    player: SamplePlayer,
+    sample_loop: SampleLoop,
+    vol_env: Sf2Envelope,
+    vol_params: Sf2EnvelopeParams,
+    mod_env: Sf2Envelope,
+    mod_params: Sf2EnvelopeParams,
+    mod_lfo: Lfo,
+    mod_lfo_config: LfoConfig,
+    vib_lfo: Lfo,
+    vib_lfo_config: LfoConfig,
+    filter: [StateVariableFilter; 2],
+    filter_config: SVFilterConfig,
+    filtered: bool,
+    step: f64,          //Playback speed at the key, without tuning and modulation
+    pitch: f64,         //Cents of tuning and pitch wheel
+    gain: (f64, f64),   //Attenuation and pan
+    exclusive_class: i32,
+    active: bool,
+}
+
+impl Default for Sf2Layer {
+    fn default() -> Self {
+        return Sf2Layer {
+            preset: 0,
+            zone: 0,
+            key: 0,
+            velocity: 0,
+            generators: [0.0; GENERATOR_COUNT],
+            player: SamplePlayer::default(),
+            sample_loop: SampleLoop::default(),
+            vol_env: Sf2Envelope::default(),
+            vol_params: Sf2EnvelopeParams::default(),
+            mod_env: Sf2Envelope::default(),
+            mod_params: Sf2EnvelopeParams::default(),
+            mod_lfo: Lfo::default(),
+            mod_lfo_config: LfoConfig::default(),
+            vib_lfo: Lfo::default(),
+            vib_lfo_config: LfoConfig::default(),
+            filter: Default::default(),
+            filter_config: SVFilterConfig::default(),
+            filtered: false,
+            step: 0.0,
+            pitch: 0.0,
+            gain: (0.0, 0.0),
+            exclusive_class: 0,
+            active: false,
+        };
+    }
+}
+
+/// A note can start multiple zones (e.g. the two sides of a stereo sample), so every voice has room for several of them
+#[derive(Default)]
+pub struct Sf2Voice {
+    layers: [Sf2Layer; MAX_ZONES],
+}
+
+/// State of a MIDI channel
+#[derive(Copy, Clone)]
+pub struct Sf2Channel {
+    pub controllers: Sf2Controllers,
+    pub program: u8,
+    pub bank_msb: u16,
+    pub bank_lsb: u16,
+    rpn: (u8, u8),              //Selected registered parameter, (127, 127) if none
+    preset: Option<usize>,
+}
+
+impl Default for Sf2Channel {
+    fn default() -> Self {
+        return Sf2Channel {
+            controllers: Sf2Controllers::default(),
+            program: 0,
+            bank_msb: 0,
+            bank_lsb: 0,
+            rpn: (127, 127),
+            preset: None,
+        };
+    }
+}
+
+impl Sf2Channel {
+
+    /// Index of the preset that new notes play
+    #[inline(always)]
+    pub fn preset(&self) -> Option<usize> {
+        return self.preset;
+    }
+
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sf2SamplerConfig {
+    pub interpolation: Interpolation,
+    pub gain: f64,      //dB
+}
+
+impl Default for Sf2SamplerConfig {
+    fn default() -> Self {
+        return Sf2SamplerConfig {
+            interpolation: Interpolation::Cubic,
+            gain: 0.0,
+        };
+    }
+}
+
+pub struct Sf2Processor {
+    pub config: Sf2SamplerConfig,
+    pub channels: [Sf2Channel; CHANNEL_COUNT],
+    font: Option<SoundFont>,
+    channel: usize,     //Channel of the voice manager that is currently processed
+    table: SincTable,
+    sample_rate: u32,
+    time_step: f64,
+}
+
+impl Sf2Processor {
+
+    /// Looks up the preset for the bank and program of a channel
+    ///
+    /// Missing banks fall back to the MSB only and then to the GM bank, missing drum kits to the standard kit.
+    fn select_preset(&mut self, channel: usize) {
+        let state = &mut self.channels[channel];
+        let font = match &self.font {
+            Some(font) => font,
+            None => {
+                state.preset = None;
+                return;
+            },
+        };
+        let program = state.program as u16;
+        let candidates = if channel == PERCUSSION_CHANNEL {
+            [(128, program), (128, 0), (128, 0)]
+        }
+        else {
+            [(state.bank_msb * 128 + state.bank_lsb, program), (state.bank_msb, program), (0, program)]
+        };
+        state.preset = candidates.iter().find_map(|(bank, program)| font.find_preset(*bank, *program));
+    }
+
+    /// Applies the modulators to the generators of a layer and updates the values derived from them
+    fn update_layer(&self, layer: &mut Sf2Layer) {
+        let zone = match &self.font {
+            Some(font) => &font.presets[layer.preset].zones[layer.zone],
+            None => return,
+        };
+        let controllers = &self.channels[self.channel].controllers;
+        for (value, base) in layer.generators.iter_mut().zip(zone.generators.iter()) {
+            *value = *base as f64;
+        }
+        for modulator in zone.modulators.iter() {
+            if let Some(value) = layer.generators.get_mut(modulator.destination as usize) {
+                *value += modulator.value(controllers, layer.key, layer.velocity);
+            }
+        }
+
+        let g = &layer.generators;
+        layer.pitch = g[GEN_COARSE_TUNE] * 100.0 + g[GEN_FINE_TUNE] + g[GEN_PITCH];
+        let gain = db_to_gain(-g[GEN_ATTENUATION].clamp(0.0, 1440.0) * 0.1);
+        let (left, right) = pan_equal_power(g[GEN_PAN]/500.0);
+        layer.gain = (left * gain, right * gain);
+        layer.filter_config = SVFilterConfig {
+            filter_type: SVFilterType::LowPass,
+            cutoff: absolute_cents(g[GEN_FILTER_FC]),
+            q: std::f64::consts::FRAC_1_SQRT_2 * db_to_gain(g[GEN_FILTER_Q].clamp(0.0, 960.0) * 0.1),
+        };
+        layer.filtered = g[GEN_FILTER_FC] < 13500.0 || g[GEN_MOD_LFO_TO_FILTER_FC] != 0.0 || g[GEN_MOD_ENV_TO_FILTER_FC] != 0.0;
+    }
+
+    fn start_layer(&self, layer: &mut Sf2Layer, preset: usize, index: usize, note: u8, velocity: u8) {
+        let font = match &self.font {
+            Some(font) => font,
+            None => return,
+        };
+        let zone = &font.presets[preset].zones[index];
+        let sample = &font.samples[zone.sample];
+        let g = &zone.generators;
+        layer.preset = preset;
+        layer.zone = index;
+        layer.key = if g[GEN_KEYNUM] >= 0 { g[GEN_KEYNUM].min(127) as u8 } else { note };
+        layer.velocity = if g[GEN_VELOCITY] >= 0 { g[GEN_VELOCITY].min(127) as u8 } else { velocity };
+        self.update_layer(layer);
+
+        //Sample and loop, the offsets are only read from the zone since modulators can't change them
+        let offset = |fine: usize, coarse: usize| g[fine] as i64 + g[coarse] as i64 * 32768;
+        let start = offset(GEN_START_OFFSET, GEN_START_COARSE_OFFSET).max(0);
+        let loop_start = (sample.loop_start as i64 + offset(GEN_LOOP_START_OFFSET, GEN_LOOP_START_COARSE_OFFSET)).max(0) as usize;
+        let loop_end = (sample.loop_end as i64 + offset(GEN_LOOP_END_OFFSET, GEN_LOOP_END_COARSE_OFFSET)).max(0) as usize;
+        let mode = g[GEN_SAMPLE_MODES] & 3;
+        layer.sample_loop = SampleLoop {
+            mode: if mode == 1 || mode == 3 { LoopMode::Forward } else { LoopMode::NoLoop },
+            start: loop_start,
+            end: loop_end,
+            crossfade: 0,
+            sustain: mode == 3,
+        };
+        let root = if g[GEN_ROOT_KEY] >= 0 { g[GEN_ROOT_KEY] } else { sample.root_key as i32 };
+        let cents = (layer.key as i32 - root) as f64 * g[GEN_SCALE_TUNING] as f64 + sample.correction as f64;
+        layer.step = note_to_freq_transpose(cents * 0.01) * sample.buffer.sample_rate() as f64/self.sample_rate as f64;
+        layer.exclusive_class = g[GEN_EXCLUSIVE_CLASS];
+
+        //Envelopes and LFOs
+        let generators = &layer.generators;
+        layer.vol_params = Sf2EnvelopeParams::new(generators, GEN_VOL_ENV_DELAY, layer.key, true);
+        layer.mod_params = Sf2EnvelopeParams::new(generators, GEN_MOD_ENV_DELAY, layer.key, false);
+        let lfo = |delay: usize, freq: usize| LfoConfig {
+            shape: LfoShape::Triangle,
+            rate: LfoRate::Free(absolute_cents(generators[freq])),
+            mode: LfoMode::Retrigger,
+            phase: 0.25,    //Starts at 0 and rises
+            delay: timecents(generators[delay]),
+            fade_in: 0.0,
+        };
+        layer.mod_lfo_config = lfo(GEN_MOD_LFO_DELAY, GEN_MOD_LFO_FREQ);
+        layer.vib_lfo_config = lfo(GEN_VIB_LFO_DELAY, GEN_VIB_LFO_FREQ);
+        layer.mod_lfo.trigger(&layer.mod_lfo_config);
+        layer.vib_lfo.trigger(&layer.vib_lfo_config);
+        layer.vol_env.start();
+        layer.mod_env.start();
+        for filter in layer.filter.iter_mut() {
+            filter.reset();
+        }
+        layer.player.start(start as f64);
+        layer.active = true;
+    }
+
+}
+
+impl voice::VoiceProcessor<Sf2Voice> for Sf2Processor {
+
+    fn process_voice(&mut self, voice: &mut voice::Voice<Sf2Voice>, _info: SampleInfo) -> (f64, f64) {
+        let font = match &self.font {
+            Some(font) => font,
+            None => return (0.0, 0.0),
+        };
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for layer in voice.data.layers.iter_mut().filter(|l| l.active) {
+            let sample = &font.samples[font.presets[layer.preset].zones[layer.zone].sample];
+            let mod_lfo = layer.mod_lfo.process(&layer.mod_lfo_config, 120.0, self.time_step);
+            let vib_lfo = layer.vib_lfo.process(&layer.vib_lfo_config, 120.0, self.time_step);
+            let mod_env = layer.mod_env.process(&layer.mod_params, false, self.time_step);
+            let amp = layer.vol_env.process(&layer.vol_params, true, self.time_step);
+            let g = &layer.generators;
+
+            let cents = layer.pitch + mod_lfo * g[GEN_MOD_LFO_TO_PITCH] + vib_lfo * g[GEN_VIB_LFO_TO_PITCH] + mod_env * g[GEN_MOD_ENV_TO_PITCH];
+            let step = layer.step * note_to_freq_transpose(cents * 0.01);
+            let (mut l, mut r) = layer.player.process(&sample.buffer, &layer.sample_loop, self.config.interpolation, &self.table, step);
+            if layer.filtered {
+                let mut filter = layer.filter_config;
+                filter.cutoff = absolute_cents(g[GEN_FILTER_FC] + mod_lfo * g[GEN_MOD_LFO_TO_FILTER_FC] + mod_env * g[GEN_MOD_ENV_TO_FILTER_FC]);
+                l = layer.filter[0].process(&filter, l, self.time_step);
+                r = layer.filter[1].process(&filter, r, self.time_step);
+            }
+            let gain = amp * db_to_gain(mod_lfo * g[GEN_MOD_LFO_TO_VOLUME] * 0.1);
+            left += l * gain * layer.gain.0;
+            right += r * gain * layer.gain.1;
+            if !layer.vol_env.is_active() || layer.player.is_finished() {
+                layer.active = false;
+            }
+        }
+        let gain = db_to_gain(self.config.gain);
+        return (left * gain, right * gain);
+    }
+
+    fn voice_on(&mut self, voice: &mut voice::Voice<Sf2Voice>, _info: SampleInfo) {
+        for layer in voice.data.layers.iter_mut() {
+            layer.active = false;
+        }
+        let preset = match (&self.font, self.channels[self.channel].preset) {
+            (Some(font), Some(preset)) => &font.presets[preset],
+            _ => return,
+        };
+        let velocity = midi_velocity(voice.velocity);
+        let mut zones = [0; MAX_ZONES];
+        let mut count = 0;
+        for (i, zone) in preset.zones.iter().enumerate() {
+            if count < MAX_ZONES && zone.matches(voice.note, velocity) {
+                zones[count] = i;
+                count += 1;
+            }
+        }
+        let preset = self.channels[self.channel].preset.unwrap_or(0);
+        for (layer, zone) in voice.data.layers.iter_mut().zip(zones[..count].iter()) {
+            self.start_layer(layer, preset, *zone, voice.note, velocity);
+        }
+    }
+
+    fn voice_off(&mut self, voice: &mut voice::Voice<Sf2Voice>, _info: SampleInfo) {
+        for layer in voice.data.layers.iter_mut().filter(|l| l.active) {
+            layer.vol_env.release(true);
+            layer.mod_env.release(false);
+            layer.player.release(&layer.sample_loop);
+        }
+    }
+
+    fn voice_kill(&mut self, voice: &mut voice::Voice<Sf2Voice>, _info: SampleInfo) {
+        for layer in voice.data.layers.iter_mut() {
+            layer.vol_env.kill(true);
+        }
+    }
+
+    fn check_attack_finished(&mut self, voice: &voice::Voice<Sf2Voice>, _info: SampleInfo) -> bool {
+        return voice.data.layers.iter().filter(|l| l.active).all(|l| l.vol_env.stage != Sf2EnvelopeStage::Delay && l.vol_env.stage != Sf2EnvelopeStage::Attack);
+    }
+
+    fn voice_level(&self, voice: &voice::Voice<Sf2Voice>) -> f64 {
+        return voice.data.layers.iter().filter(|l| l.active).map(|l| l.vol_env.level).fold(0.0, f64::max);
+    }
+
+    fn check_inactive(&mut self, voice: &voice::Voice<Sf2Voice>, _info: SampleInfo) -> bool {
+        return !voice.data.layers.iter().any(|l| l.active);
+    }
+
+}
+
+/// Plays SoundFont 2 banks on all 16 MIDI channels, e.g. general MIDI files with a GM bank
+///
+/// Banks are loaded in a background thread, so large files don't block the audio thread.
+pub struct Sf2Sampler {
+    info: DeviceInfo,
+    output: NamedAudioPort,
+    midiin: NamedMidiPort,
+    voice_mgrs: Vec<VoiceManager<Sf2Voice>>,   //One per channel
+    pub proc: Sf2Processor,
+    loader: Option<Receiver<Result<SoundFont, &'static str>>>,
+    loading: bool,
+    retired: Option<SoundFont>,     //Replaced bank, freed by free_retired outside of the audio thread
+    error: Option<&'static str>,
+}
+
+impl Sf2Sampler {
+
+    pub fn new() -> Sf2Sampler {
+        return Sf2Sampler {
+            info: DeviceInfo {
+                name: "SF2 Sampler",
+                type_identifier: "synthi_sam_sf2_sampler",
+            },
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            midiin: NamedMidiPort::new("MIDI In", "midi_in"),
+            voice_mgrs: (0..CHANNEL_COUNT).map(|_| VoiceManager::new(24)).collect(),
+            proc: Sf2Processor {
+                config: Sf2SamplerConfig::default(),
+                channels: [Sf2Channel::default(); CHANNEL_COUNT],
+                font: None,
+                channel: 0,
+                table: SincTable::new(),
+                sample_rate: 0,
+                time_step: 0.0,
+            },
+            loader: None,
+            loading: false,
+            retired: None,
+            error: None,
+        };
+    }
+
+    /// Starts loading an SF2 file in a background thread, the current bank keeps playing until it is finished
+    ///
+    /// This allocates, so it shouldn't be called from the audio thread.
+    pub fn load(&mut self, path: &str) {
+        self.retired = None;
+        let (sender, receiver) = mpsc::channel();
+        let path = path.to_string();
+        std::thread::spawn(move || {
+            let _ = sender.send(SoundFont::load(&path));
+        });
+        self.loader = Some(receiver);
+        self.loading = true;
+        self.error = None;
+    }
+
+    /// Replaces the bank immediately, this shouldn't be called from the audio thread
+    pub fn set_soundfont(&mut self, font: SoundFont) {
+        let info = SampleInfo {
+            sample_count: 0,
+            time: 0.0,
+            jitter: false,
+        };
+        self.reset_voices(info);
+        self.proc.font = Some(font);
+        for channel in 0..CHANNEL_COUNT {
+            self.proc.select_preset(channel);
+        }
+        self.retired = None;
+        self.loader = None;
+        self.loading = false;
+    }
+
+    #[inline(always)]
+    pub fn is_loading(&self) -> bool {
+        return self.loading;
+    }
+
+    /// Error of the last load, if it failed
+    #[inline(always)]
+    pub fn error(&self) -> Option<&'static str> {
+        return self.error;
+    }
+
+    /// Frees the bank that was replaced by the last finished load and returns whether there was one
+    ///
+    /// The audio thread keeps the old bank instead of deallocating it, so the host should call this from a
+    /// non-audio thread after is_loading turned false. Otherwise the old samples stay in memory until the next load.
+    pub fn free_retired(&mut self) -> bool {
+        return self.retired.take().is_some();
+    }
+
+    #[inline(always)]
+    pub fn soundfont(&self) -> Option<&SoundFont> {
+        return self.proc.font.as_ref();
+    }
+
+    fn reset_voices(&mut self, info: SampleInfo) {
+        for (channel, mgr) in self.voice_mgrs.iter_mut().enumerate() {
+            self.proc.channel = channel;
+            mgr.reset(&mut self.proc, info);
+        }
+    }
+
+    /// Swaps in a bank that finished loading
+    fn receive_soundfont(&mut self, info: SampleInfo) {
+        if !self.loading {
+            return;
+        }
+        let result = match self.loader.as_ref().map(|l| l.try_recv()) {
+            Some(Ok(result)) => result,
+            Some(Err(TryRecvError::Empty)) => return,
+            Some(Err(TryRecvError::Disconnected)) | None => Err("SF2 loader stopped unexpectedly"),
+        };
+        self.loading = false;
+        match result {
+            Ok(font) => {
+                //The voices refer to presets of the old bank
+                self.reset_voices(info);
+                self.retired = self.proc.font.replace(font);
+                for channel in 0..CHANNEL_COUNT {
+                    self.proc.select_preset(channel);
+                }
+            },
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Applies changed controllers to the playing voices of a channel
+    fn update_channel(&mut self, channel: usize) {
+        self.proc.channel = channel;
+        for voice in self.voice_mgrs[channel].voices.iter_mut().filter(|v| v.state.is_active()) {
+            for layer in voice.data.layers.iter_mut().filter(|l| l.active) {
+                self.proc.update_layer(layer);
+            }
+        }
+    }
+
+    /// Stops the other zones of the channel that share an exclusive class with a new note (e.g. open and closed hi-hats)
+    fn apply_exclusive_classes(&mut self, channel: usize, note: u8, info: SampleInfo) {
+        let voices = &mut self.voice_mgrs[channel].voices;
+        let index = match voices.iter().position(|v| v.note == note && v.state == VoiceState::Attack && v.press_time == info.time) {
+            Some(index) => index,
+            None => return,
+        };
+        let mut classes = [0; MAX_ZONES];
+        for (class, layer) in classes.iter_mut().zip(voices[index].data.layers.iter()) {
+            if layer.active {
+                *class = layer.exclusive_class;
+            }
+        }
+        for (i, voice) in voices.iter_mut().enumerate() {
+            if i == index || !voice.state.is_active() {
+                continue;
+            }
+            for layer in voice.data.layers.iter_mut() {
+                if layer.active && layer.exclusive_class != 0 && classes.contains(&layer.exclusive_class) {
+                    layer.vol_env.kill(true);
+                }
+            }
+        }
+    }
+
+    fn control_change(&mut self, channel: usize, control: u8, value: f64, info: SampleInfo) {
+        let state = &mut self.proc.channels[channel];
+        let data = (value * 127.0).round() as u8;
+        state.controllers.controllers[control as usize & 0x7F] = value;
+        match control {
+            0 => state.bank_msb = data as u16,
+            32 => state.bank_lsb = data as u16,
+            101 => state.rpn.0 = data,
+            100 => state.rpn.1 = data,
+            98 | 99 => state.rpn = (127, 127),     //NRPNs aren't supported
+            6 if state.rpn == (0, 0) => state.controllers.pitch_wheel_range = data as f64,
+            38 if state.rpn == (0, 0) => state.controllers.pitch_wheel_range = state.controllers.pitch_wheel_range.floor() + data as f64 * 0.01,
+            //Reset all controllers, except volume and pan
+            121 => {
+                let mut controllers = Sf2Controllers::default();
+                controllers.controllers[7] = state.controllers.controllers[7];
+                controllers.controllers[10] = state.controllers.controllers[10];
+                controllers.pitch_wheel_range = state.controllers.pitch_wheel_range;
+                state.controllers = controllers;
+                state.rpn = (127, 127);
+            },
+            //All sound off
+            120 => {
+                self.proc.channel = channel;
+                self.voice_mgrs[channel].reset(&mut self.proc, info);
+            },
+            //All notes off
+            123 => {
+                self.proc.channel = channel;
+                for note in 0..128 {
+                    self.voice_mgrs[channel].release_note(&mut self.proc, note, info);
+                }
+            },
+            _ => {},
+        }
+        self.proc.channel = channel;
+        self.voice_mgrs[channel].control_change(&mut self.proc, control, value, info); //Pedals
+        self.update_channel(channel);
+    }
+
+}
+
+impl Device for Sf2Sampler {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.output.port.reset();
+        self.midiin.port.reset();
+        self.proc.sample_rate = info.sample_rate;
+        self.proc.time_step = info.time_step;
+        let i = SampleInfo {
+            sample_count: 0,
+            time: 0.0,
+            jitter: false,
+        };
+        self.reset_voices(i);
+    }
+
+    fn process(&mut self, info: SampleInfo) {
+        self.receive_soundfont(info);
+        while let Some(msg) = self.midiin.port.pop() {
+            let channel = msg.channel as usize % CHANNEL_COUNT;
+            self.proc.channel = channel;
+            match msg.message {
+                MidiMessageContent::NoteOn(note) => {
+                    self.voice_mgrs[channel].press_note(&mut self.proc, note.note, note.velocity, info);
+                    self.apply_exclusive_classes(channel, note.note, info);
+                },
+                MidiMessageContent::NoteOff(note) => self.voice_mgrs[channel].release_note(&mut self.proc, note.note, info),
+                MidiMessageContent::ControlChange(cc) => self.control_change(channel, cc.control, cc.value, info),
+                MidiMessageContent::ProgramChange(program) => {
+                    self.proc.channels[channel].program = program.program & 0x7F;
+                    self.proc.select_preset(channel);
+                },
+                MidiMessageContent::PitchBend(bend) => {
+                    self.proc.channels[channel].controllers.pitch_wheel = (bend.pitch_bend + 1.0) * 0.5;
+                    self.update_channel(channel);
+                },
+                MidiMessageContent::MonophonicAftertouch(aftertouch) => {
+                    self.proc.channels[channel].controllers.channel_pressure = aftertouch.aftertouch;
+                    self.update_channel(channel);
+                },
+                _ => {},
+            }
+        }
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (channel, mgr) in self.voice_mgrs.iter_mut().enumerate() {
+            self.proc.channel = channel;
+            let (l, r) = mgr.process_voices(&mut self.proc, info);
+            left += l;
+            right += r;
+        }
+        self.output.port.take_input(&[left, right]);
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.midiin),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
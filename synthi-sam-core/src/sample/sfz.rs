@@ -0,0 +1,693 @@
+use std::{fmt::Display, path::{Path, PathBuf}};
+
+use crate::{dsp::{envelope::ADSREnvelopeConfig, filter::{SVFilterConfig, SVFilterType}, dynamics::db_to_gain}, util::random::Random};
+
+use super::{SampleBuffer, player::{SampleLoop, LoopMode}};
+
+/// Maximum number of regions a single note can start
+pub const MAX_LAYERS: usize = 8;
+/// Maximum nesting of #include directives, stops files that include themselves
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SfzTrigger {
+    Attack,     //Note on
+    Release,    //Note off, using the velocity of the note on
+    First,      //Note on while no other key is held
+    Legato,     //Note on while another key is held
+}
+
+impl Default for SfzTrigger {
+    fn default() -> Self {
+        return SfzTrigger::Attack;
+    }
+}
+
+impl Display for SfzTrigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SfzTrigger::Attack => "Attack",
+            SfzTrigger::Release => "Release",
+            SfzTrigger::First => "First",
+            SfzTrigger::Legato => "Legato",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SfzLoopMode {
+    NoLoop,
+    OneShot,        //Plays the whole sample, ignoring the note off
+    Continuous,
+    Sustain,        //Loops while the key is held
+}
+
+impl Default for SfzLoopMode {
+    fn default() -> Self {
+        return SfzLoopMode::NoLoop;
+    }
+}
+
+impl Display for SfzLoopMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SfzLoopMode::NoLoop => "No Loop",
+            SfzLoopMode::OneShot => "One Shot",
+            SfzLoopMode::Continuous => "Continuous",
+            SfzLoopMode::Sustain => "Sustain",
+        })
+    }
+}
+
+/// A sample mapped to a key and velocity range, the fields follow the SFZ opcodes
+#[derive(Clone)]
+pub struct SfzRegion {
+    pub sample: usize,              //Index into the samples of the instrument
+    pub lokey: u8,
+    pub hikey: u8,
+    pub lovel: u8,
+    pub hivel: u8,
+    pub lorand: f64,
+    pub hirand: f64,
+    pub trigger: SfzTrigger,
+    pub seq_length: u32,            //Round robin
+    pub seq_position: u32,          //Starts at 1
+    pub pitch_keycenter: u8,
+    pub pitch_keytrack: f64,        //Cents per key
+    pub transpose: i32,             //Semitones
+    pub tune: f64,                  //Cents
+    pub volume: f64,                //dB
+    pub pan: f64,                   //-100 to 100
+    pub amp_veltrack: f64,          //Percent
+    pub rt_decay: f64,              //dB per second the key was held, release triggers only
+    pub offset: usize,              //Frames
+    pub loop_mode: Option<SfzLoopMode>,
+    pub loop_start: usize,          //Frames
+    pub loop_end: Option<usize>,    //Frames, the last frame of the loop
+    pub loop_crossfade: f64,        //Seconds
+    pub amp_envelope: ADSREnvelopeConfig,
+    pub filter_type: SVFilterType,
+    pub cutoff: Option<f64>,        //Hz, no filter if not set
+    pub resonance: f64,             //dB
+    pub filter_envelope: ADSREnvelopeConfig,
+    pub filter_depth: f64,          //Cents
+}
+
+impl Default for SfzRegion {
+    fn default() -> Self {
+        return SfzRegion {
+            sample: 0,
+            lokey: 0,
+            hikey: 127,
+            lovel: 1,
+            hivel: 127,
+            lorand: 0.0,
+            hirand: 1.0,
+            trigger: SfzTrigger::Attack,
+            seq_length: 1,
+            seq_position: 1,
+            pitch_keycenter: 60,
+            pitch_keytrack: 100.0,
+            transpose: 0,
+            tune: 0.0,
+            volume: 0.0,
+            pan: 0.0,
+            amp_veltrack: 100.0,
+            rt_decay: 0.0,
+            offset: 0,
+            loop_mode: None,
+            loop_start: 0,
+            loop_end: None,
+            loop_crossfade: 0.0,
+            amp_envelope: ADSREnvelopeConfig {
+                attack: 0.0,
+                decay: 0.0,
+                sustain: 1.0,
+                release: 0.001,
+                velocity_amount: 1.0,
+                ..ADSREnvelopeConfig::default()
+            },
+            filter_type: SVFilterType::LowPass,
+            cutoff: None,
+            resonance: 0.0,
+            filter_envelope: ADSREnvelopeConfig {
+                attack: 0.0,
+                decay: 0.0,
+                sustain: 0.0,
+                release: 0.0,
+                ..ADSREnvelopeConfig::default()
+            },
+            filter_depth: 0.0,
+        };
+    }
+}
+
+impl SfzRegion {
+
+    /// Loops only if a loop end was given, unless the mode is set explicitly
+    pub fn loop_mode(&self) -> SfzLoopMode {
+        return self.loop_mode.unwrap_or(if self.loop_end.is_some() { SfzLoopMode::Continuous } else { SfzLoopMode::NoLoop });
+    }
+
+    /// Loop of the region in the frames of it's sample
+    pub fn sample_loop(&self, sample: &SampleBuffer) -> SampleLoop {
+        let mode = self.loop_mode();
+        if mode != SfzLoopMode::Continuous && mode != SfzLoopMode::Sustain {
+            return SampleLoop::default();
+        }
+        let end = self.loop_end.map_or(sample.frames(), |e| e + 1);
+        return SampleLoop {
+            mode: LoopMode::Forward,
+            start: self.loop_start,
+            end: end,
+            crossfade: (self.loop_crossfade * sample.sample_rate() as f64).round() as usize,
+            sustain: mode == SfzLoopMode::Sustain,
+        };
+    }
+
+    /// Filter at the base cutoff, None if the region isn't filtered
+    pub fn filter(&self) -> Option<SVFilterConfig> {
+        return self.cutoff.map(|cutoff| SVFilterConfig {
+            filter_type: self.filter_type,
+            cutoff: cutoff,
+            q: std::f64::consts::FRAC_1_SQRT_2 * db_to_gain(self.resonance),
+        });
+    }
+
+    /// Checks wether the region plays a note, ignoring round robin and random ranges
+    #[inline]
+    pub fn matches(&self, note: u8, velocity: u8, release: bool, legato: bool) -> bool {
+        let trigger = match self.trigger {
+            SfzTrigger::Attack => !release,
+            SfzTrigger::Release => release,
+            SfzTrigger::First => !release && !legato,
+            SfzTrigger::Legato => !release && legato,
+        };
+        return trigger && note >= self.lokey && note <= self.hikey && velocity >= self.lovel && velocity <= self.hivel;
+    }
+
+    /// Applies a single opcode, unknown opcodes and values are ignored
+    fn apply(&mut self, name: &str, value: &str) -> Result<(), &'static str> {
+        match name {
+            "lokey" => self.lokey = parse_note(value)?,
+            "hikey" => self.hikey = parse_note(value)?,
+            "key" => {
+                let key = parse_note(value)?;
+                self.lokey = key;
+                self.hikey = key;
+                self.pitch_keycenter = key;
+            },
+            "lovel" => self.lovel = parse_number(value)?.clamp(0.0, 127.0) as u8,
+            "hivel" => self.hivel = parse_number(value)?.clamp(0.0, 127.0) as u8,
+            "lorand" => self.lorand = parse_number(value)?,
+            "hirand" => self.hirand = parse_number(value)?,
+            "trigger" => self.trigger = match value {
+                "attack" => SfzTrigger::Attack,
+                "release" => SfzTrigger::Release,
+                "first" => SfzTrigger::First,
+                "legato" => SfzTrigger::Legato,
+                _ => self.trigger,
+            },
+            "seq_length" => self.seq_length = (parse_number(value)? as u32).max(1),
+            "seq_position" => self.seq_position = (parse_number(value)? as u32).max(1),
+            "pitch_keycenter" => self.pitch_keycenter = parse_note(value)?,
+            "pitch_keytrack" => self.pitch_keytrack = parse_number(value)?,
+            "transpose" => self.transpose = parse_number(value)? as i32,
+            "tune" | "pitch" => self.tune = parse_number(value)?,
+            "volume" | "gain" => self.volume = parse_number(value)?,
+            "pan" => self.pan = parse_number(value)?.clamp(-100.0, 100.0),
+            "amp_veltrack" => self.amp_veltrack = parse_number(value)?,
+            "rt_decay" => self.rt_decay = parse_number(value)?,
+            "offset" => self.offset = parse_number(value)?.max(0.0) as usize,
+            "loop_mode" | "loopmode" => self.loop_mode = match value {
+                "no_loop" => Some(SfzLoopMode::NoLoop),
+                "one_shot" => Some(SfzLoopMode::OneShot),
+                "loop_continuous" => Some(SfzLoopMode::Continuous),
+                "loop_sustain" => Some(SfzLoopMode::Sustain),
+                _ => self.loop_mode,
+            },
+            "loop_start" | "loopstart" => self.loop_start = parse_number(value)?.max(0.0) as usize,
+            "loop_end" | "loopend" => self.loop_end = Some(parse_number(value)?.max(0.0) as usize),
+            "loop_crossfade" => self.loop_crossfade = parse_number(value)?.max(0.0),
+            "ampeg_attack" => self.amp_envelope.attack = parse_number(value)?.max(0.0),
+            "ampeg_decay" => self.amp_envelope.decay = parse_number(value)?.max(0.0),
+            "ampeg_sustain" => self.amp_envelope.sustain = parse_number(value)?.clamp(0.0, 100.0) * 0.01,
+            "ampeg_release" => self.amp_envelope.release = parse_number(value)?.max(0.0),
+            "fil_type" | "filtype" => self.filter_type = match value.split('_').next() {
+                Some("lpf") => SVFilterType::LowPass,
+                Some("hpf") => SVFilterType::HighPass,
+                Some("bpf") => SVFilterType::BandPass,
+                Some("brf") => SVFilterType::Notch,
+                Some("pkf") => SVFilterType::Peak,
+                _ => self.filter_type,
+            },
+            "cutoff" => self.cutoff = Some(parse_number(value)?.max(0.0)),
+            "resonance" => self.resonance = parse_number(value)?,
+            "fileg_attack" => self.filter_envelope.attack = parse_number(value)?.max(0.0),
+            "fileg_decay" => self.filter_envelope.decay = parse_number(value)?.max(0.0),
+            "fileg_sustain" => self.filter_envelope.sustain = parse_number(value)?.clamp(0.0, 100.0) * 0.01,
+            "fileg_release" => self.filter_envelope.release = parse_number(value)?.max(0.0),
+            "fileg_depth" => self.filter_depth = parse_number(value)?,
+            _ => {},
+        }
+        //The velocity tracking is set after the envelope so the order of the opcodes doesn't matter
+        self.amp_envelope.velocity_amount = (self.amp_veltrack * 0.01).clamp(0.0, 1.0);
+        return Ok(());
+    }
+
+}
+
+#[inline]
+fn parse_number(value: &str) -> Result<f64, &'static str> {
+    return value.parse::<f64>().map_err(|_| "Invalid SFZ opcode value");
+}
+
+/// Parses a MIDI note number or a note name like c#4, where c4 is 60
+fn parse_note(value: &str) -> Result<u8, &'static str> {
+    if let Ok(note) = value.parse::<i32>() {
+        return Ok(note.clamp(0, 127) as u8);
+    }
+    let lower = value.to_ascii_lowercase();
+    let mut chars = lower.chars();
+    let mut note = match chars.next() {
+        Some('c') => 0,
+        Some('d') => 2,
+        Some('e') => 4,
+        Some('f') => 5,
+        Some('g') => 7,
+        Some('a') => 9,
+        Some('b') => 11,
+        _ => return Err("Invalid SFZ note name"),
+    };
+    let rest = chars.as_str();
+    let octave = if let Some(rest) = rest.strip_prefix('#') {
+        note += 1;
+        rest
+    }
+    else if let Some(rest) = rest.strip_prefix('b').filter(|r| !r.is_empty()) {
+        note -= 1;
+        rest
+    }
+    else {
+        rest
+    };
+    let octave: i32 = octave.parse().map_err(|_| "Invalid SFZ note name")?;
+    return Ok(((octave + 1) * 12 + note).clamp(0, 127) as u8);
+}
+
+#[derive(Debug, PartialEq)]
+enum SfzToken<'a> {
+    Header(&'a str),
+    Opcode(&'a str, &'a str),
+}
+
+/// Removes line and block comments
+fn strip_comments(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let line = rest.find("//");
+        let block = rest.find("/*");
+        match (line, block) {
+            (Some(l), b) if b.is_none_or(|b| l < b) => {
+                result.push_str(&rest[..l]);
+                rest = rest[l..].find('\n').map_or("", |end| &rest[l + end..]);
+            },
+            (_, Some(b)) => {
+                result.push_str(&rest[..b]);
+                result.push(' ');
+                rest = rest[b..].find("*/").map_or("", |end| &rest[b + end + 2..]);
+            },
+            _ => {
+                result.push_str(rest);
+                rest = "";
+            },
+        }
+    }
+    return result;
+}
+
+/// Returns the length of an opcode value
+///
+/// Values may contain spaces (e.g. sample paths), so they end at the next header or the next word containing a '='.
+fn value_length(text: &str) -> usize {
+    let end = text.find(['<', '\n']).unwrap_or(text.len());
+    let bytes = text.as_bytes();
+    for i in 1..end {
+        if bytes[i - 1].is_ascii_whitespace() && !bytes[i].is_ascii_whitespace() && text[i..end].split_whitespace().next().is_some_and(|w| w.contains('=')) {
+            return i;
+        }
+    }
+    return end;
+}
+
+fn tokenize(text: &str) -> Result<Vec<SfzToken<'_>>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if let Some(header) = rest.strip_prefix('<') {
+            let end = header.find('>').ok_or("Unterminated SFZ header")?;
+            tokens.push(SfzToken::Header(header[..end].trim()));
+            rest = header[end + 1..].trim_start();
+        }
+        else {
+            let eq = rest.find('=').ok_or("Invalid SFZ opcode")?;
+            let name = rest[..eq].trim();
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err("Invalid SFZ opcode");
+            }
+            let value = &rest[eq + 1..];
+            let length = value_length(value);
+            tokens.push(SfzToken::Opcode(name, value[..length].trim()));
+            rest = value[length..].trim_start();
+        }
+    }
+    return Ok(tokens);
+}
+
+/// Replaces the variables of #define directives
+fn substitute(line: &str, defines: &[(String, String)]) -> String {
+    let mut result = line.to_string();
+    for (name, value) in defines.iter().filter(|(name, _)| line.contains(name.as_str())) {
+        result = result.replace(name.as_str(), value);
+    }
+    return result;
+}
+
+/// Removes comments and resolves #define and #include directives, included files are relative to the directory
+fn preprocess(text: &str, directory: &Path, defines: &mut Vec<(String, String)>, depth: usize) -> Result<String, &'static str> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err("SFZ includes are nested too deep");
+    }
+    let mut result = String::with_capacity(text.len());
+    for line in strip_comments(text).lines() {
+        let trimmed = line.trim_start();
+        if let Some(define) = trimmed.strip_prefix("#define") {
+            let define = define.trim();
+            let (name, value) = define.split_once(char::is_whitespace).unwrap_or((define, ""));
+            if !name.starts_with('$') || name.len() < 2 {
+                return Err("Invalid SFZ define");
+            }
+            let value = substitute(value.trim(), defines);
+            match defines.iter_mut().find(|(n, _)| n == name) {
+                Some(define) => define.1 = value,
+                None => {
+                    defines.push((name.to_string(), value));
+                    //Longer names first, so $KEY doesn't replace the start of $KEY_HIGH
+                    defines.sort_by_key(|(n, _)| std::cmp::Reverse(n.len()));
+                },
+            }
+        }
+        else if let Some(include) = trimmed.strip_prefix("#include") {
+            let file = substitute(include.trim(), defines);
+            let file = file.trim_matches('"').replace('\\', "/");
+            let data = std::fs::read(directory.join(file)).map_err(|_| "Couldn't read included SFZ file")?;
+            result.push_str(&preprocess(&String::from_utf8_lossy(&data), directory, defines, depth + 1)?);
+        }
+        else if trimmed.starts_with('#') {
+            return Err("Unsupported SFZ directive");
+        }
+        else {
+            result.push_str(&substitute(line, defines));
+        }
+        result.push('\n');
+    }
+    return Ok(result);
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum SfzHeader {
+    Control,
+    Global,
+    Master,
+    Group,
+    Region,
+    Other,      //Unsupported headers like <curve> or <effect>, their opcodes are skipped
+}
+
+/// Parsed SFZ file before the samples are loaded
+#[derive(Clone, Default)]
+pub struct SfzDefinition {
+    pub regions: Vec<SfzRegion>,
+    pub samples: Vec<PathBuf>,
+    pub note_offset: i32,   //Semitones added to incoming notes
+}
+
+impl SfzDefinition {
+
+    /// Parses the text of an SFZ file, sample paths and included files are relative to the directory
+    pub fn parse(text: &str, directory: &Path) -> Result<SfzDefinition, &'static str> {
+        let text = preprocess(text, directory, &mut Vec::new(), 0)?;
+        let mut definition = SfzDefinition::default();
+        let mut default_path = PathBuf::new();
+        let mut header = SfzHeader::Other;
+        //Opcodes of the enclosing headers, inherited by the regions
+        let mut global: Vec<(&str, &str)> = Vec::new();
+        let mut master: Vec<(&str, &str)> = Vec::new();
+        let mut group: Vec<(&str, &str)> = Vec::new();
+        let mut region: Vec<(&str, &str)> = Vec::new();
+
+        let tokens = tokenize(&text)?;
+        for token in tokens.iter().chain(std::iter::once(&SfzToken::Header(""))) {
+            match *token {
+                SfzToken::Header(name) => {
+                    if header == SfzHeader::Region {
+                        definition.add_region(&[&global, &master, &group, &region], directory, &default_path)?;
+                    }
+                    header = match name {
+                        "control" => SfzHeader::Control,
+                        "global" => SfzHeader::Global,
+                        "master" => SfzHeader::Master,
+                        "group" => SfzHeader::Group,
+                        "region" => SfzHeader::Region,
+                        _ => SfzHeader::Other,
+                    };
+                    //A header clears everything below it
+                    match header {
+                        SfzHeader::Global => {
+                            global.clear();
+                            master.clear();
+                            group.clear();
+                        },
+                        SfzHeader::Master => {
+                            master.clear();
+                            group.clear();
+                        },
+                        SfzHeader::Group => group.clear(),
+                        _ => {},
+                    }
+                    region.clear();
+                },
+                SfzToken::Opcode(name, value) => match header {
+                    SfzHeader::Control => match name {
+                        "default_path" => default_path = PathBuf::from(value.replace('\\', "/")),
+                        "note_offset" => definition.note_offset += parse_number(value)? as i32,
+                        "octave_offset" => definition.note_offset += parse_number(value)? as i32 * 12,
+                        _ => {},
+                    },
+                    SfzHeader::Global => global.push((name, value)),
+                    SfzHeader::Master => master.push((name, value)),
+                    SfzHeader::Group => group.push((name, value)),
+                    SfzHeader::Region => region.push((name, value)),
+                    SfzHeader::Other => {},
+                },
+            }
+        }
+        return Ok(definition);
+    }
+
+    /// Reads and parses an SFZ file
+    pub fn load(path: &str) -> Result<SfzDefinition, &'static str> {
+        let data = std::fs::read(path).map_err(|_| "Couldn't read SFZ file")?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+        return SfzDefinition::parse(&String::from_utf8_lossy(&data), directory);
+    }
+
+    fn add_region(&mut self, levels: &[&Vec<(&str, &str)>], directory: &Path, default_path: &Path) -> Result<(), &'static str> {
+        let mut region = SfzRegion::default();
+        let mut sample = None;
+        for (name, value) in levels.iter().flat_map(|l| l.iter()) {
+            if *name == "sample" {
+                sample = Some(*value);
+            }
+            else {
+                region.apply(name, value)?;
+            }
+        }
+        //Regions without a sample file (or with generated sounds like *sine) are skipped
+        let sample = match sample {
+            Some(s) if !s.starts_with('*') => s,
+            _ => return Ok(()),
+        };
+        let path = directory.join(default_path).join(sample.replace('\\', "/"));
+        region.sample = match self.samples.iter().position(|p| *p == path) {
+            Some(index) => index,
+            None => {
+                self.samples.push(path);
+                self.samples.len() - 1
+            },
+        };
+        self.regions.push(region);
+        return Ok(());
+    }
+
+}
+
+/// SFZ instrument with all of it's samples loaded
+pub struct SfzInstrument {
+    pub regions: Vec<SfzRegion>,
+    pub samples: Vec<SampleBuffer>,
+    pub note_offset: i32,
+    sequence: Vec<u32>,     //Round robin counter of each region
+    random: Random,
+}
+
+impl SfzInstrument {
+
+    pub fn new(definition: SfzDefinition, samples: Vec<SampleBuffer>) -> Result<SfzInstrument, &'static str> {
+        if definition.regions.iter().any(|r| r.sample >= samples.len()) {
+            return Err("SFZ region refers to a missing sample");
+        }
+        return Ok(SfzInstrument {
+            sequence: vec![0; definition.regions.len()],
+            regions: definition.regions,
+            samples: samples,
+            note_offset: definition.note_offset,
+            random: Random::default(),
+        });
+    }
+
+    /// Parses an SFZ file and decodes all of it's samples
+    ///
+    /// The samples are decoded on multiple threads, but this still blocks for a long time with large sample sets.
+    pub fn load(path: &str) -> Result<SfzInstrument, &'static str> {
+        let definition = SfzDefinition::load(path)?;
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = definition.samples.len().div_ceil(threads).max(1);
+        let samples = std::thread::scope(|scope| {
+            let handles: Vec<_> = definition.samples.chunks(chunk_size).map(|paths| scope.spawn(move || {
+                return paths.iter()
+                    .map(|p| SampleBuffer::load(p.to_str().ok_or("Invalid sample path")?))
+                    .collect::<Result<Vec<SampleBuffer>, &'static str>>();
+            })).collect();
+            return handles.into_iter()
+                .map(|h| h.join().unwrap_or(Err("Sample loading thread panicked")))
+                .collect::<Result<Vec<Vec<SampleBuffer>>, &'static str>>();
+        })?;
+        return SfzInstrument::new(definition, samples.into_iter().flatten().collect());
+    }
+
+    /// Applies the note offset of the instrument to an incoming note
+    #[inline]
+    pub fn map_note(&self, note: u8) -> u8 {
+        return (note as i32 + self.note_offset).clamp(0, 127) as u8;
+    }
+
+    /// Checks wether releasing a mapped note can start release triggers
+    pub fn has_release_regions(&self, note: u8, velocity: u8) -> bool {
+        return self.regions.iter().any(|r| r.matches(note, velocity, true, false));
+    }
+
+    /// Collects the regions that a mapped note starts and returns their count
+    ///
+    /// The round robin counters of all regions that match the note advance, even if the region doesn't play.
+    pub fn select_regions(&mut self, note: u8, velocity: u8, release: bool, legato: bool, regions: &mut [usize; MAX_LAYERS]) -> usize {
+        let random = self.random.next_f64();
+        let mut count = 0;
+        for (i, region) in self.regions.iter().enumerate() {
+            if !region.matches(note, velocity, release, legato) {
+                continue;
+            }
+            let position = self.sequence[i] % region.seq_length;
+            self.sequence[i] = self.sequence[i].wrapping_add(1);
+            if position + 1 == region.seq_position && random >= region.lorand && random < region.hirand && count < MAX_LAYERS {
+                regions[count] = i;
+                count += 1;
+            }
+        }
+        return count;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> SfzDefinition {
+        return SfzDefinition::parse(text, Path::new("/samples")).unwrap();
+    }
+
+    #[test]
+    fn note_names() {
+        assert_eq!(parse_note("60"), Ok(60));
+        assert_eq!(parse_note("c4"), Ok(60));
+        assert_eq!(parse_note("C#4"), Ok(61));
+        assert_eq!(parse_note("db4"), Ok(61));
+        assert_eq!(parse_note("b3"), Ok(59));
+        assert_eq!(parse_note("bb3"), Ok(58));
+        assert_eq!(parse_note("c-1"), Ok(0));
+        assert_eq!(parse_note("g9"), Ok(127));
+        assert!(parse_note("h4").is_err());
+    }
+
+    #[test]
+    fn comments_and_values_with_spaces() {
+        let definition = parse("<region> sample=Grand Piano/C4 soft.wav lokey=c4 // hikey=c5\n/* block\n comment */ hikey=e4 volume=-3.5");
+        assert_eq!(definition.samples, vec![PathBuf::from("/samples/Grand Piano/C4 soft.wav")]);
+        let region = &definition.regions[0];
+        assert_eq!((region.lokey, region.hikey), (60, 64));
+        assert_eq!(region.volume, -3.5);
+        assert_eq!(value_length("a b.wav key=1"), 8);
+        assert_eq!(value_length("a.wav<region>"), 5);
+    }
+
+    #[test]
+    fn header_inheritance() {
+        let definition = parse("
+            <control> default_path=Piano/ note_offset=12
+            <global> volume=-6 trigger=unknown
+            <group> lokey=10
+            <region> sample=a.wav
+            <region> sample=b.wav lokey=20 volume=-1
+            <group> hikey=50
+            <region> sample=a.wav
+        ");
+        assert_eq!(definition.note_offset, 12);
+        assert_eq!(definition.samples, vec![PathBuf::from("/samples/Piano/a.wav"), PathBuf::from("/samples/Piano/b.wav")]);
+        let regions = &definition.regions;
+        assert_eq!(regions.len(), 3);
+        assert_eq!((regions[0].sample, regions[0].lokey, regions[0].volume), (0, 10, -6.0));
+        assert_eq!((regions[1].sample, regions[1].lokey, regions[1].volume), (1, 20, -1.0));
+        //A new group drops the opcodes of the previous one, but keeps the global ones
+        assert_eq!((regions[2].sample, regions[2].lokey, regions[2].hikey, regions[2].volume), (0, 0, 50, -6.0));
+        assert_eq!(regions[2].trigger, SfzTrigger::Attack);
+    }
+
+    #[test]
+    fn defines() {
+        let definition = parse("#define $KEY 62\n#define $KEY_HIGH 64\n#define $NAME piano $KEY\n<region> sample=$NAME.wav lokey=$KEY hikey=$KEY_HIGH");
+        assert_eq!(definition.samples, vec![PathBuf::from("/samples/piano 62.wav")]);
+        assert_eq!((definition.regions[0].lokey, definition.regions[0].hikey), (62, 64));
+        assert!(SfzDefinition::parse("#define KEY 62", Path::new("")).is_err());
+        assert!(SfzDefinition::parse("#pragma once", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn includes() {
+        let directory = std::env::temp_dir().join(format!("synthi_sam_sfz_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("parts")).unwrap();
+        std::fs::write(directory.join("parts/regions.sfz"), "<region> sample=$NAME.wav key=$KEY\n").unwrap();
+        std::fs::write(directory.join("parts/loop.sfz"), "#include \"parts/loop.sfz\"\n").unwrap();
+
+        let definition = SfzDefinition::parse("#define $NAME a\n#define $KEY 40\n#include \"parts/regions.sfz\"\n#define $KEY 41\n#include \"parts/regions.sfz\"", &directory).unwrap();
+        assert_eq!(definition.regions.len(), 2);
+        assert_eq!((definition.regions[0].lokey, definition.regions[1].lokey), (40, 41));
+        assert_eq!(definition.samples, vec![directory.join("a.wav")]);
+        //Missing and recursive includes are errors instead of silently missing regions
+        assert!(SfzDefinition::parse("#include \"missing.sfz\"", &directory).is_err());
+        assert!(SfzDefinition::parse("#include \"parts/loop.sfz\"", &directory).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+}
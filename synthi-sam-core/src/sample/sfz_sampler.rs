@@ -0,0 +1,361 @@
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use crate::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::MidiMessageContent}, dsp::{envelope::{ADSREnvelope, ADSRStage}, filter::StateVariableFilter, interpolation::{Interpolation, SincTable}, dynamics::db_to_gain, note_to_freq_transpose, pan_equal_power}, util::voice::{self, VoiceManager, VoiceState}};
+
+use super::{midi_velocity, player::{SamplePlayer, SampleLoop}, sfz::{SfzInstrument, SfzLoopMode, MAX_LAYERS}};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SfzSamplerConfig {
+    pub interpolation: Interpolation,
+    pub gain: f64,                  //dB
+    pub pitch_bend_range: f64,      //Semitones
+}
+
+impl Default for SfzSamplerConfig {
+    fn default() -> Self {
+        return SfzSamplerConfig {
+            interpolation: Interpolation::Cubic,
+            gain: 0.0,
+            pitch_bend_range: 2.0,
+        };
+    }
+}
+
+/// A single region played by a voice
+#[derive(Default)]
+pub struct SfzLayer {
+    region: usize,
+    player: SamplePlayer,
+    sample_loop: SampleLoop,
+    amp_env: ADSREnvelope,
+    filter_env: ADSREnvelope,
+    filter: [StateVariableFilter; 2],
+    gain: (f64, f64),   //Volume and pan
+    step: f64,          //Playback speed without the pitch bend
+    release: bool,      //Started by the release of the key
+    active: bool,
+}
+
+/// A note can start multiple regions (layers and release triggers), so every voice has room for several of them
+#[derive(Default)]
+pub struct SfzVoice {
+    layers: [SfzLayer; MAX_LAYERS],
+    release_trigger: bool,  //Keeps the voice alive while the key is held, even if no region plays
+}
+
+pub struct SfzProcessor {
+    pub config: SfzSamplerConfig,
+    instrument: Option<SfzInstrument>,
+    table: SincTable,
+    sample_rate: u32,
+    time_step: f64,
+    pitch_bend: f64,
+}
+
+impl SfzProcessor {
+
+    fn start_layer(&self, layer: &mut SfzLayer, index: usize, note: u8, velocity: f64, gain: f64, release: bool) {
+        let instrument = match &self.instrument {
+            Some(instrument) => instrument,
+            None => return,
+        };
+        let region = &instrument.regions[index];
+        let sample = &instrument.samples[region.sample];
+        let cents = (note as f64 - region.pitch_keycenter as f64) * region.pitch_keytrack + region.transpose as f64 * 100.0 + region.tune;
+        let (left, right) = pan_equal_power(region.pan * 0.01);
+        let gain = db_to_gain(region.volume + gain);
+
+        layer.region = index;
+        //Release samples are played once, a loop would keep them sounding forever
+        layer.sample_loop = if release { SampleLoop::default() } else { region.sample_loop(sample) };
+        layer.step = note_to_freq_transpose(cents * 0.01) * sample.sample_rate() as f64/self.sample_rate as f64;
+        layer.gain = (left * gain, right * gain);
+        layer.release = release;
+        layer.active = true;
+        layer.player.start(region.offset as f64);
+        layer.amp_env.reset();
+        layer.amp_env.press(&region.amp_envelope, velocity);
+        layer.filter_env.reset();
+        layer.filter_env.press(&region.filter_envelope, velocity);
+        for filter in layer.filter.iter_mut() {
+            filter.reset();
+        }
+    }
+
+}
+
+impl voice::VoiceProcessor<SfzVoice> for SfzProcessor {
+
+    fn process_voice(&mut self, voice: &mut voice::Voice<SfzVoice>, _info: SampleInfo) -> (f64, f64) {
+        let instrument = match &self.instrument {
+            Some(instrument) => instrument,
+            None => return (0.0, 0.0),
+        };
+        let bend = note_to_freq_transpose(self.pitch_bend * self.config.pitch_bend_range);
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for layer in voice.data.layers.iter_mut().filter(|l| l.active) {
+            let region = &instrument.regions[layer.region];
+            let sample = &instrument.samples[region.sample];
+            let amp = layer.amp_env.process(&region.amp_envelope, self.time_step);
+            let (mut l, mut r) = layer.player.process(sample, &layer.sample_loop, self.config.interpolation, &self.table, layer.step * bend);
+            if let Some(mut filter) = region.filter() {
+                let modulation = layer.filter_env.process(&region.filter_envelope, self.time_step);
+                filter.cutoff *= note_to_freq_transpose(region.filter_depth * 0.01 * modulation);
+                l = layer.filter[0].process(&filter, l, self.time_step);
+                r = layer.filter[1].process(&filter, r, self.time_step);
+            }
+            left += l * amp * layer.gain.0;
+            right += r * amp * layer.gain.1;
+            if !layer.amp_env.is_active() || layer.player.is_finished() {
+                layer.active = false;
+            }
+        }
+        let gain = db_to_gain(self.config.gain);
+        return (left * gain, right * gain);
+    }
+
+    fn voice_on(&mut self, voice: &mut voice::Voice<SfzVoice>, _info: SampleInfo) {
+        for layer in voice.data.layers.iter_mut() {
+            layer.active = false;
+        }
+        let mut regions = [0; MAX_LAYERS];
+        let (note, count) = match self.instrument.as_mut() {
+            Some(instrument) => {
+                let note = instrument.map_note(voice.note);
+                voice.data.release_trigger = instrument.has_release_regions(note, midi_velocity(voice.velocity));
+                (note, instrument.select_regions(note, midi_velocity(voice.velocity), false, voice.legato, &mut regions))
+            },
+            None => return,
+        };
+        for (layer, region) in voice.data.layers.iter_mut().zip(regions[..count].iter()) {
+            self.start_layer(layer, *region, note, voice.velocity, 0.0, false);
+        }
+    }
+
+    fn voice_off(&mut self, voice: &mut voice::Voice<SfzVoice>, info: SampleInfo) {
+        //The voice manager also calls this for idle voices when it is reset
+        if voice.state != VoiceState::Release {
+            return;
+        }
+        let instrument = match self.instrument.as_mut() {
+            Some(instrument) => instrument,
+            None => return,
+        };
+        for layer in voice.data.layers.iter_mut().filter(|l| l.active) {
+            if instrument.regions[layer.region].loop_mode() != SfzLoopMode::OneShot {
+                layer.amp_env.release();
+                layer.filter_env.release();
+                layer.player.release(&layer.sample_loop);
+            }
+        }
+
+        //Release triggers use free layers of the same voice
+        let note = instrument.map_note(voice.note);
+        let mut regions = [0; MAX_LAYERS];
+        let count = instrument.select_regions(note, midi_velocity(voice.velocity), true, false, &mut regions);
+        let held = (info.time - voice.press_time).max(0.0);
+        for region in regions[..count].iter() {
+            let decay = self.instrument.as_ref().map_or(0.0, |i| i.regions[*region].rt_decay * held);
+            if let Some(layer) = voice.data.layers.iter_mut().find(|l| !l.active) {
+                self.start_layer(layer, *region, note, voice.velocity, -decay, true);
+            }
+        }
+    }
+
+    fn voice_kill(&mut self, voice: &mut voice::Voice<SfzVoice>, _info: SampleInfo) {
+        for layer in voice.data.layers.iter_mut() {
+            layer.amp_env.kill();
+        }
+    }
+
+    fn check_attack_finished(&mut self, voice: &voice::Voice<SfzVoice>, _info: SampleInfo) -> bool {
+        return voice.data.layers.iter().filter(|l| l.active && !l.release).all(|l| l.amp_env.stage() != ADSRStage::Attack);
+    }
+
+    fn voice_level(&self, voice: &voice::Voice<SfzVoice>) -> f64 {
+        return voice.data.layers.iter().filter(|l| l.active).map(|l| l.amp_env.level()).fold(0.0, f64::max);
+    }
+
+    fn check_inactive(&mut self, voice: &voice::Voice<SfzVoice>, _info: SampleInfo) -> bool {
+        let waiting = voice.data.release_trigger && voice.state.is_pressed();
+        return !waiting && !voice.data.layers.iter().any(|l| l.active);
+    }
+
+}
+
+/// Plays SFZ instruments, they are loaded in a background thread so large sample sets don't block the audio thread
+pub struct SfzSampler {
+    info: DeviceInfo,
+    output: NamedAudioPort,
+    midiin: NamedMidiPort,
+    voice_mgr: VoiceManager<SfzVoice>,
+    pub proc: SfzProcessor,
+    loader: Option<Receiver<Result<SfzInstrument, &'static str>>>,
+    loading: bool,
+    retired: Option<SfzInstrument>,     //Replaced instrument, freed by free_retired outside of the audio thread
+    error: Option<&'static str>,
+}
+
+impl SfzSampler {
+
+    pub fn new() -> SfzSampler {
+        return SfzSampler {
+            info: DeviceInfo {
+                name: "SFZ Sampler",
+                type_identifier: "synthi_sam_sfz_sampler",
+            },
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2),
+            midiin: NamedMidiPort::new("MIDI In", "midi_in"),
+            voice_mgr: VoiceManager::new(32),
+            proc: SfzProcessor {
+                config: SfzSamplerConfig::default(),
+                instrument: None,
+                table: SincTable::new(),
+                sample_rate: 0,
+                time_step: 0.0,
+                pitch_bend: 0.0,
+            },
+            loader: None,
+            loading: false,
+            retired: None,
+            error: None,
+        };
+    }
+
+    /// Starts loading an SFZ file in a background thread, the current instrument keeps playing until it is finished
+    ///
+    /// This allocates, so it shouldn't be called from the audio thread.
+    pub fn load(&mut self, path: &str) {
+        self.retired = None;
+        let (sender, receiver) = mpsc::channel();
+        let path = path.to_string();
+        std::thread::spawn(move || {
+            let _ = sender.send(SfzInstrument::load(&path));
+        });
+        self.loader = Some(receiver);
+        self.loading = true;
+        self.error = None;
+    }
+
+    /// Replaces the instrument immediately, this shouldn't be called from the audio thread
+    pub fn set_instrument(&mut self, instrument: SfzInstrument) {
+        let info = SampleInfo {
+            sample_count: 0,
+            time: 0.0,
+            jitter: false,
+        };
+        self.voice_mgr.reset(&mut self.proc, info);
+        self.proc.instrument = Some(instrument);
+        self.retired = None;
+        self.loader = None;
+        self.loading = false;
+    }
+
+    #[inline(always)]
+    pub fn is_loading(&self) -> bool {
+        return self.loading;
+    }
+
+    /// Error of the last load, if it failed
+    #[inline(always)]
+    pub fn error(&self) -> Option<&'static str> {
+        return self.error;
+    }
+
+    /// Frees the instrument that was replaced by the last finished load and returns whether there was one
+    ///
+    /// The audio thread keeps the old instrument instead of deallocating it, so the host should call this from a
+    /// non-audio thread after is_loading turned false. Otherwise the old samples stay in memory until the next load.
+    pub fn free_retired(&mut self) -> bool {
+        return self.retired.take().is_some();
+    }
+
+    #[inline(always)]
+    pub fn instrument(&self) -> Option<&SfzInstrument> {
+        return self.proc.instrument.as_ref();
+    }
+
+    /// Swaps in an instrument that finished loading
+    fn receive_instrument(&mut self, info: SampleInfo) {
+        if !self.loading {
+            return;
+        }
+        let result = match self.loader.as_ref().map(|l| l.try_recv()) {
+            Some(Ok(result)) => result,
+            Some(Err(TryRecvError::Empty)) => return,
+            Some(Err(TryRecvError::Disconnected)) | None => Err("SFZ loader stopped unexpectedly"),
+        };
+        self.loading = false;
+        match result {
+            Ok(instrument) => {
+                //The voices refer to regions of the old instrument
+                self.voice_mgr.reset(&mut self.proc, info);
+                self.retired = self.proc.instrument.replace(instrument);
+            },
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+}
+
+impl Device for SfzSampler {
+
+    fn info(&self) -> &DeviceInfo {
+        return &self.info;
+    }
+
+    fn setup(&mut self, info: ProcessingInfo) {
+        self.output.port.reset();
+        self.midiin.port.reset();
+        self.proc.sample_rate = info.sample_rate;
+        self.proc.time_step = info.time_step;
+        self.proc.pitch_bend = 0.0;
+        let i = SampleInfo {
+            sample_count: 0,
+            time: 0.0,
+            jitter: false,
+        };
+        self.voice_mgr.reset(&mut self.proc, i);
+    }
+
+    fn process(&mut self, info: SampleInfo) {
+        self.receive_instrument(info);
+        while let Some(msg) = self.midiin.port.pop() {
+            match msg.message {
+                MidiMessageContent::NoteOn(note) => self.voice_mgr.press_note(&mut self.proc, note.note, note.velocity, info),
+                MidiMessageContent::NoteOff(note) => self.voice_mgr.release_note(&mut self.proc, note.note, info),
+                MidiMessageContent::PitchBend(bend) => self.proc.pitch_bend = bend.pitch_bend,
+                MidiMessageContent::ControlChange(cc) => {
+                    self.voice_mgr.control_change(&mut self.proc, cc.control, cc.value, info); //Pedals
+                },
+                _ => {},
+            }
+        }
+        let (left, right) = self.voice_mgr.process_voices(&mut self.proc, info);
+        self.output.port.take_input(&[left, right]);
+    }
+
+    fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {
+        return None;
+    }
+
+    fn audio_output_port(&mut self, index: usize) -> Option<&mut NamedAudioPort> {
+        return match index {
+            0 => Some(&mut self.output),
+            _ => None,
+        }
+    }
+
+    fn midi_input_port(&mut self, index: usize) -> Option<&mut NamedMidiPort> {
+        return match index {
+            0 => Some(&mut self.midiin),
+            _ => None,
+        }
+    }
+
+    fn midi_output_port(&mut self, _: usize) -> Option<&mut NamedMidiPort> {
+        return None;
+    }
+
+}
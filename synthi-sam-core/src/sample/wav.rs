@@ -0,0 +1,18 @@
+use hound::{WavReader, SampleFormat};
+
+use super::SampleBuffer;
+
+/// Decodes a WAV file with 8 to 32 bit integer or 32 bit float samples
+pub fn decode(data: &[u8]) -> Result<SampleBuffer, &'static str> {
+    let mut reader = WavReader::new(data).map_err(|_| "Invalid WAV file")?;
+    let spec = reader.spec();
+    let samples: Result<Vec<f32>, hound::Error> = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect(),
+        SampleFormat::Int => {
+            let scale = 1.0/f64::from(2.0).powi(spec.bits_per_sample as i32 - 1);
+            reader.samples::<i32>().map(|s| s.map(|s| (s as f64 * scale) as f32)).collect()
+        },
+    };
+    let samples = samples.map_err(|_| "Invalid WAV sample data")?;
+    return SampleBuffer::from_interleaved(&samples, spec.channels as usize, spec.sample_rate);
+}
@@ -1,6 +1,9 @@
 pub mod voice;
+pub mod random;
+pub mod tempo;
+pub mod modulation;
 
 #[inline(always)]
 pub fn get_default<T: Copy>(slice: &[T], index: usize, default: T) -> T {
-    return if slice.len() < index { slice[index] } else { default };
+    return if index < slice.len() { slice[index] } else { default };
 }
\ No newline at end of file
@@ -0,0 +1,398 @@
+use std::{fmt::Display, str::FromStr};
+
+pub const MOD_MATRIX_SLOTS: usize = 16;
+pub const MAX_MOD_ENVELOPES: usize = 4;
+pub const MAX_MOD_LFOS: usize = 4;
+pub const MAX_MOD_OSCILLATORS: usize = 4;
+
+const DESTINATION_COUNT: usize = 5 + MAX_MOD_OSCILLATORS * 2;
+
+/// A value that can modulate a destination
+///
+/// Envelopes, velocity, aftertouch and mod wheel are unipolar (0 to 1), LFOs, pitch bend and random are bipolar (-1 to 1).
+/// The note is the distance to middle C (60) in octaves.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModulationSource {
+    None,
+    Envelope(usize),
+    Lfo(usize),
+    Velocity,
+    Note,
+    Aftertouch,
+    ModWheel,
+    PitchBend,
+    Random,
+}
+
+impl Default for ModulationSource {
+    fn default() -> Self {
+        return ModulationSource::None;
+    }
+}
+
+impl Display for ModulationSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            ModulationSource::None => write!(f, "none"),
+            ModulationSource::Envelope(i) => write!(f, "env{}", i + 1),
+            ModulationSource::Lfo(i) => write!(f, "lfo{}", i + 1),
+            ModulationSource::Velocity => write!(f, "velocity"),
+            ModulationSource::Note => write!(f, "note"),
+            ModulationSource::Aftertouch => write!(f, "aftertouch"),
+            ModulationSource::ModWheel => write!(f, "modwheel"),
+            ModulationSource::PitchBend => write!(f, "pitchbend"),
+            ModulationSource::Random => write!(f, "random"),
+        }
+    }
+}
+
+/// Parses the 1-based index after a prefix, e.g. "lfo2" => 1
+fn parse_index(s: &str, prefix: &str, max: usize) -> Option<usize> {
+    return s.strip_prefix(prefix)
+        .and_then(|i| i.parse::<usize>().ok())
+        .filter(|i| *i >= 1 && *i <= max)
+        .map(|i| i - 1);
+}
+
+impl FromStr for ModulationSource {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "none" => Ok(ModulationSource::None),
+            "velocity" => Ok(ModulationSource::Velocity),
+            "note" => Ok(ModulationSource::Note),
+            "aftertouch" => Ok(ModulationSource::Aftertouch),
+            "modwheel" => Ok(ModulationSource::ModWheel),
+            "pitchbend" => Ok(ModulationSource::PitchBend),
+            "random" => Ok(ModulationSource::Random),
+            _ => {
+                if let Some(i) = parse_index(s, "env", MAX_MOD_ENVELOPES) {
+                    Ok(ModulationSource::Envelope(i))
+                }
+                else if let Some(i) = parse_index(s, "lfo", MAX_MOD_LFOS) {
+                    Ok(ModulationSource::Lfo(i))
+                }
+                else {
+                    Err("Invalid modulation source!")
+                }
+            }
+        }
+    }
+}
+
+/// A parameter that can be modulated
+///
+/// Pitch is in semitones, the pulse width is added to the base width, the cutoff is in octaves,
+/// amplitude is added to a gain of 1 and pan is added to the center position (-1 to 1).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModulationDestination {
+    None,
+    Pitch,                      //Pitch of all oscillators
+    OscillatorPitch(usize),
+    PulseWidth(usize),
+    FilterCutoff,
+    FilterResonance,
+    Amplitude,
+    Pan,
+}
+
+impl Default for ModulationDestination {
+    fn default() -> Self {
+        return ModulationDestination::None;
+    }
+}
+
+impl Display for ModulationDestination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            ModulationDestination::None => write!(f, "none"),
+            ModulationDestination::Pitch => write!(f, "pitch"),
+            ModulationDestination::OscillatorPitch(i) => write!(f, "osc{}_pitch", i + 1),
+            ModulationDestination::PulseWidth(i) => write!(f, "osc{}_pw", i + 1),
+            ModulationDestination::FilterCutoff => write!(f, "cutoff"),
+            ModulationDestination::FilterResonance => write!(f, "resonance"),
+            ModulationDestination::Amplitude => write!(f, "amp"),
+            ModulationDestination::Pan => write!(f, "pan"),
+        }
+    }
+}
+
+impl FromStr for ModulationDestination {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "none" => Ok(ModulationDestination::None),
+            "pitch" => Ok(ModulationDestination::Pitch),
+            "cutoff" => Ok(ModulationDestination::FilterCutoff),
+            "resonance" => Ok(ModulationDestination::FilterResonance),
+            "amp" => Ok(ModulationDestination::Amplitude),
+            "pan" => Ok(ModulationDestination::Pan),
+            _ => {
+                if let Some(i) = s.strip_suffix("_pitch").and_then(|s| parse_index(s, "osc", MAX_MOD_OSCILLATORS)) {
+                    Ok(ModulationDestination::OscillatorPitch(i))
+                }
+                else if let Some(i) = s.strip_suffix("_pw").and_then(|s| parse_index(s, "osc", MAX_MOD_OSCILLATORS)) {
+                    Ok(ModulationDestination::PulseWidth(i))
+                }
+                else {
+                    Err("Invalid modulation destination!")
+                }
+            }
+        }
+    }
+}
+
+impl ModulationDestination {
+
+    /// Index in the destination value array
+    fn index(&self) -> Option<usize> {
+        return match self {
+            ModulationDestination::None => None,
+            ModulationDestination::Pitch => Some(0),
+            ModulationDestination::FilterCutoff => Some(1),
+            ModulationDestination::FilterResonance => Some(2),
+            ModulationDestination::Amplitude => Some(3),
+            ModulationDestination::Pan => Some(4),
+            ModulationDestination::OscillatorPitch(i) => if *i < MAX_MOD_OSCILLATORS { Some(5 + i) } else { None },
+            ModulationDestination::PulseWidth(i) => if *i < MAX_MOD_OSCILLATORS { Some(5 + MAX_MOD_OSCILLATORS + i) } else { None },
+        }
+    }
+
+}
+
+/// Response curve applied to the source value, bipolar values are mirrored at 0
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModulationCurve {
+    Linear,
+    Exponential,
+    Logarithmic,
+    SCurve,
+}
+
+impl Default for ModulationCurve {
+    fn default() -> Self {
+        return ModulationCurve::Linear;
+    }
+}
+
+impl Display for ModulationCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ModulationCurve::Linear => "linear",
+            ModulationCurve::Exponential => "exponential",
+            ModulationCurve::Logarithmic => "logarithmic",
+            ModulationCurve::SCurve => "s_curve",
+        })
+    }
+}
+
+impl FromStr for ModulationCurve {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "linear" => Ok(ModulationCurve::Linear),
+            "exponential" => Ok(ModulationCurve::Exponential),
+            "logarithmic" => Ok(ModulationCurve::Logarithmic),
+            "s_curve" => Ok(ModulationCurve::SCurve),
+            _ => Err("Invalid modulation curve!"),
+        }
+    }
+}
+
+impl ModulationCurve {
+
+    #[inline]
+    pub fn apply(&self, value: f64) -> f64 {
+        let x = value.abs();
+        return value.signum() * match self {
+            ModulationCurve::Linear => x,
+            ModulationCurve::Exponential => x * x,
+            ModulationCurve::Logarithmic => x.sqrt(),
+            ModulationCurve::SCurve => {
+                let c = x.min(1.0);
+                c * c * (3.0 - 2.0 * c)
+            },
+        }
+    }
+
+}
+
+/// A single routing from a source to a destination
+///
+/// The value added to the destination is `depth * curve(source) * via`, where via is 1 if no via-source is set
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct ModulationSlot {
+    pub source: ModulationSource,
+    pub destination: ModulationDestination,
+    pub depth: f64,
+    pub curve: ModulationCurve,
+    pub via: ModulationSource,
+}
+
+impl ModulationSlot {
+
+    pub fn new(source: ModulationSource, destination: ModulationDestination, depth: f64) -> ModulationSlot {
+        return ModulationSlot {
+            source: source,
+            destination: destination,
+            depth: depth,
+            curve: ModulationCurve::Linear,
+            via: ModulationSource::None,
+        };
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        return self.source == ModulationSource::None || self.destination == ModulationDestination::None;
+    }
+
+}
+
+/// Format: `<source> <destination> <depth> <curve> <via>`, e.g. `lfo1 pitch 0.5 linear modwheel`
+impl Display for ModulationSlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {} {}", self.source, self.destination, self.depth, self.curve, self.via)
+    }
+}
+
+impl FromStr for ModulationSlot {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let source = parts.next().ok_or("Missing modulation source!")?.parse()?;
+        let destination = parts.next().ok_or("Missing modulation destination!")?.parse()?;
+        let depth = parts.next().ok_or("Missing modulation depth!")?.parse::<f64>().map_err(|_| "Invalid modulation depth!")?;
+        let curve = match parts.next() {
+            Some(c) => c.parse()?,
+            None => ModulationCurve::Linear,
+        };
+        let via = match parts.next() {
+            Some(v) => v.parse()?,
+            None => ModulationSource::None,
+        };
+        return Ok(ModulationSlot { source: source, destination: destination, depth: depth, curve: curve, via: via });
+    }
+}
+
+/// Current values of all modulation sources of a voice, filled by the voice processor
+#[derive(Copy, Clone, Default)]
+pub struct ModulationSources {
+    pub envelopes: [f64; MAX_MOD_ENVELOPES],
+    pub lfos: [f64; MAX_MOD_LFOS],
+    pub velocity: f64,
+    pub note: f64,
+    pub aftertouch: f64,
+    pub mod_wheel: f64,
+    pub pitch_bend: f64,
+    pub random: f64,
+}
+
+impl ModulationSources {
+
+    /// Sets the values that are fixed for the whole note
+    pub fn set_note(&mut self, note: u8, velocity: f64, random: f64) {
+        self.note = (note as f64 - 60.0)/12.0;
+        self.velocity = velocity;
+        self.random = random;
+    }
+
+    #[inline]
+    pub fn get(&self, source: ModulationSource) -> f64 {
+        return match source {
+            ModulationSource::None => 0.0,
+            ModulationSource::Envelope(i) => self.envelopes.get(i).copied().unwrap_or(0.0),
+            ModulationSource::Lfo(i) => self.lfos.get(i).copied().unwrap_or(0.0),
+            ModulationSource::Velocity => self.velocity,
+            ModulationSource::Note => self.note,
+            ModulationSource::Aftertouch => self.aftertouch,
+            ModulationSource::ModWheel => self.mod_wheel,
+            ModulationSource::PitchBend => self.pitch_bend,
+            ModulationSource::Random => self.random,
+        }
+    }
+
+}
+
+/// Summed modulation of all destinations
+#[derive(Copy, Clone, Default)]
+pub struct ModulationValues {
+    values: [f64; DESTINATION_COUNT],
+}
+
+impl ModulationValues {
+
+    #[inline]
+    pub fn get(&self, destination: ModulationDestination) -> f64 {
+        return match destination.index() {
+            Some(i) => self.values[i],
+            None => 0.0,
+        }
+    }
+
+    /// Pitch modulation of an oscillator in semitones including the global pitch modulation
+    #[inline]
+    pub fn oscillator_pitch(&self, osc: usize) -> f64 {
+        return self.get(ModulationDestination::Pitch) + self.get(ModulationDestination::OscillatorPitch(osc));
+    }
+
+}
+
+/// Routes modulation sources to destinations for every voice, can be stored as text with the preset
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct ModulationMatrix {
+    pub slots: [ModulationSlot; MOD_MATRIX_SLOTS],
+}
+
+impl ModulationMatrix {
+
+    /// Creates a matrix with the given routings, slots beyond the capacity are ignored
+    pub fn new(slots: &[ModulationSlot]) -> ModulationMatrix {
+        let mut matrix = ModulationMatrix::default();
+        for (slot, s) in matrix.slots.iter_mut().zip(slots.iter()) {
+            *slot = *s;
+        }
+        return matrix;
+    }
+
+    pub fn process(&self, sources: &ModulationSources) -> ModulationValues {
+        let mut values = ModulationValues::default();
+        for slot in self.slots.iter() {
+            if slot.is_empty() {
+                continue;
+            }
+            if let Some(i) = slot.destination.index() {
+                let via = if slot.via == ModulationSource::None { 1.0 } else { sources.get(slot.via) };
+                values.values[i] += slot.depth * slot.curve.apply(sources.get(slot.source)) * via;
+            }
+        }
+        return values;
+    }
+
+}
+
+/// One slot per line, empty slots are skipped
+impl Display for ModulationMatrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for slot in self.slots.iter().filter(|s| !s.is_empty()) {
+            writeln!(f, "{}", slot)?;
+        }
+        return Ok(());
+    }
+}
+
+impl FromStr for ModulationMatrix {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut matrix = ModulationMatrix::default();
+        let mut slots = matrix.slots.iter_mut();
+        for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            *slots.next().ok_or("Too many modulation slots!")? = line.parse()?;
+        }
+        return Ok(matrix);
+    }
+}
@@ -0,0 +1,41 @@
+/// Small and fast pseudo random generator (xorshift64*) that is safe to use in the audio thread
+pub struct Random {
+    state: u64,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        return Random::new(0x2545_F491_4F6C_DD1D);
+    }
+}
+
+impl Random {
+
+    /// Creates a new generator with the given seed, a seed of 0 is replaced by a fixed value
+    pub fn new(seed: u64) -> Random {
+        return Random {
+            state: if seed == 0 { 0x2545_F491_4F6C_DD1D } else { seed },
+        };
+    }
+
+    #[inline]
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+    }
+
+    /// Returns a random value in the range [0, 1)
+    #[inline]
+    pub fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+
+    /// Returns a random value in the range [-1, 1)
+    #[inline]
+    pub fn next_bipolar(&mut self) -> f64 {
+        return self.next_f64() * 2.0 - 1.0;
+    }
+
+}
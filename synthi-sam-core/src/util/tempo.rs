@@ -0,0 +1,195 @@
+use std::{fmt::Display, str::FromStr};
+
+pub const CLOCK_PPQN: f64 = 24.0;    //MIDI clock ticks per quarter note
+const CLOCK_TICKS: usize = 24;      //Tick intervals the clock tempo is averaged over
+const CLOCK_TIMEOUT: f64 = 1.0;     //Seconds between ticks after which the clock counts as restarted
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NoteModifier {
+    Straight,
+    Dotted,
+    Triplet,
+}
+
+impl Default for NoteModifier {
+    fn default() -> Self {
+        return NoteModifier::Straight;
+    }
+}
+
+/// A note value like 1/4, 3/16 or 1/8 triplet that can be converted to a duration at a tempo
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NoteDivision {
+    pub numerator: u32,
+    pub denominator: u32,
+    pub modifier: NoteModifier,
+}
+
+impl Default for NoteDivision {
+    fn default() -> Self {
+        return NoteDivision::new(1, 4);
+    }
+}
+
+impl Display for NoteDivision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}{}", self.numerator, self.denominator, match self.modifier {
+            NoteModifier::Straight => "",
+            NoteModifier::Dotted => ".",
+            NoteModifier::Triplet => "T",
+        })
+    }
+}
+
+impl FromStr for NoteDivision {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (s, modifier) = if let Some(s) = s.strip_suffix('.') {
+            (s, NoteModifier::Dotted)
+        }
+        else if let Some(s) = s.strip_suffix('T') {
+            (s, NoteModifier::Triplet)
+        }
+        else {
+            (s, NoteModifier::Straight)
+        };
+        let (numerator, denominator) = s.split_once('/').ok_or("Invalid note division!")?;
+        let numerator: u32 = numerator.parse().map_err(|_| "Invalid note division!")?;
+        let denominator: u32 = denominator.parse().map_err(|_| "Invalid note division!")?;
+        if numerator == 0 || denominator == 0 {
+            return Err("Note division can't be zero!");
+        }
+        return Ok(NoteDivision {
+            numerator: numerator,
+            denominator: denominator,
+            modifier: modifier,
+        });
+    }
+}
+
+impl NoteDivision {
+
+    /// Zero numerators and denominators are raised to one
+    pub fn new(numerator: u32, denominator: u32) -> NoteDivision {
+        return NoteDivision { numerator: numerator.max(1), denominator: denominator.max(1), modifier: NoteModifier::Straight };
+    }
+
+    pub fn dotted(numerator: u32, denominator: u32) -> NoteDivision {
+        return NoteDivision { numerator: numerator.max(1), denominator: denominator.max(1), modifier: NoteModifier::Dotted };
+    }
+
+    pub fn triplet(numerator: u32, denominator: u32) -> NoteDivision {
+        return NoteDivision { numerator: numerator.max(1), denominator: denominator.max(1), modifier: NoteModifier::Triplet };
+    }
+
+    /// Length in quarter notes, zero parts count as one so the length is never zero
+    pub fn beats(&self) -> f64 {
+        let beats = 4.0 * (self.numerator.max(1) as f64)/(self.denominator.max(1) as f64);
+        return match self.modifier {
+            NoteModifier::Straight => beats,
+            NoteModifier::Dotted => beats * 1.5,
+            NoteModifier::Triplet => beats * 2.0/3.0,
+        }
+    }
+
+    /// Length in seconds at the given tempo in beats per minute
+    #[inline]
+    pub fn duration(&self, bpm: f64) -> f64 {
+        return self.beats() * 60.0/bpm.max(1.0);
+    }
+
+    /// Frequency in Hz of one cycle per note at the given tempo
+    #[inline]
+    pub fn freq(&self, bpm: f64) -> f64 {
+        return 1.0/self.duration(bpm);
+    }
+
+}
+
+/// Measures the tempo of incoming MIDI clock
+///
+/// The tempo is averaged over the last beat of ticks, so the jitter of single ticks doesn't make it wobble.
+#[derive(Copy, Clone, Default)]
+pub struct ClockTempo {
+    ticks: [f64; CLOCK_TICKS + 1],
+    count: usize,
+    next: usize,
+}
+
+impl ClockTempo {
+
+    pub fn new() -> ClockTempo {
+        return ClockTempo::default();
+    }
+
+    /// Records a clock tick at the given time in seconds
+    pub fn tick(&mut self, time: f64) {
+        //Start over after a pause, the gap would slow down the average otherwise
+        if self.count > 0 {
+            let last = self.ticks[(self.next + CLOCK_TICKS) % (CLOCK_TICKS + 1)];
+            if time < last || time - last > CLOCK_TIMEOUT {
+                self.count = 0;
+            }
+        }
+        self.ticks[self.next] = time;
+        self.next = (self.next + 1) % (CLOCK_TICKS + 1);
+        self.count = (self.count + 1).min(CLOCK_TICKS + 1);
+    }
+
+    /// Measured tempo in beats per minute, none until at least two ticks were received
+    pub fn bpm(&self) -> Option<f64> {
+        if self.count < 2 {
+            return None;
+        }
+        let len = CLOCK_TICKS + 1;
+        let newest = self.ticks[(self.next + len - 1) % len];
+        let oldest = self.ticks[(self.next + len - self.count) % len];
+        let span = newest - oldest;
+        if span <= 0.0 {
+            return None;
+        }
+        return Some(60.0 * (self.count - 1) as f64/(CLOCK_PPQN * span));
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_tempo_averages_jitter() {
+        let mut tempo = ClockTempo::new();
+        assert_eq!(tempo.bpm(), None);
+        //120 bpm with every other tick arriving 2 ms late
+        let interval = 60.0/(120.0 * CLOCK_PPQN);
+        for i in 0..100 {
+            let jitter = if i % 2 == 1 { 0.002 } else { 0.0 };
+            tempo.tick(i as f64 * interval + jitter);
+            if i >= CLOCK_TICKS {
+                assert!((tempo.bpm().unwrap() - 120.0).abs() < 1.0, "{:?}", tempo.bpm());
+            }
+        }
+    }
+
+    #[test]
+    fn clock_tempo_restarts_after_pause() {
+        let mut tempo = ClockTempo::new();
+        for i in 0..48 {
+            tempo.tick(i as f64 * 60.0/(60.0 * CLOCK_PPQN));
+        }
+        assert!((tempo.bpm().unwrap() - 60.0).abs() < 1e-9);
+        //Resumes at 150 bpm after a pause
+        let start = 10.0;
+        for i in 0..2 {
+            tempo.tick(start + i as f64 * 60.0/(150.0 * CLOCK_PPQN));
+        }
+        assert!((tempo.bpm().unwrap() - 150.0).abs() < 1e-9);
+    }
+
+}
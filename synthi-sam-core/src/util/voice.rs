@@ -1,18 +1,50 @@
 use crate::core::audio::{SampleInfo, AudioSample};
 
-#[derive(PartialEq)]
+/// Lifecycle of a voice
+///
+/// * Idle -> Attack: a note is pressed
+/// * Attack -> Sustain: the processor reports that the attack is finished
+/// * Attack/Sustain -> Release: the key is released and no pedal holds the note
+/// * Attack/Sustain/Release -> Kill: the voice is stolen and fades out quickly
+/// * Attack/Sustain/Release -> Attack: the note is pressed again with the retrigger policy
+/// * any active state -> Idle: the processor reports silence
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum VoiceState {
-    Incative,
-    Pressed,
-    Released
+    Idle,
+    Attack,
+    Sustain,
+    Release,
+    Kill,
 }
 
 impl Default for VoiceState {
     fn default() -> Self {
-        return VoiceState::Incative
+        return VoiceState::Idle
     }
 }
 
+impl VoiceState {
+
+    /// The key is down (or held by a pedal)
+    #[inline(always)]
+    pub fn is_pressed(&self) -> bool {
+        return *self == VoiceState::Attack || *self == VoiceState::Sustain;
+    }
+
+    /// The voice plays a note that wasn't stolen
+    #[inline(always)]
+    pub fn is_sounding(&self) -> bool {
+        return self.is_pressed() || *self == VoiceState::Release;
+    }
+
+    /// The voice needs to be processed
+    #[inline(always)]
+    pub fn is_active(&self) -> bool {
+        return *self != VoiceState::Idle;
+    }
+
+}
+
 #[derive(Default)]
 pub struct Voice<T> where T: Default{
     pub state: VoiceState,
@@ -20,11 +52,133 @@ pub struct Voice<T> where T: Default{
     pub velocity: f64,
     pub press_time: f64,
     pub release_time: f64,
+    pub sustained: bool,    //The key is up, but the voice is held by the sustain or sostenuto pedal
+    pub sostenuto: bool,    //The key was down when the sostenuto pedal was pressed
+    pub legato: bool,       //The note was played while another key was still held
     pub data: T,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VoiceMode {
+    Poly,
+    Mono,       //Single voice, every note retriggers it
+    Legato,     //Single voice, overlapping notes only change the note without retriggering
+}
+
+impl Default for VoiceMode {
+    fn default() -> Self {
+        return VoiceMode::Poly;
+    }
+}
+
+/// Decides which of the held keys sounds in mono and legato mode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NotePriority {
+    Last,
+    Low,
+    High,
+}
+
+impl Default for NotePriority {
+    fn default() -> Self {
+        return NotePriority::Last;
+    }
+}
+
+const MAX_HELD_NOTES: usize = 128;
+
+/// Additional voices that can fade out after being stolen while the new note already plays
+const STEAL_VOICES: usize = 4;
+
+/// Decides which sounding voice is replaced when all voices are in use
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StealPolicy {
+    Oldest,     //Released voices first, then the longest pressed voice
+    Quietest,   //Lowest level reported by the processor
+    Lowest,     //Released voices first, then the lowest note
+    Highest,    //Released voices first, then the highest note
+    SameNote,   //A voice playing the same note, otherwise the oldest
+}
+
+impl Default for StealPolicy {
+    fn default() -> Self {
+        return StealPolicy::Oldest;
+    }
+}
+
+/// Decides what happens when a note is pressed that is still sounding
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RepressPolicy {
+    NewVoice,   //The old voice is released and rings out while a new voice starts
+    Retrigger,  //The old voice starts again from the attack
+}
+
+impl Default for RepressPolicy {
+    fn default() -> Self {
+        return RepressPolicy::NewVoice;
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct AllocationConfig {
+    pub steal_policy: StealPolicy,
+    pub repress_policy: RepressPolicy,
+    pub round_robin: bool,      //Cycle through the free voices instead of always using the first one
+    pub note_limit: usize,      //Maximum voices playing the same note at once, 0 for no limit
+}
+
+#[derive(Copy, Clone)]
+struct HeldNote {
+    note: u8,
+    velocity: f64,
+}
+
+pub const SUSTAIN_CONTROL: u8 = 64;
+pub const SOSTENUTO_CONTROL: u8 = 66;
+pub const SOFT_PEDAL_CONTROL: u8 = 67;
+
+/// Pedal values from 0 to 1 above which the sustain and sostenuto pedal count as pressed
+pub const PEDAL_THRESHOLD: f64 = 0.5;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Pedal {
+    Sustain,
+    Sostenuto,
+    Soft,
+}
+
+/// Current values of the pedals from 0 to 1, values in between are half-pedal positions
+#[derive(Copy, Clone, Default)]
+pub struct PedalState {
+    pub sustain: f64,
+    pub sostenuto: f64,
+    pub soft: f64,
+}
+
+impl PedalState {
+
+    #[inline(always)]
+    pub fn sustain_down(&self) -> bool {
+        return self.sustain >= PEDAL_THRESHOLD;
+    }
+
+    #[inline(always)]
+    pub fn sostenuto_down(&self) -> bool {
+        return self.sostenuto >= PEDAL_THRESHOLD;
+    }
+
+}
+
 pub struct VoiceManager<T> where T: Default{
     pub voices: Vec<Voice<T>>,
+    pub allocation: AllocationConfig,
+    polyphony: usize,
+    next_voice: usize,
+    pub pedals: PedalState,
+    pub soft_pedal_depth: f64,  //Velocity reduction when the soft pedal is fully pressed
+    pub priority: NotePriority,
+    mode: VoiceMode,
+    held_notes: Vec<HeldNote>,  //Keys that are down in mono and legato mode, in the order they were pressed
 }
 
 pub trait VoiceProcessor<T> where T: Default{
@@ -37,15 +191,30 @@ pub trait VoiceProcessor<T> where T: Default{
     }
 
     /**
-     * Process voices
+     * Process voices, returns the left and right sample
      */
-    fn process_voice(&mut self, voice: &mut Voice<T>, info: SampleInfo) -> f64;
+    fn process_voice(&mut self, voice: &mut Voice<T>, info: SampleInfo) -> (AudioSample, AudioSample);
 
     /**
      * Checks if a not can be set invalid now
+     * By default a voice ends as soon as it is released, processors with a release stage (e.g. an envelope) should keep it active until it is silent
      */
     fn check_inactive(&mut self, voice: &Voice<T>, _info: SampleInfo) -> bool {
-        return voice.state != VoiceState::Pressed;
+        return !voice.state.is_pressed();
+    }
+
+    /**
+     * Checks if a voice in the attack state can move on to the sustain state, by default after the first sample
+     */
+    fn check_attack_finished(&mut self, _voice: &Voice<T>, _info: SampleInfo) -> bool {
+        return true;
+    }
+
+    /**
+     * Called when a sounding voice is pressed again with the retrigger policy, by default it is treated like a new note
+     */
+    fn voice_retrigger(&mut self, voice: &mut Voice<T>, info: SampleInfo) {
+        self.voice_on(voice, info);
     }
 
     /**
@@ -55,15 +224,58 @@ pub trait VoiceProcessor<T> where T: Default{
 
     }
 
+    /**
+     * Called when polyphonic aftertouch for the note of a pressed voice was recieved
+     */
+    fn voice_aftertouch(&mut self, _voice: &mut Voice<T>, _aftertouch: f64, _info: SampleInfo) {
+
+    }
+
+    /**
+     * Called when a pedal value changed, useful for processors that react to half-pedal positions (e.g. damper resonance)
+     */
+    fn pedal_change(&mut self, _pedal: Pedal, _value: f64, _info: SampleInfo) {
+
+    }
+
+    /**
+     * Called in legato mode when the note of a pressed voice changed without retriggering it
+     */
+    fn voice_legato(&mut self, _voice: &mut Voice<T>, _info: SampleInfo) {
+
+    }
+
+    /**
+     * Called when a voice is stolen, it should fade out quickly to avoid clicks and then be set inactive by check_inactive
+     */
+    fn voice_kill(&mut self, _voice: &mut Voice<T>, _info: SampleInfo) {
+
+    }
+
+    /**
+     * Returns the current level of a voice, used to find the quietest voice when stealing
+     */
+    fn voice_level(&self, voice: &Voice<T>) -> f64 {
+        return if voice.state.is_pressed() { voice.velocity } else { voice.velocity * 0.5 };
+    }
+
 }
 
 impl<T> VoiceManager<T> where T: Default {
     //Init voice manager with a specific amount of polyphony
     pub fn new(size: usize) -> VoiceManager<T> {
         let mut mgr: VoiceManager<T> = VoiceManager {
-            voices: Vec::new()
+            voices: Vec::new(),
+            allocation: AllocationConfig::default(),
+            polyphony: size,
+            next_voice: 0,
+            pedals: PedalState::default(),
+            soft_pedal_depth: 0.3,
+            priority: NotePriority::Last,
+            mode: VoiceMode::Poly,
+            held_notes: Vec::with_capacity(MAX_HELD_NOTES),
         };
-        for _i in 0..size {
+        for _i in 0..(size + STEAL_VOICES) {
             let voice: Voice<T> = Voice::default();
             mgr.voices.push(voice);
         }
@@ -72,78 +284,483 @@ impl<T> VoiceManager<T> where T: Default {
 
     pub fn reset<E: VoiceProcessor<T>>(&mut self, proc: &mut E, info: SampleInfo) {
         for mut voice in self.voices.iter_mut() {
-            voice.state = VoiceState::Incative;
+            voice.state = VoiceState::Idle;
             voice.release_time = info.time;
+            voice.sustained = false;
+            voice.sostenuto = false;
             proc.voice_off(&mut voice, info);
         }
+        self.pedals = PedalState::default();
+        self.held_notes.clear();
     }
 
-    fn find_next_slot(&mut self) -> usize {
-        let mut released = false;
-        let mut longest_index: usize = 0;
-        let mut longest_time: f64 = f64::MAX;
+    #[inline(always)]
+    pub fn mode(&self) -> VoiceMode {
+        return self.mode;
+    }
 
-        //TODO refactor, bad code when more states are added
-        for i in 0..self.voices.len() {
-            let voice: &Voice<T> = &self.voices[i];
-            if voice.state == VoiceState::Incative {    // Note is free, use it
-                return i;
-            }
-            if voice.state == VoiceState::Released {
-                if released {  // Only counting released notes
-                    if voice.release_time < longest_time {
-                        longest_index = i;
-                        longest_time = voice.release_time;
-                    }
+    /// Changes the voice mode, all playing voices are stopped
+    pub fn set_mode<E: VoiceProcessor<T>>(&mut self, proc: &mut E, mode: VoiceMode, info: SampleInfo) {
+        if mode != self.mode {
+            self.mode = mode;
+            for voice in self.voices.iter_mut() {
+                if voice.state.is_pressed() {
+                    Self::release_voice(voice, proc, info);
                 }
-                else {  // First released note, only count released notes from now on
-                    longest_index = i;
-                    longest_time = voice.release_time;
-                    released = true;
+            }
+            self.held_notes.clear();
+        }
+    }
+
+    /// Attack/Sustain -> Release
+    fn release_voice<E: VoiceProcessor<T>>(voice: &mut Voice<T>, proc: &mut E, info: SampleInfo) {
+        voice.state = VoiceState::Release;
+        voice.release_time = info.time;
+        voice.sustained = false;
+        voice.sostenuto = false;
+        proc.voice_off(voice, info);
+    }
+
+    #[inline(always)]
+    pub fn polyphony(&self) -> usize {
+        return self.polyphony;
+    }
+
+    /// Attack/Sustain/Release -> Kill
+    fn kill_voice<E: VoiceProcessor<T>>(voice: &mut Voice<T>, proc: &mut E, info: SampleInfo) {
+        voice.state = VoiceState::Kill;
+        voice.release_time = info.time;
+        voice.sustained = false;
+        voice.sostenuto = false;
+        proc.voice_kill(voice, info);
+    }
+
+    /// Chooses the voice that is stolen according to the steal policy
+    fn find_steal_victim<E: VoiceProcessor<T>>(&self, proc: &E, note: u8) -> Option<usize> {
+        let sounding = self.voices.iter().enumerate().filter(|(_, v)| v.state.is_sounding());
+        let released = sounding.clone().filter(|(_, v)| v.state == VoiceState::Release);
+        let oldest_released = released.min_by(|(_, a), (_, b)| a.release_time.total_cmp(&b.release_time)).map(|(i, _)| i);
+        let oldest = || oldest_released.or(sounding.clone().min_by(|(_, a), (_, b)| a.press_time.total_cmp(&b.press_time)).map(|(i, _)| i));
+
+        return match self.allocation.steal_policy {
+            StealPolicy::Oldest => oldest(),
+            StealPolicy::Quietest => sounding.clone().min_by(|(_, a), (_, b)| proc.voice_level(a).total_cmp(&proc.voice_level(b))).map(|(i, _)| i),
+            StealPolicy::Lowest => oldest_released.or(sounding.clone().min_by_key(|(_, v)| v.note).map(|(i, _)| i)),
+            StealPolicy::Highest => oldest_released.or(sounding.clone().max_by_key(|(_, v)| v.note).map(|(i, _)| i)),
+            StealPolicy::SameNote => sounding.clone().filter(|(_, v)| v.note == note).min_by(|(_, a), (_, b)| a.press_time.total_cmp(&b.press_time)).map(|(i, _)| i).or_else(oldest),
+        }
+    }
+
+    /// Returns a free voice, if there is none the killed voice that fades out the longest is cut off
+    fn find_free_slot(&mut self) -> usize {
+        let len = self.voices.len();
+        let start = if self.allocation.round_robin { self.next_voice % len } else { 0 };
+        let index = (0..len).map(|i| (start + i) % len)
+            .find(|i| self.voices[*i].state == VoiceState::Idle)
+            .or_else(|| self.voices.iter().enumerate()
+                .filter(|(_, v)| v.state == VoiceState::Kill)
+                .min_by(|(_, a), (_, b)| a.release_time.total_cmp(&b.release_time))
+                .map(|(i, _)| i))
+            .unwrap_or(0);
+        self.next_voice = index + 1;
+        return index;
+    }
+
+    fn find_next_slot<E: VoiceProcessor<T>>(&mut self, proc: &mut E, note: u8, info: SampleInfo) -> usize {
+        //Per note limit
+        if self.allocation.note_limit > 0 {
+            let mut same_note = self.voices.iter().filter(|v| v.note == note && v.state.is_sounding()).count();
+            while same_note >= self.allocation.note_limit {
+                let victim = self.voices.iter().enumerate()
+                    .filter(|(_, v)| v.note == note && v.state.is_sounding())
+                    .min_by(|(_, a), (_, b)| a.press_time.total_cmp(&b.press_time))
+                    .map(|(i, _)| i);
+                match victim {
+                    Some(i) => Self::kill_voice(&mut self.voices[i], proc, info),
+                    None => break,
                 }
+                same_note -= 1;
+            }
+        }
+
+        //Steal
+        let sounding = self.voices.iter().filter(|v| v.state.is_sounding()).count();
+        if sounding >= self.polyphony {
+            if let Some(i) = self.find_steal_victim(proc, note) {
+                Self::kill_voice(&mut self.voices[i], proc, info);
+            }
+        }
+        return self.find_free_slot();
+    }
+
+    /// Returns the held key that should sound according to the note priority
+    fn priority_note(&self) -> Option<HeldNote> {
+        return match self.priority {
+            NotePriority::Last => self.held_notes.last().copied(),
+            NotePriority::Low => self.held_notes.iter().min_by_key(|n| n.note).copied(),
+            NotePriority::High => self.held_notes.iter().max_by_key(|n| n.note).copied(),
+        }
+    }
+
+    /// Idle/Release/Kill -> Attack, or Attack/Sustain -> Attack when retriggered
+    fn start_voice(voice: &mut Voice<T>, note: u8, velocity: f64, legato: bool, info: SampleInfo) {
+        voice.note = note;
+        voice.velocity = velocity;
+        voice.state = VoiceState::Attack;
+        voice.press_time = info.time;
+        voice.release_time = 0.0;
+        voice.sustained = false;
+        voice.sostenuto = false;
+        voice.legato = legato;
+    }
+
+    /// Lets the mono voice play the held note, either by retriggering or by a legato note change
+    fn play_mono_note<E: VoiceProcessor<T>>(&mut self, proc: &mut E, held: HeldNote, legato: bool, info: SampleInfo) {
+        let mode = self.mode;
+        let voice = &mut self.voices[0];
+        let playing = voice.state.is_pressed();
+        if mode == VoiceMode::Legato && legato && playing {
+            //Only the note changes, the state stays the same
+            voice.note = held.note;
+            voice.legato = true;
+            voice.sustained = false;
+            proc.voice_legato(voice, info);
+        }
+        else {
+            let sounding = voice.state.is_sounding();
+            Self::start_voice(voice, held.note, held.velocity, legato && playing, info);
+            if sounding {
+                proc.voice_retrigger(voice, info);
             }
-            else if voice.press_time < longest_time { // Check for pressed notes
-                longest_index = i;
-                longest_time = voice.press_time;
+            else {
+                proc.voice_on(voice, info);
             }
         }
+    }
+
+    fn press_mono_note<E: VoiceProcessor<T>>(&mut self, proc: &mut E, note: u8, velocity: f64, info: SampleInfo) {
+        let legato = !self.held_notes.is_empty();
+        self.held_notes.retain(|n| n.note != note);
+        if self.held_notes.len() >= MAX_HELD_NOTES {
+            self.held_notes.remove(0);
+        }
+        self.held_notes.push(HeldNote { note: note, velocity: velocity });
 
-		return longest_index;
+        if let Some(held) = self.priority_note() {
+            if held.note == note {
+                self.play_mono_note(proc, held, legato, info);
+            }
+        }
+    }
+
+    fn release_mono_note<E: VoiceProcessor<T>>(&mut self, proc: &mut E, note: u8, info: SampleInfo) {
+        self.held_notes.retain(|n| n.note != note);
+        if self.voices.is_empty() || self.voices[0].note != note || !self.voices[0].state.is_pressed() {
+            return;
+        }
+        //Return to the previous held key
+        match self.priority_note() {
+            Some(held) => self.play_mono_note(proc, held, true, info),
+            None => {
+                let hold = self.pedals.sustain_down();
+                let voice = &mut self.voices[0];
+                if hold || voice.sostenuto {
+                    voice.sustained = true;
+                }
+                else {
+                    Self::release_voice(voice, proc, info);
+                }
+            },
+        }
     }
 
     pub fn press_note<E: VoiceProcessor<T>>(&mut self, proc: &mut E, note: u8, velocity: f64, info: SampleInfo) {
-        let index = self.find_next_slot();
-        self.voices[index].note = note;
-        self.voices[index].velocity = velocity;
-        self.voices[index].state = VoiceState::Pressed;
-        self.voices[index].press_time = info.time;
-        self.voices[index].release_time = 0.0;
+        let velocity = velocity * (1.0 - self.pedals.soft * self.soft_pedal_depth);
+        if self.mode != VoiceMode::Poly {
+            self.press_mono_note(proc, note, velocity, info);
+            return;
+        }
+
+        let legato = self.voices.iter().any(|v| v.state.is_pressed() && !v.sustained && v.note != note);
+
+        //Retrigger the latest voice that still plays the note
+        if self.allocation.repress_policy == RepressPolicy::Retrigger {
+            let existing = self.voices.iter().enumerate()
+                .filter(|(_, v)| v.note == note && v.state.is_sounding())
+                .max_by(|(_, a), (_, b)| a.press_time.total_cmp(&b.press_time))
+                .map(|(i, _)| i);
+            if let Some(i) = existing {
+                Self::start_voice(&mut self.voices[i], note, velocity, legato, info);
+                proc.voice_retrigger(&mut self.voices[i], info);
+                return;
+            }
+        }
 
+        //The same key can't be held twice, so a voice still pressed (e.g. by a pedal) rings out
+        for voice in self.voices.iter_mut() {
+            if voice.note == note && voice.state.is_pressed() {
+                Self::release_voice(voice, proc, info);
+            }
+        }
+
+        let index = self.find_next_slot(proc, note, info);
+        Self::start_voice(&mut self.voices[index], note, velocity, legato, info);
         proc.voice_on(&mut self.voices[index], info);
     }
 
     pub fn release_note<E: VoiceProcessor<T>>(&mut self, proc: &mut E, note: u8, info: SampleInfo) {
-        for mut voice in self.voices.iter_mut() {
-            if voice.note == note {     //Check if note is equal
-                voice.state = VoiceState::Released;
-                voice.release_time = info.time;
-                proc.voice_off(&mut voice, info);
+        if self.mode != VoiceMode::Poly {
+            self.release_mono_note(proc, note, info);
+            return;
+        }
+
+        let hold = self.pedals.sustain_down();
+        for voice in self.voices.iter_mut() {
+            if voice.note == note && voice.state.is_pressed() && !voice.sustained {     //Check if note is equal
+                if hold || voice.sostenuto {
+                    voice.sustained = true;
+                }
+                else {
+                    Self::release_voice(voice, proc, info);
+                }
+            }
+        }
+    }
+
+    /// Updates the sustain pedal (CC64), lifting it releases all voices that are only held by it
+    pub fn sustain_pedal<E: VoiceProcessor<T>>(&mut self, proc: &mut E, value: f64, info: SampleInfo) {
+        self.pedals.sustain = value;
+        if !self.pedals.sustain_down() {
+            for voice in self.voices.iter_mut() {
+                if voice.state.is_pressed() && voice.sustained && !voice.sostenuto {
+                    Self::release_voice(voice, proc, info);
+                }
+            }
+        }
+        proc.pedal_change(Pedal::Sustain, value, info);
+    }
+
+    /// Updates the sostenuto pedal (CC66), pressing it latches only the keys that are currently held down
+    pub fn sostenuto_pedal<E: VoiceProcessor<T>>(&mut self, proc: &mut E, value: f64, info: SampleInfo) {
+        let was_down = self.pedals.sostenuto_down();
+        self.pedals.sostenuto = value;
+        let down = self.pedals.sostenuto_down();
+        if down && !was_down {
+            for voice in self.voices.iter_mut() {
+                if voice.state.is_pressed() && !voice.sustained {
+                    voice.sostenuto = true;
+                }
+            }
+        }
+        else if !down && was_down {
+            let hold = self.pedals.sustain_down();
+            for voice in self.voices.iter_mut() {
+                if voice.sostenuto {
+                    voice.sostenuto = false;
+                    //Keys that were already let go are only kept if the sustain pedal is still down
+                    if voice.state.is_pressed() && voice.sustained && !hold {
+                        Self::release_voice(voice, proc, info);
+                    }
+                }
+            }
+        }
+        proc.pedal_change(Pedal::Sostenuto, value, info);
+    }
+
+    /// Updates the soft pedal (CC67), it scales the velocity of new notes by up to the soft pedal depth
+    pub fn soft_pedal<E: VoiceProcessor<T>>(&mut self, proc: &mut E, value: f64, info: SampleInfo) {
+        self.pedals.soft = value;
+        proc.pedal_change(Pedal::Soft, value, info);
+    }
+
+    /// Handles the pedal control changes, returns wether the control was a pedal
+    pub fn control_change<E: VoiceProcessor<T>>(&mut self, proc: &mut E, control: u8, value: f64, info: SampleInfo) -> bool {
+        match control {
+            SUSTAIN_CONTROL => self.sustain_pedal(proc, value, info),
+            SOSTENUTO_CONTROL => self.sostenuto_pedal(proc, value, info),
+            SOFT_PEDAL_CONTROL => self.soft_pedal(proc, value, info),
+            _ => return false,
+        }
+        return true;
+    }
+
+    /// Sends polyphonic aftertouch to all pressed voices playing the note
+    pub fn aftertouch<E: VoiceProcessor<T>>(&mut self, proc: &mut E, note: u8, aftertouch: f64, info: SampleInfo) {
+        for voice in self.voices.iter_mut() {
+            if voice.note == note && voice.state.is_pressed() {
+                proc.voice_aftertouch(voice, aftertouch, info);
             }
         }
     }
   
-    pub fn process_voices<E: VoiceProcessor<T>>(&mut self, proc: &mut E, info: SampleInfo) -> AudioSample {
-        let mut sample = 0.0;
+    pub fn process_voices<E: VoiceProcessor<T>>(&mut self, proc: &mut E, info: SampleInfo) -> (AudioSample, AudioSample) {
+        let mut left = 0.0;
+        let mut right = 0.0;
         for mut voice in self.voices.iter_mut() {
-            if voice.state != VoiceState::Incative {
+            if voice.state.is_active() {
                 //Process sound
-                sample += proc.process_voice(&mut voice, info);
-                //Invalidate note
+                let (l, r) = proc.process_voice(&mut voice, info);
+                left += l;
+                right += r;
+                //Update state
+                if voice.state == VoiceState::Attack && proc.check_attack_finished(&voice, info) {
+                    voice.state = VoiceState::Sustain;
+                }
                 if proc.check_inactive(&voice, info) {
-                    voice.state = VoiceState::Incative;
+                    voice.state = VoiceState::Idle;
                 }
             }
         }
-        return sample;
+        return (left, right);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct StubVoice {
+        on: usize,
+        off: usize,
+        kill: usize,
+        retrigger: usize,
+    }
+
+    /// Keeps released and killed voices active until silent is set, like a processor with a release stage
+    #[derive(Default)]
+    struct StubProcessor {
+        silent: bool,
+    }
+
+    impl VoiceProcessor<StubVoice> for StubProcessor {
+
+        fn voice_on(&mut self, voice: &mut Voice<StubVoice>, _info: SampleInfo) {
+            voice.data.on += 1;
+        }
+
+        fn process_voice(&mut self, _voice: &mut Voice<StubVoice>, _info: SampleInfo) -> (AudioSample, AudioSample) {
+            return (0.0, 0.0);
+        }
+
+        fn check_inactive(&mut self, voice: &Voice<StubVoice>, _info: SampleInfo) -> bool {
+            return !voice.state.is_pressed() && self.silent;
+        }
+
+        fn voice_retrigger(&mut self, voice: &mut Voice<StubVoice>, _info: SampleInfo) {
+            voice.data.retrigger += 1;
+        }
+
+        fn voice_off(&mut self, voice: &mut Voice<StubVoice>, _info: SampleInfo) {
+            voice.data.off += 1;
+        }
+
+        fn voice_kill(&mut self, voice: &mut Voice<StubVoice>, _info: SampleInfo) {
+            voice.data.kill += 1;
+        }
+
+    }
+
+    fn info(time: f64) -> SampleInfo {
+        return SampleInfo {
+            sample_count: 0,
+            time: time,
+            jitter: false,
+        };
+    }
+
+    fn states(mgr: &VoiceManager<StubVoice>, note: u8) -> Vec<VoiceState> {
+        return mgr.voices.iter().filter(|v| v.note == note && v.state.is_active()).map(|v| v.state).collect();
+    }
+
+    #[test]
+    fn lifecycle() {
+        let mut mgr = VoiceManager::new(4);
+        let mut proc = StubProcessor::default();
+        mgr.press_note(&mut proc, 60, 1.0, info(0.0));
+        assert_eq!(mgr.voices[0].state, VoiceState::Attack);
+        assert_eq!(mgr.voices[0].data.on, 1);
+
+        mgr.process_voices(&mut proc, info(0.1));
+        assert_eq!(mgr.voices[0].state, VoiceState::Sustain);
+
+        mgr.release_note(&mut proc, 60, info(0.2));
+        assert_eq!(mgr.voices[0].state, VoiceState::Release);
+        assert_eq!(mgr.voices[0].data.off, 1);
+
+        //The release stage keeps the voice active until the processor reports silence
+        mgr.process_voices(&mut proc, info(0.3));
+        assert_eq!(mgr.voices[0].state, VoiceState::Release);
+        proc.silent = true;
+        mgr.process_voices(&mut proc, info(0.4));
+        assert_eq!(mgr.voices[0].state, VoiceState::Idle);
+    }
+
+    #[test]
+    fn kill_on_steal() {
+        let mut mgr = VoiceManager::new(2);
+        let mut proc = StubProcessor::default();
+        mgr.press_note(&mut proc, 60, 1.0, info(0.0));
+        mgr.press_note(&mut proc, 61, 1.0, info(0.1));
+        mgr.press_note(&mut proc, 62, 1.0, info(0.2));
+
+        //The oldest voice fades out in one of the extra voices while the new note starts
+        assert_eq!(states(&mgr, 60), vec![VoiceState::Kill]);
+        assert_eq!(mgr.voices.iter().find(|v| v.note == 60).unwrap().data.kill, 1);
+        assert_eq!(states(&mgr, 61), vec![VoiceState::Attack]);
+        assert_eq!(states(&mgr, 62), vec![VoiceState::Attack]);
+
+        proc.silent = true;
+        mgr.process_voices(&mut proc, info(0.3));
+        assert!(states(&mgr, 60).is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn repress_new_voice() {
+        let mut mgr = VoiceManager::new(4);
+        let mut proc = StubProcessor::default();
+        mgr.allocation.repress_policy = RepressPolicy::NewVoice;
+        mgr.press_note(&mut proc, 60, 1.0, info(0.0));
+        mgr.release_note(&mut proc, 60, info(0.1));
+        mgr.press_note(&mut proc, 60, 1.0, info(0.2));
+
+        //The released voice rings out next to the new one
+        assert_eq!(states(&mgr, 60), vec![VoiceState::Release, VoiceState::Attack]);
+        assert_eq!(mgr.voices[0].data.on, 1);
+        assert_eq!(mgr.voices[1].data.on, 1);
+        assert!(mgr.voices.iter().all(|v| v.data.retrigger == 0));
+    }
+
+    #[test]
+    fn repress_retrigger() {
+        let mut mgr = VoiceManager::new(4);
+        let mut proc = StubProcessor::default();
+        mgr.allocation.repress_policy = RepressPolicy::Retrigger;
+        mgr.press_note(&mut proc, 60, 1.0, info(0.0));
+        mgr.release_note(&mut proc, 60, info(0.1));
+        mgr.press_note(&mut proc, 60, 0.5, info(0.2));
+
+        //The same voice starts again from the attack
+        assert_eq!(states(&mgr, 60), vec![VoiceState::Attack]);
+        assert_eq!(mgr.voices[0].data.on, 1);
+        assert_eq!(mgr.voices[0].data.retrigger, 1);
+        assert_eq!(mgr.voices[0].velocity, 0.5);
+        assert_eq!(mgr.voices[0].press_time, 0.2);
+    }
+
+    #[test]
+    fn release_ignores_released_and_idle_voices() {
+        let mut mgr = VoiceManager::new(4);
+        let mut proc = StubProcessor::default();
+        mgr.press_note(&mut proc, 60, 1.0, info(0.0));
+        mgr.release_note(&mut proc, 60, info(0.1));
+        mgr.release_note(&mut proc, 60, info(0.2));
+        mgr.release_note(&mut proc, 61, info(0.3));
+
+        assert_eq!(mgr.voices[0].state, VoiceState::Release);
+        assert_eq!(mgr.voices[0].data.off, 1);
+        assert_eq!(mgr.voices[0].release_time, 0.1);
+        assert!(mgr.voices[1..].iter().all(|v| v.state == VoiceState::Idle && v.data.off == 0));
+    }
+
+}
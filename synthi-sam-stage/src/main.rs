@@ -1,22 +1,33 @@
 use io::AudioMidiProcessor;
 use synth::DemoDevice;
-use synthi_sam_core::core::device::Device;
+use synthi_sam_core::{core::device::Device, dsp::tuning::Tuning, effects::limiter::Limiter};
 
 mod synth;
 mod io;
 
 struct DemoProcessor {
     synth: DemoDevice,
+    limiter: Limiter,
 }
 
 impl AudioMidiProcessor for DemoProcessor {
     fn setup(&mut self, info: synthi_sam_core::core::audio::ProcessingInfo) {
         self.synth.setup(info);
+        self.limiter.setup(info);
     }
 
     fn process(&mut self, info: synthi_sam_core::core::audio::SampleInfo) -> (f64, f64) {
         self.synth.process(info);
-        return match self.synth.audio_output_port(0) {
+        //Master bus
+        let (l, r) = match self.synth.audio_output_port(0) {
+            Some(port) => (port.port.channels()[0], port.port.channels()[1]),
+            _ => (0.0, 0.0)
+        };
+        if let Some(port) = self.limiter.audio_input_port(0) {
+            port.port.take_input(&[l, r]);
+        }
+        self.limiter.process(info);
+        return match self.limiter.audio_output_port(0) {
             Some(port) => (port.port.channels()[0], port.port.channels()[1]),
             _ => (0.0, 0.0)
         }
@@ -31,7 +42,16 @@ impl AudioMidiProcessor for DemoProcessor {
 }
 
 fn main() {
+    //Optional tuning: synthi-sam-stage [scale.scl] [mapping.kbm]
+    let mut device = DemoDevice::new();
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(scale) = args.get(1) {
+        match Tuning::load(scale, args.get(2)) {
+            Ok(tuning) => device.set_tuning(tuning),
+            Err(err) => eprintln!("Couldn't load tuning: {}", err),
+        }
+    }
     //Audio
-    let synth: Box<DemoProcessor> = Box::new(DemoProcessor { synth: DemoDevice::new() });
+    let synth: Box<DemoProcessor> = Box::new(DemoProcessor { synth: device, limiter: Limiter::new() });
     let mut _handler = io::AudioMidiHandler::new(synth);
 }
@@ -1,10 +1,20 @@
-use synthi_sam_core::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessageContent}}, dsp::{oscillator::{WaveForm, Oscillator, OscilatorConfig}, note_to_freq_transpose, note_to_freq}, util::voice::{VoiceManager, self}};
+use synthi_sam_core::{core::{device::{Device, DeviceInfo, NamedAudioPort, NamedMidiPort}, audio::{ProcessingInfo, SampleInfo}, midi::{MidiMessageContent}}, dsp::{oscillator::{WaveForm, OscilatorConfig}, lfo::{Lfo, LfoConfig, LfoShape, LfoRate, LfoMode}, filter::{LadderFilter, LadderFilterConfig}, unison::{UnisonOscillator, UnisonConfig, DetuneCurve}, envelope::{ADSREnvelope, ADSREnvelopeConfig, ADSRStage, EnvelopeCurve, TriggerMode}, portamento::{Portamento, PortamentoConfig, GlideMode}, tuning::Tuning, note_to_freq_transpose, pan_equal_power}, util::{voice::{VoiceManager, AllocationConfig, StealPolicy, RepressPolicy, self}, random::Random, modulation::{ModulationMatrix, ModulationSlot, ModulationSources, ModulationSource, ModulationDestination}, tempo::ClockTempo}};
 
 
+const MOD_WHEEL_CONTROL: u8 = 1;
+
 #[derive(Default)]
 pub struct SynthVoice {
-    pub osc1: Oscillator,
-    pub osc2: Oscillator,
+    pub osc1: UnisonOscillator,
+    pub osc2: UnisonOscillator,
+    pub amp_env: ADSREnvelope,
+    pub mod_env: ADSREnvelope,
+    pub lfo: Lfo,
+    pub filter_l: LadderFilter,
+    pub filter_r: LadderFilter,
+    pub modulation: ModulationSources,
+    pub aftertouch: f64,
+    pub glide: Portamento,
     pub freq: f64,
 }
 
@@ -13,27 +23,126 @@ pub struct SynthPreset {
     osc1_waveform: WaveForm,
     osc2_waveform: WaveForm,
     detune: f64,
+    unison: UnisonConfig,
+    amp_envelope: ADSREnvelopeConfig,
+    mod_envelope: ADSREnvelopeConfig,
+    lfo: LfoConfig,
+    filter: LadderFilterConfig,
+    mod_matrix: ModulationMatrix,
+    pitch_bend_range: f64,  //Semitones
+    mod_wheel_vibrato: f64, //Depth of LFO 1 on the pitch in semitones at full mod wheel
+    portamento: PortamentoConfig,
 }
 
 struct SynthProcessor {
     pub preset: SynthPreset,
     sample_rate: u32,
     time_step: f64,
+    bpm: f64,
+    global_lfo: Lfo,
+    global_lfo_value: f64,
+    random: Random,
+    last_note: Option<f64>,
+    mod_wheel: f64,
+    pitch_bend: f64,
+    aftertouch: f64,
+    tuning: Tuning,
 }
 
 impl voice::VoiceProcessor<SynthVoice> for SynthProcessor {
 
-    fn process_voice(&mut self, voice: &mut voice::Voice<SynthVoice>, _info: SampleInfo) -> f64 {
-        let osc1 = OscilatorConfig {waveform: self.preset.osc1_waveform, freq: voice.data.freq};
-        let osc2 = OscilatorConfig {waveform: self.preset.osc2_waveform, freq: voice.data.freq * self.preset.detune};
+    fn process_voice(&mut self, voice: &mut voice::Voice<SynthVoice>, _info: SampleInfo) -> (f64, f64) {
+        voice.data.freq = self.tuning.freq(voice.data.glide.process(self.time_step));
 
-        let sample = (voice.data.osc1.process(osc1, self.time_step) + voice.data.osc2.process(osc2, self.time_step)) * 0.5;
+        //Modulation
+        let amp = voice.data.amp_env.process(&self.preset.amp_envelope, self.time_step);
+        let sources = &mut voice.data.modulation;
+        sources.envelopes[0] = amp;
+        sources.envelopes[1] = voice.data.mod_env.process(&self.preset.mod_envelope, self.time_step);
+        sources.lfos[0] = if self.preset.lfo.mode == LfoMode::Global {
+            self.global_lfo_value
+        } else {
+            voice.data.lfo.process(&self.preset.lfo, self.bpm, self.time_step)
+        };
+        sources.mod_wheel = self.mod_wheel;
+        sources.pitch_bend = self.pitch_bend;
+        sources.aftertouch = voice.data.aftertouch.max(self.aftertouch);   //Polyphonic and channel pressure
+        let modulation = self.preset.mod_matrix.process(sources);
+        let pitch = self.pitch_bend * self.preset.pitch_bend_range + sources.lfos[0] * self.mod_wheel * self.preset.mod_wheel_vibrato;
 
-        return sample; //Mix both oscillators equally
+        let filter = LadderFilterConfig {
+            cutoff: self.preset.filter.cutoff * f64::from(2.0).powf(modulation.get(ModulationDestination::FilterCutoff)),
+            resonance: self.preset.filter.resonance + modulation.get(ModulationDestination::FilterResonance),
+            ..self.preset.filter
+        };
+
+        //Oscillators
+        let osc1 = OscilatorConfig {
+            waveform: self.preset.osc1_waveform,
+            freq: voice.data.freq * note_to_freq_transpose(pitch + modulation.oscillator_pitch(0)),
+            pulse_width: 0.5 + modulation.get(ModulationDestination::PulseWidth(0)),
+        };
+        let osc2 = OscilatorConfig {
+            waveform: self.preset.osc2_waveform,
+            freq: voice.data.freq * self.preset.detune * note_to_freq_transpose(pitch + modulation.oscillator_pitch(1)),
+            pulse_width: 0.5 + modulation.get(ModulationDestination::PulseWidth(1)),
+        };
+        let (l1, r1) = voice.data.osc1.process(osc1, &self.preset.unison, self.time_step);
+        let (l2, r2) = voice.data.osc2.process(osc2, &self.preset.unison, self.time_step);
+
+        //Filter and amp
+        let left = voice.data.filter_l.process(&filter, (l1 + l2) * 0.5, self.time_step); //Mix both oscillators equally
+        let right = voice.data.filter_r.process(&filter, (r1 + r2) * 0.5, self.time_step);
+        let gain = amp * (1.0 + modulation.get(ModulationDestination::Amplitude)).max(0.0);
+        let (pan_l, pan_r) = pan_equal_power(modulation.get(ModulationDestination::Pan));
+        return (left * gain * pan_l, right * gain * pan_r);
     }
 
     fn voice_on(&mut self, voice: &mut voice::Voice<SynthVoice>, _info: SampleInfo) {
-        voice.data.freq = note_to_freq(voice.note as f64);
+        //Glide from the current pitch when the voice is reused (e.g. in mono mode)
+        let from = if voice.data.amp_env.is_active() { Some(voice.data.glide.current()) } else { self.last_note };
+        voice.data.glide.start(&self.preset.portamento, from, voice.note as f64, voice.legato);
+        voice.data.freq = self.tuning.freq(voice.data.glide.current());
+        self.last_note = Some(voice.note as f64);
+        voice.data.osc1.reset(&self.preset.unison, &mut self.random);
+        voice.data.osc2.reset(&self.preset.unison, &mut self.random);
+        voice.data.amp_env.press(&self.preset.amp_envelope, voice.velocity);
+        voice.data.mod_env.press(&self.preset.mod_envelope, voice.velocity);
+        voice.data.lfo.seed(self.random.next_u64());
+        voice.data.lfo.trigger(&self.preset.lfo);
+        voice.data.modulation.set_note(voice.note, voice.velocity, self.random.next_bipolar());
+        voice.data.aftertouch = 0.0;
+    }
+
+    fn voice_off(&mut self, voice: &mut voice::Voice<SynthVoice>, _info: SampleInfo) {
+        voice.data.amp_env.release();
+        voice.data.mod_env.release();
+    }
+
+    fn voice_legato(&mut self, voice: &mut voice::Voice<SynthVoice>, _info: SampleInfo) {
+        voice.data.glide.start(&self.preset.portamento, Some(voice.data.glide.current()), voice.note as f64, true);
+        voice.data.modulation.set_note(voice.note, voice.velocity, voice.data.modulation.random);
+        self.last_note = Some(voice.note as f64);
+    }
+
+    fn check_attack_finished(&mut self, voice: &voice::Voice<SynthVoice>, _info: SampleInfo) -> bool {
+        return voice.data.amp_env.stage() != ADSRStage::Attack;
+    }
+
+    fn voice_kill(&mut self, voice: &mut voice::Voice<SynthVoice>, _info: SampleInfo) {
+        voice.data.amp_env.kill();
+    }
+
+    fn voice_level(&self, voice: &voice::Voice<SynthVoice>) -> f64 {
+        return voice.data.amp_env.level();
+    }
+
+    fn voice_aftertouch(&mut self, voice: &mut voice::Voice<SynthVoice>, aftertouch: f64, _info: SampleInfo) {
+        voice.data.aftertouch = aftertouch;
+    }
+
+    fn check_inactive(&mut self, voice: &voice::Voice<SynthVoice>, _info: SampleInfo) -> bool {
+        return !voice.data.amp_env.is_active();
     }
 
 }
@@ -44,18 +153,19 @@ pub struct DemoDevice {
     midiin: NamedMidiPort,
 
     voice_mgr: VoiceManager<SynthVoice>,
-    proc: SynthProcessor
+    proc: SynthProcessor,
+    clock_tempo: ClockTempo,
 }
 
 impl DemoDevice {
 
     pub fn new() -> DemoDevice {
-        return DemoDevice {
+        let mut device = DemoDevice {
             info: DeviceInfo {
                 name: "Demo Synth",
                 type_identifier: "synthi_sam_demo_synth"
             }, 
-            output: NamedAudioPort::new("Mono Out", "mono_out", 1), 
+            output: NamedAudioPort::new("Stereo Out", "stereo_out", 2), 
             midiin: NamedMidiPort::new("MIDI In", "midi_in"),
 
             voice_mgr: VoiceManager::new(30),
@@ -64,11 +174,95 @@ impl DemoDevice {
                     osc1_waveform: WaveForm::Saw,
                     osc2_waveform: WaveForm::Saw,
                     detune: note_to_freq_transpose(0.1),
+                    unison: UnisonConfig {
+                        voices: 5,
+                        detune: 0.2,
+                        detune_curve: DetuneCurve::Linear,
+                        spread: 0.8,
+                        blend: 0.6,
+                        random_phase: true,
+                    },
+                    amp_envelope: ADSREnvelopeConfig {
+                        attack: 0.01,
+                        decay: 0.3,
+                        sustain: 0.7,
+                        release: 0.4,
+                        attack_curve: EnvelopeCurve::Linear,
+                        decay_curve: EnvelopeCurve::Exponential,
+                        release_curve: EnvelopeCurve::Exponential,
+                        velocity_amount: 0.5,
+                        trigger_mode: TriggerMode::Retrigger,
+                    },
+                    mod_envelope: ADSREnvelopeConfig {
+                        attack: 0.005,
+                        decay: 0.6,
+                        sustain: 0.2,
+                        release: 0.4,
+                        attack_curve: EnvelopeCurve::Linear,
+                        decay_curve: EnvelopeCurve::Exponential,
+                        release_curve: EnvelopeCurve::Exponential,
+                        velocity_amount: 0.8,
+                        trigger_mode: TriggerMode::Retrigger,
+                    },
+                    lfo: LfoConfig {
+                        shape: LfoShape::Sine,
+                        rate: LfoRate::Free(5.0),
+                        mode: LfoMode::Retrigger,
+                        phase: 0.0,
+                        delay: 0.3,
+                        fade_in: 0.5,
+                    },
+                    filter: LadderFilterConfig {
+                        cutoff: 1500.0,
+                        resonance: 0.2,
+                        drive: 1.0,
+                    },
+                    mod_matrix: ModulationMatrix::new(&[
+                        ModulationSlot::new(ModulationSource::Aftertouch, ModulationDestination::FilterCutoff, 1.0),
+                        ModulationSlot::new(ModulationSource::Envelope(1), ModulationDestination::FilterCutoff, 2.0),
+                        ModulationSlot::new(ModulationSource::Note, ModulationDestination::FilterCutoff, 0.5),
+                        ModulationSlot::new(ModulationSource::Random, ModulationDestination::Pan, 0.2),
+                        ModulationSlot::new(ModulationSource::Lfo(0), ModulationDestination::FilterCutoff, 0.3),
+                        //PWM for pulse waveforms: ModulationSlot::new(ModulationSource::Lfo(0), ModulationDestination::PulseWidth(0), 0.3)
+                    ]),
+                    pitch_bend_range: 2.0,
+                    mod_wheel_vibrato: 0.5,
+                    portamento: PortamentoConfig {
+                        time: 0.0,
+                        mode: GlideMode::ConstantTime,
+                        legato_only: true,
+                    },
                 },
                 sample_rate: 0,
                 time_step: 0.0,
-            }
-        }
+                bpm: 120.0,
+                global_lfo: Lfo::default(),
+                global_lfo_value: 0.0,
+                mod_wheel: 0.0,
+                pitch_bend: 0.0,
+                aftertouch: 0.0,
+                random: Random::default(),
+                last_note: None,
+                tuning: Tuning::default(),
+            },
+            clock_tempo: ClockTempo::new(),
+        };
+        device.voice_mgr.allocation = AllocationConfig {
+            steal_policy: StealPolicy::Quietest,
+            repress_policy: RepressPolicy::NewVoice,
+            round_robin: false,
+            note_limit: 2,
+        };
+        return device;
+    }
+
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.proc.tuning = tuning;
+    }
+
+    /// Sets the tempo for synced LFO rates, incoming MIDI clock overrides it
+    pub fn set_bpm(&mut self, bpm: f64) {
+        self.proc.bpm = bpm;
     }
 
 }
@@ -87,6 +281,12 @@ impl Device for DemoDevice {
         //Processor
         self.proc.sample_rate = info.sample_rate;
         self.proc.time_step = info.time_step;
+        self.proc.mod_wheel = 0.0;
+        self.proc.pitch_bend = 0.0;
+        self.proc.aftertouch = 0.0;
+        self.proc.global_lfo = Lfo::default();
+        self.proc.global_lfo_value = 0.0;
+        self.clock_tempo.reset();
         let i = SampleInfo {
             sample_count: 0,
             time: 0.0,
@@ -102,13 +302,37 @@ impl Device for DemoDevice {
             match msg.message {
                 MidiMessageContent::NoteOn(note) => self.voice_mgr.press_note(&mut self.proc, note.note, note.velocity, info),
                 MidiMessageContent::NoteOff(note) => self.voice_mgr.release_note(&mut self.proc, note.note, info),
+                MidiMessageContent::PitchBend(bend) => self.proc.pitch_bend = bend.pitch_bend,
+                MidiMessageContent::ControlChange(cc) => match cc.control {
+                    MOD_WHEEL_CONTROL => self.proc.mod_wheel = cc.value,
+                    _ => {
+                        self.voice_mgr.control_change(&mut self.proc, cc.control, cc.value, info); //Pedals
+                    },
+                },
+                MidiMessageContent::PolyphonicAftertouch(at) => self.voice_mgr.aftertouch(&mut self.proc, at.note, at.aftertouch, info),
+                MidiMessageContent::MonophonicAftertouch(at) => self.proc.aftertouch = at.aftertouch,
+                MidiMessageContent::SysEx(sysex) => {
+                    self.proc.tuning.apply_sysex(&sysex.data);
+                },
+                MidiMessageContent::Clock => {
+                    //Follow the tempo of incoming MIDI clock
+                    self.clock_tempo.tick(info.time);
+                    if let Some(bpm) = self.clock_tempo.bpm() {
+                        self.set_bpm(bpm);
+                    }
+                },
+                MidiMessageContent::Stop => self.clock_tempo.reset(),
                 _ => {},
             }
         }
+        //The global LFO runs once per sample for all voices
+        if self.proc.preset.lfo.mode == LfoMode::Global {
+            self.proc.global_lfo_value = self.proc.global_lfo.process(&self.proc.preset.lfo, self.proc.bpm, self.proc.time_step);
+        }
         //Process voice mgr
-        let sample = self.voice_mgr.process_voices(&mut self.proc, info);
+        let (left, right) = self.voice_mgr.process_voices(&mut self.proc, info);
         //Output
-        self.output.port.take_input_mono(sample);
+        self.output.port.take_input(&[left, right]);
     }
     
     fn audio_input_port(&mut self, _: usize) -> Option<&mut NamedAudioPort> {